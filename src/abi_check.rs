@@ -0,0 +1,159 @@
+//! Checking that the headers found belong to the library found.
+//!
+//! With a library installed twice, e.g. by the distribution and into `/usr/local`, the
+//! compiler can take the headers of one and the linker the library of the other. Both build
+//! fine together and then crash at runtime where structures or constants changed between
+//! the versions. The version the header declares tells them apart.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+
+use crate::link;
+use crate::{io_error, Error, ErrorKind, TargetInfo, Version};
+
+/// A header declaring the library's version, to compare with what pkg-config reports.
+///
+/// ```toml
+/// [package.metadata.buildkit.pkg-config]
+/// name = "zlib"
+/// abi-check = { header = "zlib.h", macro = "ZLIB_VERSION", so-version = true }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct AbiCheck {
+    /// The header, relative to an include directory.
+    header: Utf8PathBuf,
+    /// The macro defined to the version, like `"1.3.1"`.
+    #[serde(rename = "macro")]
+    macro_name: String,
+    /// Whether to compare with the version in the shared library's file name too, for
+    /// libraries whose `.so.x.y.z` follows the release version.
+    #[serde(default)]
+    so_version: bool,
+}
+
+impl AbiCheck {
+    /// Compares the version in the header found in `include_paths` with the library's
+    /// `version` and, if enabled, the versions of its shared libraries `libs` in
+    /// `link_paths` or the system's library directories.
+    ///
+    /// Versions match if they agree in the components both have, so a header declaring
+    /// `1.3` matches a library `1.3.1`.
+    pub(crate) fn verify(
+        &self,
+        target: &TargetInfo,
+        library: &str,
+        include_paths: &[Utf8PathBuf],
+        version: Option<&Version>,
+        libs: &[String],
+        link_paths: &[Utf8PathBuf],
+    ) -> Result<(), Error> {
+        let header = match self.find_header(include_paths) {
+            Some(header) => header,
+            None => {
+                warning!(
+                    "couldn't find `{}` to check {library}'s headers against it",
+                    self.header
+                );
+                return Ok(());
+            }
+        };
+        let header_version = self.header_version(&header)?;
+        let mismatch = |found: String, found_in: String| ErrorKind::AbiMismatch {
+            library: library.to_owned(),
+            header: header.clone(),
+            header_version: header_version.to_string(),
+            found,
+            found_in,
+        };
+        if let Some(version) = version {
+            if !same_version(&header_version, version) {
+                return Err(mismatch(version.to_string(), "pkg-config".to_owned()).into());
+            }
+        }
+        if self.so_version {
+            let mut dirs = link_paths.to_vec();
+            dirs.extend(
+                link::system_lib_dirs(target)
+                    .into_iter()
+                    .map(Utf8PathBuf::from),
+            );
+            for (path, version) in shared_library_versions(libs, &dirs) {
+                if !same_version(&header_version, &version) {
+                    return Err(mismatch(version.to_string(), path.into_string()).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the header the C compiler would, the `-I` directories first.
+    fn find_header(&self, include_paths: &[Utf8PathBuf]) -> Option<Utf8PathBuf> {
+        let system = [
+            Utf8Path::new("/usr/local/include"),
+            Utf8Path::new("/usr/include"),
+        ];
+        include_paths
+            .iter()
+            .map(Utf8PathBuf::as_path)
+            .chain(system)
+            .map(|dir| dir.join(&self.header))
+            .find(|path| path.is_file())
+    }
+
+    fn header_version(&self, header: &Utf8Path) -> Result<Version, Error> {
+        let contents = std::fs::read(header).map_err(io_error(header))?;
+        let contents = String::from_utf8_lossy(&contents);
+        let value = contents.lines().find_map(|line| {
+            let rest = line.trim_start().strip_prefix('#')?.trim_start();
+            let mut words = rest.strip_prefix("define")?.split_whitespace();
+            if words.next()? != self.macro_name {
+                return None;
+            }
+            Some(words.next()?.trim_matches('"').to_owned())
+        });
+        let value = value.ok_or_else(|| ErrorKind::MissingVersionMacro {
+            header: header.to_owned(),
+            name: self.macro_name.clone(),
+        })?;
+        Version::parse(&value)
+    }
+}
+
+/// Whether `a` and `b` agree in the components both have.
+fn same_version(a: &Version, b: &Version) -> bool {
+    a.components()
+        .iter()
+        .zip(b.components())
+        .all(|(a, b)| a == b)
+}
+
+/// Gets the versions in the file names of the shared libraries `libs` resolve to in `dirs`,
+/// e.g. `1.3.1` for `libz.so` linking to `libz.so.1.3.1`, or `libz.1.3.1.dylib`.
+fn shared_library_versions(libs: &[String], dirs: &[Utf8PathBuf]) -> Vec<(Utf8PathBuf, Version)> {
+    let mut versions = Vec::new();
+    for lib in libs {
+        let found = dirs.iter().find_map(|dir| {
+            [format!("lib{lib}.so"), format!("lib{lib}.dylib")]
+                .iter()
+                .map(|file| dir.join(file))
+                .find(|path| path.is_file())
+        });
+        let real = match found.and_then(|path| path.canonicalize_utf8().ok()) {
+            Some(real) => real,
+            None => continue,
+        };
+        let name = real.file_name().unwrap_or_default();
+        let version = name
+            .split_once(".so.")
+            .map(|(_, version)| version)
+            .or_else(|| {
+                name.strip_suffix(".dylib")?
+                    .strip_prefix(&format!("lib{lib}."))
+            });
+        if let Some(Ok(version)) = version.map(Version::parse) {
+            versions.push((real, version));
+        }
+    }
+    versions
+}
@@ -0,0 +1,82 @@
+//! Confining probes to the directories an integrator allows.
+//!
+//! In cross and containerized builds, a library of the build machine or the container image
+//! that's found by accident links fine, and the result then fails on the target or differs
+//! from what the integrator vetted. `BUILDKIT_ALLOWED_ROOTS` lists the directories libraries
+//! and headers may come from, e.g. only the sysroot provided, separated like `PATH`. Cargo's
+//! build directories and the crate's own are always allowed, as vendored builds, prebuilt
+//! binaries and other crates' libraries are there.
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::link::LinkDirectives;
+use crate::{env_overrides, env_var, path_var, utf8_path, Error, ErrorKind};
+
+/// Gets the allowed roots from `BUILDKIT_ALLOWED_ROOTS`, if it's set.
+fn roots() -> Result<Option<Vec<Utf8PathBuf>>, Error> {
+    let value = match env_overrides::var("BUILDKIT_ALLOWED_ROOTS")? {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let mut roots = Vec::new();
+    for root in std::env::split_paths(&value) {
+        let root = utf8_path(root, "A root in `BUILDKIT_ALLOWED_ROOTS`")?;
+        if root.is_relative() {
+            return Err(env_overrides::invalid(
+                "BUILDKIT_ALLOWED_ROOTS",
+                format!("`{root}` is not an absolute path"),
+            ));
+        }
+        roots.push(root);
+    }
+    // `OUT_DIR` is `<profile dir>/build/<package>-<hash>/out`.
+    let out_dir = path_var("OUT_DIR")?;
+    roots.push(out_dir.ancestors().nth(2).unwrap_or(&out_dir).to_owned());
+    roots.push(env_var("CARGO_MANIFEST_DIR")?.into());
+    if let Some(build_dir) = env_overrides::path("BUILDKIT_BUILD_DIR")? {
+        roots.push(build_dir);
+    }
+    Ok(Some(roots))
+}
+
+/// Whether `BUILDKIT_ALLOWED_ROOTS` is set.
+pub(crate) fn enabled() -> Result<bool, Error> {
+    Ok(roots()?.is_some())
+}
+
+/// Fails if `library` was found at `path`, outside the allowed roots.
+pub(crate) fn check(library: &str, path: &Utf8Path) -> Result<(), Error> {
+    let roots = match roots()? {
+        Some(roots) => roots,
+        None => return Ok(()),
+    };
+    if roots.iter().any(|root| path.starts_with(root)) {
+        return Ok(());
+    }
+    Err(ErrorKind::OutsideAllowedRoots {
+        library: library.to_owned(),
+        path: path.to_owned(),
+    }
+    .into())
+}
+
+/// Fails if `link` searches or links files outside the allowed roots.
+pub(crate) fn check_link(library: &str, link: &LinkDirectives) -> Result<(), Error> {
+    let search_dirs = link
+        .search_paths
+        .iter()
+        .map(|path| match path.split_once('=') {
+            Some((_, dir)) => dir,
+            None => path.as_str(),
+        });
+    // Libraries given by path, like `/usr/lib/libfoo.a`, are linked through the arguments.
+    let files = link
+        .args
+        .iter()
+        .map(|arg| arg.strip_prefix("-l:").unwrap_or(arg))
+        .filter(|arg| Utf8Path::new(arg).is_absolute());
+    for path in search_dirs.chain(files) {
+        check(library, Utf8Path::new(path))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+//! Building with or without the hand-written x86 assembly of a vendored library.
+//!
+//! Libraries like OpenSSL, aws-lc, libjpeg-turbo and x264 assemble their optimized x86 code
+//! with nasm or yasm, which is rarely installed on Windows and not always elsewhere. Rather
+//! than failing, or every `-sys` crate checking by hand, the build falls back to the portable C
+//! code with the library's "no asm" options, and tells the crate which path it took.
+
+use std::ffi::OsStr;
+
+use serde::Deserialize;
+
+use crate::features::BuildOptions;
+use crate::{tools, Error, TargetInfo};
+
+/// The assembler a vendored library's x86 code needs, and the options without it.
+///
+/// ```toml
+/// [package.metadata.buildkit.assembler]
+/// target-os = ["windows"]
+/// no-asm = { configure = ["no-asm"], cmake = { OPENSSL_NO_ASM = "ON" } }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct Assembler {
+    /// The assemblers that work, in order of preference.
+    #[serde(default = "default_assemblers")]
+    assemblers: Vec<String>,
+    /// The target OSes whose builds need one, or all if empty.
+    #[serde(default)]
+    target_os: Vec<String>,
+    /// Options applied when an assembler is found.
+    #[serde(flatten)]
+    asm: BuildOptions,
+    /// Options applied when none is.
+    #[serde(default)]
+    no_asm: BuildOptions,
+}
+
+fn default_assemblers() -> Vec<String> {
+    vec!["nasm".to_owned(), "yasm".to_owned()]
+}
+
+impl Assembler {
+    /// Looks for the assemblers on `path` and returns the options for the build,
+    /// setting `cfg(buildkit_asm)` to the one found, or `"none"`.
+    ///
+    /// Targets other than x86 and x86-64, or not in `target-os`, build their assembly with
+    /// the C compiler and get the `asm` options without an assembler or the cfg.
+    pub(crate) fn resolve(
+        &self,
+        target: &TargetInfo,
+        path: &OsStr,
+    ) -> Result<&BuildOptions, Error> {
+        let mut values: Vec<String> = self
+            .assemblers
+            .iter()
+            .map(|name| format!("{name:?}"))
+            .collect();
+        values.push("\"none\"".to_owned());
+        println!(
+            "cargo:rustc-check-cfg=cfg(buildkit_asm, values({}))",
+            values.join(", ")
+        );
+
+        let os = target.os().to_owned();
+        if !matches!(target.arch(), "x86" | "x86_64")
+            || !(self.target_os.is_empty() || self.target_os.contains(&os))
+        {
+            return Ok(&self.asm);
+        }
+        match self
+            .assemblers
+            .iter()
+            .find(|name| tools::find_on(name, path).is_some())
+        {
+            Some(name) => {
+                println!("cargo:rustc-cfg=buildkit_asm={name:?}");
+                Ok(&self.asm)
+            }
+            None => {
+                println!("cargo:rustc-cfg=buildkit_asm=\"none\"");
+                warning!(
+                    "building without assembly optimizations, \
+                     as none of {} is on PATH",
+                    self.assemblers.join(", ")
+                );
+                Ok(&self.no_asm)
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Custom ways to resolve system libraries.
+
+use crate::{BuildOutcome, Error, LinkKind};
+
+/// Resolves a system library in place of pkg-config and vcpkg,
+/// e.g. from a corporate package manager or a Bazel-backed artifact store.
+///
+/// Register it with [`BuildKit::backend`](crate::BuildKit::backend) and select it with
+/// `package.metadata.buildkit.backend`. Mode selection, `optional` and the link helpers
+/// like `cxx` still apply; the backend only replaces the probe itself.
+///
+/// ```no_run
+/// use buildkit::{BuildKit, BuildOutcome, Error, ProbeBackend, ProbeRequest};
+///
+/// struct Artifactory;
+///
+/// impl ProbeBackend for Artifactory {
+///     fn name(&self) -> &str {
+///         "artifactory"
+///     }
+///
+///     fn probe(&self, request: &ProbeRequest<'_>) -> Result<BuildOutcome, Error> {
+///         // Fetch the library, then emit `cargo:rustc-link-*` for it.
+///         Ok(BuildOutcome::found(None))
+///     }
+/// }
+///
+/// BuildKit::from_metadata()?
+///     .backend(Artifactory)
+///     .build(|_| Ok(()))?;
+/// # Ok::<(), Error>(())
+/// ```
+pub trait ProbeBackend {
+    /// Gets the name `package.metadata.buildkit.backend` selects the backend by.
+    fn name(&self) -> &str;
+
+    /// Finds the library and emits the cargo directives to link it.
+    fn probe(&self, request: &ProbeRequest<'_>) -> Result<BuildOutcome, Error>;
+}
+
+/// What a [`ProbeBackend`] is asked to resolve.
+#[derive(Debug)]
+pub struct ProbeRequest<'a> {
+    pub(crate) package: &'a str,
+    pub(crate) link_kind: Option<LinkKind>,
+    pub(crate) config: Option<&'a serde_json::Value>,
+}
+
+impl ProbeRequest<'_> {
+    /// Gets the name of the package being built.
+    pub fn package(&self) -> &str {
+        self.package
+    }
+
+    /// Gets the linkage selected for the build, if any.
+    pub fn link_kind(&self) -> Option<LinkKind> {
+        self.link_kind
+    }
+
+    /// Gets the backend's own settings from `package.metadata.buildkit.backend-config`.
+    pub fn config(&self) -> Option<&serde_json::Value> {
+        self.config
+    }
+}
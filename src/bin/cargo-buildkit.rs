@@ -0,0 +1,108 @@
+//! `cargo buildkit`: prints the native requirements of a workspace for packaging tools,
+//! and reports conflicting resolutions of native libraries.
+
+use std::process::Command;
+
+use buildkit::{Lockfile, Requirements, Resolutions, RuntimeEnv, Timings};
+
+const USAGE: &str = "\
+Usage: cargo buildkit <COMMAND> [--manifest-path <PATH>] [-- <CARGO BUILD ARGS>...]
+
+Commands:
+  nix          Print the system libraries and sources as JSON for Nix tooling
+  debian       Print a Build-Depends field for the system libraries
+  rpm          Print BuildRequires lines for the system libraries
+  conflicts    Report native libraries that crates of the last builds resolved differently
+  runtime-env  Print the library search paths the last builds' programs need as JSON
+  timings      Print the time the last builds' build scripts spent per phase as JSON
+  lock         Build, hashing every source, and print a buildkit.lock of them";
+
+fn main() {
+    // Run as `cargo buildkit`, cargo passes the subcommand name first.
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("buildkit") {
+        args.next();
+    }
+
+    let mut command = None;
+    let mut manifest_path = String::from("Cargo.toml");
+    let mut build_args = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--manifest-path" => match args.next() {
+                Some(path) => manifest_path = path,
+                None => fail("`--manifest-path` needs a value"),
+            },
+            "--" => build_args.extend(args.by_ref()),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ if command.is_none() => command = Some(arg),
+            _ => fail(&format!("unexpected argument `{arg}`")),
+        }
+    }
+
+    let command = command.unwrap_or_else(|| fail("no command given"));
+    match command.as_str() {
+        "conflicts" => {
+            let resolutions = match Resolutions::from_manifest(&manifest_path) {
+                Ok(resolutions) => resolutions,
+                Err(err) => fail(&err.to_string()),
+            };
+            let conflicts = resolutions.conflicts();
+            if !conflicts.is_empty() {
+                eprint!("{conflicts}");
+                std::process::exit(1);
+            }
+        }
+        "runtime-env" => match RuntimeEnv::from_manifest(&manifest_path) {
+            Ok(runtime_env) => println!("{}", runtime_env.to_json()),
+            Err(err) => fail(&err.to_string()),
+        },
+        "timings" => match Timings::from_manifest(&manifest_path) {
+            Ok(timings) => println!("{}", timings.to_json()),
+            Err(err) => fail(&err.to_string()),
+        },
+        "lock" => {
+            build_recording_digests(&manifest_path, &build_args);
+            match Lockfile::from_manifest(&manifest_path) {
+                Ok(lockfile) => print!("{}", lockfile.to_toml()),
+                Err(err) => fail(&err.to_string()),
+            }
+        }
+        "nix" => println!("{}", requirements(&manifest_path).to_nix_json()),
+        "debian" => print!("{}", requirements(&manifest_path).to_debian_build_depends()),
+        "rpm" => print!("{}", requirements(&manifest_path).to_rpm_build_requires()),
+        _ => fail(&format!("unknown command `{command}`")),
+    }
+}
+
+/// Collects the system library requirements of the workspace at `manifest_path`.
+fn requirements(manifest_path: &str) -> Requirements {
+    match Requirements::from_manifest(manifest_path) {
+        Ok(requirements) => requirements,
+        Err(err) => fail(&err.to_string()),
+    }
+}
+
+/// Builds the workspace with `BUILDKIT_RECORD_DIGESTS=1`, which reruns the build scripts
+/// fetching sources so that they hash them.
+fn build_recording_digests(manifest_path: &str, build_args: &[String]) {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let status = Command::new(cargo)
+        .args(["build", "--manifest-path", manifest_path])
+        .args(build_args)
+        .env("BUILDKIT_RECORD_DIGESTS", "1")
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => fail(&format!("`cargo build` failed with {status}")),
+        Err(err) => fail(&format!("couldn't run `cargo build`: {err}")),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {message}\n\n{USAGE}");
+    std::process::exit(1)
+}
@@ -0,0 +1,178 @@
+//! Selecting and linking a BLAS/LAPACK provider.
+//!
+//! Like the `blas-src` and `lapack-src` crates, the provider is picked with a cargo feature
+//! named after it, so the final binary decides which one the numeric crates share.
+
+use serde::Deserialize;
+
+use crate::fortran::FortranRuntime;
+use crate::link::LinkDirectives;
+use crate::linkage::feature_enabled;
+use crate::{Error, ErrorKind, LinkKind, TargetInfo};
+
+/// An implementation of the BLAS (and LAPACK) interface.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum BlasProvider {
+    Openblas,
+    IntelMkl,
+    Accelerate,
+    Netlib,
+}
+
+impl BlasProvider {
+    const ALL: [BlasProvider; 4] = [
+        BlasProvider::Openblas,
+        BlasProvider::IntelMkl,
+        BlasProvider::Accelerate,
+        BlasProvider::Netlib,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            BlasProvider::Openblas => "openblas",
+            BlasProvider::IntelMkl => "intel-mkl",
+            BlasProvider::Accelerate => "accelerate",
+            BlasProvider::Netlib => "netlib",
+        }
+    }
+}
+
+/// BLAS requirements from `package.metadata.buildkit.blas`.
+///
+/// ```toml
+/// [package.metadata.buildkit.blas]
+/// default = "openblas"
+/// lapack = true
+/// ```
+///
+/// An enabled `openblas`, `intel-mkl`, `accelerate` or `netlib` feature takes precedence
+/// over `default`, which falls back to Accelerate on Apple targets and OpenBLAS elsewhere.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct Blas {
+    default: Option<BlasProvider>,
+    /// Whether LAPACK is needed too.
+    #[serde(default)]
+    lapack: bool,
+}
+
+impl Blas {
+    /// Links the selected provider, emitting `cargo:blas-provider` for dependents.
+    pub(crate) fn link(
+        &self,
+        target: &TargetInfo,
+        link_kind: Option<LinkKind>,
+        link: &mut LinkDirectives,
+    ) -> Result<(), Error> {
+        let apple = target.is_apple();
+        let provider = BlasProvider::ALL
+            .iter()
+            .copied()
+            .find(|provider| feature_enabled(provider.name()))
+            .or(self.default)
+            .unwrap_or(if apple {
+                BlasProvider::Accelerate
+            } else {
+                BlasProvider::Openblas
+            });
+        let statik = link_kind == Some(LinkKind::Static);
+        println!("cargo:blas-provider={}", provider.name());
+
+        match provider {
+            // OpenBLAS bundles LAPACK, which is written in Fortran.
+            BlasProvider::Openblas => {
+                probe_or_link(target, "openblas", &["openblas"], link_kind, link)?;
+                if statik {
+                    FortranRuntime::Gfortran.link(link)?;
+                }
+            }
+            BlasProvider::IntelMkl => {
+                if let Ok(root) = std::env::var("MKLROOT") {
+                    link.search_paths.push(format!("native={root}/lib/intel64"));
+                    link.search_paths.push(format!("native={root}/lib"));
+                }
+                println!("cargo:rerun-if-env-changed=MKLROOT");
+                let module = if statik {
+                    "mkl-static-lp64-seq"
+                } else {
+                    "mkl-dynamic-lp64-seq"
+                };
+                probe_or_link(
+                    target,
+                    module,
+                    &["mkl_intel_lp64", "mkl_sequential", "mkl_core"],
+                    link_kind,
+                    link,
+                )?;
+            }
+            BlasProvider::Accelerate if apple => {
+                link.libs.push("framework=Accelerate".to_owned());
+            }
+            BlasProvider::Accelerate => {
+                return Err(ErrorKind::UnsupportedBlasProvider {
+                    provider: provider.name(),
+                    target: target.triple().to_owned(),
+                }
+                .into());
+            }
+            BlasProvider::Netlib => {
+                if self.lapack {
+                    probe_or_link(target, "lapack", &["lapack"], link_kind, link)?;
+                }
+                probe_or_link(target, "blas", &["blas"], link_kind, link)?;
+                if statik {
+                    FortranRuntime::Gfortran.link(link)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Links a pkg-config module, or `libs` directly if pkg-config doesn't know it or isn't
+/// installed. Other pkg-config failures, e.g. a broken `.pc` file, are errors.
+fn probe_or_link(
+    target: &TargetInfo,
+    module: &str,
+    libs: &[&str],
+    link_kind: Option<LinkKind>,
+    link: &mut LinkDirectives,
+) -> Result<(), Error> {
+    let mut config = pkg_config::Config::new();
+    config.cargo_metadata(false);
+    if let Some(link_kind) = link_kind {
+        config.statik(link_kind == LinkKind::Static);
+    }
+    match config.probe(module) {
+        Ok(lib) => {
+            let statik = link_kind.map_or_else(
+                || crate::link::pkg_config_static(module),
+                |kind| kind == LinkKind::Static,
+            );
+            link.extend(LinkDirectives::from_pkg_config(&lib, statik, target));
+            return Ok(());
+        }
+        // Both pkg-config and pkgconf say so for a missing module, unlike for a broken one or
+        // a missing dependency of it.
+        Err(pkg_config::Error::ProbeFailure { ref output, .. })
+            if String::from_utf8_lossy(&output.stderr)
+                .contains(&format!("Package {module} was not found")) => {}
+        Err(pkg_config::Error::Command { ref cause, .. })
+            if cause.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(ErrorKind::PkgConfigProbe {
+                library: module.to_owned(),
+                err: Box::new(err),
+            }
+            .into())
+        }
+    }
+    for lib in libs {
+        match link_kind {
+            Some(link_kind) => link.libs.push(format!("{}={lib}", link_kind.as_str())),
+            None => link.libs.push(lib.to_string()),
+        }
+    }
+    Ok(())
+}
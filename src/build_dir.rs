@@ -0,0 +1,216 @@
+//! Keeping the vendored build out of paths build tools can't handle.
+//!
+//! make, libtool and many configure scripts split paths at spaces and mangle quotes, `$` or
+//! `#`, and MSVC tools can fail on non-ASCII paths, all of which show up in `OUT_DIR` when the
+//! project or the user's home directory has them, as Windows user names often do. With
+//! `BUILDKIT_BUILD_DIR` set, such builds work in a directory named by the hash of `OUT_DIR`
+//! in there instead, with their sources staged there too.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use sha2::{Digest, Sha256};
+
+use crate::fetch;
+use crate::linkage::Linkage;
+use crate::{env_overrides, env_var, hermetic, io_error, path_var, utf8_vars, Error, ErrorKind};
+
+/// The file a vendored build building both kinds of libraries leaves in its work directory
+/// once it's done, so builds selecting the other kind reuse it.
+pub(crate) const BUILT_STAMP: &str = ".buildkit-built";
+
+/// Gets the directory the vendored build works and caches its sources in: `OUT_DIR`, or if
+/// that has characters build tools can't handle, one in `BUILDKIT_BUILD_DIR` if it's set.
+pub(crate) fn work_dir() -> Result<Utf8PathBuf, Error> {
+    let out_dir = path_var("OUT_DIR")?;
+    if unsafe_component(&out_dir).is_none() {
+        return Ok(out_dir);
+    }
+    match relocation_root()? {
+        Some(root) => {
+            let key = &hex::encode(Sha256::digest(out_dir.as_str()))[..16];
+            let dir = root.join(key);
+            hermetic::check_write("build in", &dir)?;
+            Ok(dir)
+        }
+        None => Ok(out_dir),
+    }
+}
+
+/// Gets the work directory of a vendored build building both static and shared libraries.
+///
+/// Cargo gives each feature set its own `OUT_DIR`, so it's next to those instead, under a
+/// hash of what the build depends on other than the features selecting the linkage: the
+/// package, its metadata, the source tree at `source`, target, compiler flags, tool
+/// environment and the other features. A bumped or edited source is built anew.
+pub(crate) fn shared_work_dir(
+    linkage: &Linkage,
+    metadata_digest: &str,
+    source: &Utf8Path,
+    cflags: &[String],
+    env: &[(String, String)],
+) -> Result<Utf8PathBuf, Error> {
+    let mut hasher = Sha256::new();
+    hasher.update(metadata_digest);
+    hasher.update([0]);
+    fetch::fingerprint(source, source, &mut hasher)?;
+    for var in [
+        "CARGO_PKG_NAME",
+        "CARGO_PKG_VERSION",
+        "CARGO_MANIFEST_DIR",
+        "TARGET",
+    ] {
+        hasher.update(env_var(var)?);
+        hasher.update([0]);
+    }
+    for flag in cflags {
+        hasher.update(flag);
+        hasher.update([0]);
+    }
+    for (var, value) in env {
+        hasher.update(format!("{var}={value}"));
+        hasher.update([0]);
+    }
+    let mut features: Vec<String> = utf8_vars()
+        .map(|(var, _)| var)
+        .filter(|var| var.starts_with("CARGO_FEATURE_") && !linkage.is_feature_var(var))
+        .collect();
+    features.sort();
+    for feature in features {
+        hasher.update(feature);
+        hasher.update([0]);
+    }
+    let key = format!(
+        "buildkit-{}-{}",
+        env_var("CARGO_PKG_NAME")?,
+        &hex::encode(hasher.finalize())[..16]
+    );
+
+    // `OUT_DIR` is `<profile dir>/build/<package>-<hash>/out`.
+    let out_dir = path_var("OUT_DIR")?;
+    let build_dir = out_dir.ancestors().nth(2).unwrap_or(&out_dir);
+    if unsafe_component(build_dir).is_some() {
+        if let Some(root) = relocation_root()? {
+            return Ok(root.join(key));
+        }
+    }
+    Ok(build_dir.join(key))
+}
+
+/// An exclusive lock on a shared work directory, released when dropped.
+#[derive(Debug)]
+pub(crate) struct WorkDirLock {
+    _file: File,
+}
+
+/// Locks the shared work directory `dir`, waiting for the build of another feature set
+/// that holds it, so that only one of them builds into it and the other reuses that.
+pub(crate) fn lock(dir: &Utf8Path) -> Result<WorkDirLock, Error> {
+    std::fs::create_dir_all(dir).map_err(io_error(dir))?;
+    let path = dir.join(".buildkit-lock");
+    lock_file(&path).map(|file| WorkDirLock { _file: file })
+}
+
+#[cfg(unix)]
+fn lock_file(path: &Utf8Path) -> Result<File, Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .map_err(io_error(path))?;
+    loop {
+        // SAFETY: the descriptor stays open for as long as `file`, which closing releases
+        // the lock with.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(file);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(io_error(path)(err).into());
+        }
+    }
+}
+
+#[cfg(windows)]
+fn lock_file(path: &Utf8Path) -> Result<File, Error> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    // `ERROR_SHARING_VIOLATION`, while another build has the file open.
+    const SHARING_VIOLATION: i32 = 32;
+    loop {
+        match OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .share_mode(0)
+            .open(path)
+        {
+            Ok(file) => return Ok(file),
+            Err(err) if err.raw_os_error() == Some(SHARING_VIOLATION) => {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            Err(err) => return Err(io_error(path)(err).into()),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn lock_file(path: &Utf8Path) -> Result<File, Error> {
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .map_err(|err| io_error(path)(err).into())
+}
+
+/// Whether a source at `path` has to be staged into the work directory to be built.
+pub(crate) fn relocates(path: &Utf8Path) -> Result<bool, Error> {
+    Ok(unsafe_component(path).is_some() && relocation_root()?.is_some())
+}
+
+/// Checks that build tools can handle `paths`, naming the first component they can't.
+pub(crate) fn check(paths: &[&Utf8Path]) -> Result<(), Error> {
+    for path in paths {
+        if let Some(component) = unsafe_component(path) {
+            return Err(ErrorKind::UnsafeBuildPath {
+                path: path.to_path_buf(),
+                component: component.to_owned(),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Gets `BUILDKIT_BUILD_DIR`, checking that it's safe itself.
+fn relocation_root() -> Result<Option<Utf8PathBuf>, Error> {
+    let root = match env_overrides::path("BUILDKIT_BUILD_DIR")? {
+        Some(root) => root,
+        None => return Ok(None),
+    };
+    if let Some(component) = unsafe_component(&root) {
+        return Err(env_overrides::invalid(
+            "BUILDKIT_BUILD_DIR",
+            format!("`{component}` has characters build tools can't handle"),
+        ));
+    }
+    Ok(Some(root))
+}
+
+/// Gets the first component of `path` with whitespace, non-ASCII characters
+/// or characters shells and make treat specially.
+fn unsafe_component(path: &Utf8Path) -> Option<&str> {
+    path.components()
+        .filter(|c| !matches!(c, Utf8Component::Prefix(_)))
+        .map(|c| c.as_str())
+        .find(|component| {
+            component
+                .chars()
+                .any(|c| c.is_whitespace() || !c.is_ascii() || "\"'`$#;&|()<>*?%!".contains(c))
+        })
+}
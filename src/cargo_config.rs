@@ -0,0 +1,171 @@
+//! Reading cargo's configuration files.
+//!
+//! Cargo passes little of `.cargo/config.toml` on to build scripts, so buildkit reads
+//! the files itself. Cargo looks for them upwards from where it was invoked, which build
+//! scripts can't see; the crate's directory and the target directory stand in for it.
+//! Like in cargo, the closest file wins and `CARGO_*` variables override all files.
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{io_error, Error, ErrorKind};
+
+/// The `.cargo/config.toml` files that apply to the build, closest first.
+pub(crate) struct CargoConfig {
+    files: Vec<(Utf8PathBuf, toml::Table)>,
+}
+
+impl CargoConfig {
+    pub(crate) fn load() -> Result<CargoConfig, Error> {
+        let mut dirs: Vec<Utf8PathBuf> = Vec::new();
+        for var in ["CARGO_MANIFEST_DIR", "OUT_DIR"] {
+            if let Ok(start) = std::env::var(var) {
+                for dir in Utf8Path::new(&start).ancestors() {
+                    let dir = dir.join(".cargo");
+                    if !dirs.contains(&dir) {
+                        dirs.push(dir);
+                    }
+                }
+            }
+        }
+        if let Some(home) = cargo_home() {
+            if !dirs.contains(&home) {
+                dirs.push(home);
+            }
+        }
+
+        let mut files = Vec::new();
+        for dir in dirs {
+            // Cargo uses the legacy `config` if both exist.
+            let path = match ["config", "config.toml"]
+                .iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file())
+            {
+                Some(path) => path,
+                None => continue,
+            };
+            let contents = std::fs::read_to_string(&path).map_err(io_error(&path))?;
+            let table =
+                contents
+                    .parse()
+                    .map_err(|err: toml::de::Error| ErrorKind::InvalidCargoConfig {
+                        origin: format!("`{path}`"),
+                        reason: err.message().to_owned(),
+                    })?;
+            files.push((path, table));
+        }
+        Ok(CargoConfig { files })
+    }
+
+    /// Gets the string at the dotted `key`, e.g. `http.proxy`.
+    pub(crate) fn string(&self, key: &str) -> Result<Option<String>, Error> {
+        if let Some(value) = env_override(key) {
+            return Ok(Some(value));
+        }
+        self.typed(key, "a string", |value| value.as_str().map(str::to_owned))
+    }
+
+    /// Gets the path at `key`, resolving relative paths against the directory
+    /// containing the `.cargo` directory they were set in.
+    pub(crate) fn path(&self, key: &str) -> Result<Option<Utf8PathBuf>, Error> {
+        if let Some(value) = env_override(key) {
+            return Ok(Some(value.into()));
+        }
+        let (value, file) = match self.value(key) {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let path = Utf8Path::new(value.as_str().ok_or_else(|| invalid(key, file, "a path"))?);
+        let base = file.parent().and_then(Utf8Path::parent);
+        Ok(Some(match base {
+            Some(base) if path.is_relative() => base.join(path),
+            _ => path.to_owned(),
+        }))
+    }
+
+    pub(crate) fn integer(&self, key: &str) -> Result<Option<u64>, Error> {
+        if let Some(value) = env_override(key) {
+            return value.parse().map(Some).map_err(|_| {
+                ErrorKind::InvalidCargoConfig {
+                    origin: format!("`{}`", env_name(key)),
+                    reason: format!("`{value}` is not a non-negative integer"),
+                }
+                .into()
+            });
+        }
+        self.typed(key, "a non-negative integer", |value| {
+            value.as_integer().and_then(|n| u64::try_from(n).ok())
+        })
+    }
+
+    pub(crate) fn bool(&self, key: &str) -> Result<Option<bool>, Error> {
+        if let Some(value) = env_override(key) {
+            return match value.as_str() {
+                "true" => Ok(Some(true)),
+                "false" => Ok(Some(false)),
+                _ => Err(ErrorKind::InvalidCargoConfig {
+                    origin: format!("`{}`", env_name(key)),
+                    reason: format!("`{value}` is not `true` or `false`"),
+                }
+                .into()),
+            };
+        }
+        self.typed(key, "a boolean", toml::Value::as_bool)
+    }
+
+    /// Gets the raw value at `key` and the file that set it, ignoring the environment.
+    pub(crate) fn value(&self, key: &str) -> Option<(&toml::Value, &Utf8Path)> {
+        self.files.iter().find_map(|(path, table)| {
+            let mut parts = key.split('.');
+            let mut value = table.get(parts.next()?)?;
+            for part in parts {
+                value = value.get(part)?;
+            }
+            Some((value, path.as_path()))
+        })
+    }
+
+    fn typed<T>(
+        &self,
+        key: &str,
+        expected: &str,
+        convert: impl FnOnce(&toml::Value) -> Option<T>,
+    ) -> Result<Option<T>, Error> {
+        match self.value(key) {
+            Some((value, file)) => convert(value)
+                .map(Some)
+                .ok_or_else(|| invalid(key, file, expected)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Reports that `key` in `file` isn't `expected`.
+pub(crate) fn invalid(key: &str, file: &Utf8Path, expected: &str) -> Error {
+    ErrorKind::InvalidCargoConfig {
+        origin: format!("`{key}` in `{file}`"),
+        reason: format!("expected {expected}"),
+    }
+    .into()
+}
+
+/// Gets the variable overriding `key`, e.g. `CARGO_HTTP_PROXY` for `http.proxy`.
+fn env_override(key: &str) -> Option<String> {
+    let var = env_name(key);
+    println!("cargo:rerun-if-env-changed={var}");
+    std::env::var(var).ok()
+}
+
+fn env_name(key: &str) -> String {
+    format!("CARGO_{}", key.to_uppercase().replace(['.', '-'], "_"))
+}
+
+fn cargo_home() -> Option<Utf8PathBuf> {
+    if let Ok(home) = std::env::var("CARGO_HOME") {
+        return Some(home.into());
+    }
+    ["HOME", "USERPROFILE"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok())
+        .map(|home| Utf8Path::new(&home).join(".cargo"))
+}
@@ -0,0 +1,94 @@
+//! Default compiler flags for vendored builds.
+
+use crate::{env_var, Error, TargetInfo};
+
+/// The command-line syntax of the target's C compiler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flavor {
+    /// GCC, Clang and anything else accepting their flags.
+    Gnu,
+    /// `cl.exe` and `clang-cl`.
+    Msvc,
+}
+
+impl Flavor {
+    pub(crate) fn detect(target: &TargetInfo) -> Flavor {
+        if target.env() == "msvc" {
+            Flavor::Msvc
+        } else {
+            Flavor::Gnu
+        }
+    }
+}
+
+/// Whether the C runtime is linked statically, with `-C target-feature=+crt-static`.
+pub(crate) fn crt_static(target: &TargetInfo) -> bool {
+    target.has_feature("crt-static")
+}
+
+/// Maps the cargo profile to C/C++ compiler flags:
+/// `OPT_LEVEL` to `-O`, `DEBUG` to `-g`, disabled debug assertions to `-DNDEBUG`,
+/// and `panic = "unwind"` to unwind tables so panics can cross C frames.
+/// For MSVC, the CRT is selected with `/MT` or `/MD` to match `crt-static`, as mixing them
+/// fails the final link with LNK2038.
+pub(crate) fn profile_flags(flavor: Flavor, target: &TargetInfo) -> Result<Vec<String>, Error> {
+    let opt_level = env_var("OPT_LEVEL")?;
+    let debug = !matches!(env_var("DEBUG")?.as_str(), "false" | "0" | "none");
+    let debug_assertions = target.cfg("debug_assertions").is_some();
+    let unwind = target
+        .cfg("panic")
+        .map_or(false, |panic| panic.iter().any(|panic| panic == "unwind"));
+
+    let mut flags = Vec::new();
+    match flavor {
+        Flavor::Gnu => {
+            flags.push(match opt_level.as_str() {
+                // `-Oz` is too new for many GCCs.
+                "s" | "z" => "-Os".to_owned(),
+                level => format!("-O{level}"),
+            });
+            if debug {
+                flags.push("-g".to_owned());
+            }
+            if target.os() != "windows" {
+                flags.push("-fPIC".to_owned());
+            }
+            if unwind {
+                flags.push("-funwind-tables".to_owned());
+            }
+        }
+        Flavor::Msvc => {
+            flags.push(
+                match opt_level.as_str() {
+                    "0" => "/Od",
+                    "1" | "s" | "z" => "/O1",
+                    _ => "/O2",
+                }
+                .to_owned(),
+            );
+            if debug {
+                flags.push("/Z7".to_owned());
+            }
+            flags.push(if crt_static(target) { "/MT" } else { "/MD" }.to_owned());
+        }
+    }
+    if !debug_assertions {
+        flags.push(match flavor {
+            Flavor::Gnu => "-DNDEBUG".to_owned(),
+            Flavor::Msvc => "/DNDEBUG".to_owned(),
+        });
+    }
+    Ok(flags)
+}
+
+/// The CMake build type closest to the cargo profile.
+pub(crate) fn cmake_build_type() -> Result<&'static str, Error> {
+    let opt_level = env_var("OPT_LEVEL")?;
+    let debug = !matches!(env_var("DEBUG")?.as_str(), "false" | "0" | "none");
+    Ok(match (opt_level.as_str(), debug) {
+        ("0", _) => "Debug",
+        ("s" | "z", _) => "MinSizeRel",
+        (_, true) => "RelWithDebInfo",
+        (_, false) => "Release",
+    })
+}
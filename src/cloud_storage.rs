@@ -0,0 +1,40 @@
+//! Fetching sources from S3 and Google Cloud Storage.
+//!
+//! Downloads go through the `aws` and `gcloud` CLIs, so they use the same ambient
+//! credentials as everything else on the machine: environment variables, profiles,
+//! instance metadata and so on. `AWS_ENDPOINT_URL` points `s3://` at S3-compatible stores.
+
+use std::process::Command;
+
+use camino::Utf8Path;
+
+use crate::{run_command, Error};
+
+/// Downloads an `s3://` or `gs://` object to `dest`.
+pub(crate) fn download(url: &str, dest: &Utf8Path) -> Result<(), Error> {
+    if url.starts_with("s3://") {
+        run_command(
+            Command::new("aws")
+                .args(["s3", "cp", "--only-show-errors", url])
+                .arg(dest),
+        )?;
+        return Ok(());
+    }
+    // Older Cloud SDKs only have `gsutil`.
+    let gcloud = run_command(
+        Command::new("gcloud")
+            .args(["storage", "cp", "--no-user-output-enabled", url])
+            .arg(dest),
+    );
+    match gcloud {
+        Err(_) if which_gsutil() => {
+            run_command(Command::new("gsutil").args(["-q", "cp", url]).arg(dest))?;
+            Ok(())
+        }
+        result => result.map(drop),
+    }
+}
+
+fn which_gsutil() -> bool {
+    Command::new("gsutil").arg("version").output().is_ok()
+}
@@ -0,0 +1,195 @@
+//! Detecting a native library resolved differently by crates in the same build.
+//!
+//! Two crates linking the same library, one a vendored copy and the other the system's, or
+//! each their own vendored copy, commonly end in duplicate symbols or, worse, code compiled
+//! against one version's headers calling the other. Each build passes the resolutions it knows
+//! of on to dependents as `DEP_<links>_RESOLUTIONS`, so a crate that sees a conflict through
+//! its dependencies warns about it, and [`Resolutions`] collects them from a whole build.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use cargo_metadata::MetadataCommand;
+
+use crate::{env_var, io_error, utf8_vars, Error, ErrorKind};
+
+/// How a crate resolved a native library.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Resolution {
+    library: String,
+    vendored: bool,
+    package: String,
+}
+
+impl Resolution {
+    /// Parses `<library>:<vendored|system>:<package>`.
+    fn parse(entry: &str) -> Option<Resolution> {
+        let mut parts = entry.splitn(3, ':');
+        let library = parts.next()?.to_owned();
+        let vendored = match parts.next()? {
+            "vendored" => true,
+            "system" => false,
+            _ => return None,
+        };
+        let package = parts.next()?.to_owned();
+        Some(Resolution {
+            library,
+            vendored,
+            package,
+        })
+    }
+
+    fn kind(&self) -> &'static str {
+        if self.vendored {
+            "vendored"
+        } else {
+            "system"
+        }
+    }
+}
+
+/// Reports how this crate resolved its `(library, vendored)`, if it did itself, along with
+/// those of its dependencies, and warns about conflicts among them.
+pub(crate) fn report(own: Option<(&str, bool)>) -> Result<(), Error> {
+    let mut resolutions: BTreeSet<Resolution> = utf8_vars()
+        .filter(|(key, _)| key.starts_with("DEP_") && key.ends_with("_RESOLUTIONS"))
+        .flat_map(|(_, value)| {
+            value
+                .split(';')
+                .filter_map(Resolution::parse)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    if let Some((library, vendored)) = own {
+        resolutions.insert(Resolution {
+            library: library.to_owned(),
+            vendored,
+            package: env_var("CARGO_PKG_NAME")?,
+        });
+    }
+    for conflict in conflicts(&resolutions) {
+        warning!("{}", describe(&conflict));
+    }
+    let exported: Vec<String> = resolutions
+        .iter()
+        .map(|r| format!("{}:{}:{}", r.library, r.kind(), r.package))
+        .collect();
+    println!("cargo:resolutions={}", exported.join(";"));
+    Ok(())
+}
+
+/// Groups the resolutions of libraries that are vendored by one crate and taken from the
+/// system by another, or vendored by several.
+fn conflicts(resolutions: &BTreeSet<Resolution>) -> Vec<Vec<&Resolution>> {
+    let mut by_library: BTreeMap<&str, Vec<&Resolution>> = BTreeMap::new();
+    for resolution in resolutions {
+        by_library
+            .entry(&resolution.library)
+            .or_default()
+            .push(resolution);
+    }
+    by_library
+        .into_values()
+        .filter(|group| {
+            let vendored = group.iter().filter(|r| r.vendored).count();
+            vendored > 1 || (vendored == 1 && group.len() > 1)
+        })
+        .collect()
+}
+
+fn describe(conflict: &[&Resolution]) -> String {
+    let mut message = format!("`{}` is resolved differently by", conflict[0].library);
+    for (i, resolution) in conflict.iter().enumerate() {
+        let separator = if i == 0 { " " } else { ", " };
+        write!(
+            message,
+            "{separator}{} ({})",
+            resolution.package,
+            resolution.kind()
+        )
+        .unwrap();
+    }
+    message.push_str(", which can cause duplicate symbols or ABI mismatches");
+    message
+}
+
+/// How the crates of a build resolved their native libraries,
+/// from the build script outputs cargo keeps in the target directory.
+///
+/// Outputs of earlier builds with other settings are still there, so this is most accurate
+/// after a clean build.
+pub struct Resolutions {
+    resolutions: BTreeSet<Resolution>,
+}
+
+impl Resolutions {
+    /// Collects the resolutions from the target directory of the workspace at `manifest_path`.
+    pub fn from_manifest(manifest_path: impl AsRef<Utf8Path>) -> Result<Resolutions, Error> {
+        let metadata = MetadataCommand::new()
+            .manifest_path(manifest_path.as_ref())
+            .no_deps()
+            .exec()
+            .map_err(ErrorKind::CargoMetadataError)?;
+        Resolutions::from_target_dir(metadata.target_directory)
+    }
+
+    /// Collects the resolutions from the build script outputs under `target_dir`,
+    /// for every profile and target.
+    pub fn from_target_dir(target_dir: impl AsRef<Utf8Path>) -> Result<Resolutions, Error> {
+        let mut resolutions = BTreeSet::new();
+        for output in build_outputs(target_dir.as_ref())? {
+            let contents = std::fs::read_to_string(&output).map_err(io_error(&output))?;
+            for line in contents.lines() {
+                if let Some(value) = line.strip_prefix("cargo:resolutions=") {
+                    resolutions.extend(value.split(';').filter_map(Resolution::parse));
+                }
+            }
+        }
+        Ok(Resolutions { resolutions })
+    }
+
+    /// Describes each conflicting resolution on a line, or returns an empty string if
+    /// there are none.
+    pub fn conflicts(&self) -> String {
+        conflicts(&self.resolutions)
+            .iter()
+            .map(|conflict| format!("{}\n", describe(conflict)))
+            .collect()
+    }
+}
+
+/// Finds the `build/*/output` files of each profile, including those of each target.
+pub(crate) fn build_outputs(target_dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>, Error> {
+    let mut outputs = Vec::new();
+    let mut profile_dirs = subdirs(target_dir)?;
+    // Cross builds have a profile directory per target triple.
+    for dir in subdirs(target_dir)? {
+        profile_dirs.extend(subdirs(&dir)?);
+    }
+    for profile_dir in profile_dirs {
+        let build_dir = profile_dir.join("build");
+        if !build_dir.is_dir() {
+            continue;
+        }
+        for dir in subdirs(&build_dir)? {
+            let output = dir.join("output");
+            if output.is_file() {
+                outputs.push(output);
+            }
+        }
+    }
+    Ok(outputs)
+}
+
+fn subdirs(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>, Error> {
+    let mut dirs = Vec::new();
+    for entry in dir.read_dir_utf8().map_err(io_error(dir))? {
+        let path = entry.map_err(io_error(dir))?.into_path();
+        if path.is_dir() {
+            dirs.push(path);
+        }
+    }
+    Ok(dirs)
+}
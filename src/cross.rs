@@ -0,0 +1,145 @@
+//! Carrying cargo's cross-compilation setup over to vendored builds.
+//!
+//! Cross builds are often configured purely through `.cargo/config.toml`
+//! (`target.<triple>.linker` and `rustflags`), which C build systems know nothing about.
+
+use camino::Utf8Path;
+
+use crate::rustflags::RustFlags;
+use crate::{env_var, Error};
+
+/// Derives tool overrides from cargo's target configuration, adding them to `env`
+/// unless the user or buildkit already chose the tool.
+///
+/// Returns the GNU host triple to configure for if this is a cross build.
+pub(crate) fn configure(env: &mut Vec<(String, String)>) -> Result<Option<String>, Error> {
+    let target = env_var("TARGET")?;
+    let linker = target_linker(&target);
+    let prefix = linker.as_deref().and_then(tool_prefix);
+
+    if let Some(linker) = linker.as_deref() {
+        let name = Utf8Path::new(linker).file_name().unwrap_or(linker);
+        let is_driver = name.ends_with("gcc") || name.ends_with("clang") || name.ends_with("cc");
+        if is_driver && !user_sets("CC", &target) && !has(env, "CC") {
+            env.push(("CC".to_owned(), linker.to_owned()));
+            let cxx = if let Some(stem) = linker.strip_suffix("gcc") {
+                format!("{stem}g++")
+            } else if let Some(stem) = linker.strip_suffix("clang") {
+                format!("{stem}clang++")
+            } else {
+                format!("{linker}++")
+            };
+            env.push(("CXX".to_owned(), cxx));
+        }
+        if !user_sets("AR", &target) && !has(env, "AR") {
+            if let Some(prefix) = &prefix {
+                env.push(("AR".to_owned(), format!("{prefix}ar")));
+            } else if name.ends_with("clang") {
+                env.push(("AR".to_owned(), "llvm-ar".to_owned()));
+            }
+        }
+    }
+
+    let search_paths: Vec<String> = RustFlags::from_env()
+        .values("-L")
+        .filter_map(|path| match path.split_once('=') {
+            Some(("native" | "all", path)) => Some(path),
+            Some(_) => None,
+            None => Some(path),
+        })
+        .map(|path| format!("-L{path}"))
+        .collect();
+    if !search_paths.is_empty() {
+        println!("cargo:rerun-if-env-changed=LDFLAGS");
+        let mut ldflags = search_paths.join(" ");
+        if let Ok(user) = std::env::var("LDFLAGS") {
+            ldflags = format!("{user} {ldflags}");
+        }
+        env.push(("LDFLAGS".to_owned(), ldflags));
+    }
+
+    if target == env_var("HOST")? {
+        return Ok(None);
+    }
+    Ok(Some(
+        prefix
+            .map(|prefix| prefix.trim_end_matches('-').to_owned())
+            .unwrap_or(target),
+    ))
+}
+
+/// Finds the target's `tool`, e.g. `OBJCOPY`, the way the drivers would:
+/// from `env`, the user's environment, or else next to the target linker.
+pub(crate) fn target_tool(env: &[(String, String)], tool: &str, default: &str) -> String {
+    if let Some((_, value)) = env.iter().find(|(name, _)| name == tool) {
+        return value.clone();
+    }
+    if let Some(value) = user_setting(tool) {
+        return value;
+    }
+    let target = std::env::var("TARGET").unwrap_or_default();
+    match target_linker(&target).as_deref().and_then(tool_prefix) {
+        Some(prefix) => format!("{prefix}{default}"),
+        None => default.to_owned(),
+    }
+}
+
+/// Gets the user's setting for the target, e.g. `CXXSTDLIB`,
+/// from `<VAR>_<target>`, `TARGET_<VAR>` or `<VAR>` like `cc` does.
+pub(crate) fn user_setting(var: &str) -> Option<String> {
+    let target = std::env::var("TARGET").unwrap_or_default();
+    tool_vars(var, &target).iter().find_map(|var| {
+        println!("cargo:rerun-if-env-changed={var}");
+        std::env::var(var).ok()
+    })
+}
+
+/// Gets the linker cargo uses for the target.
+///
+/// Cargo resolves `target.<triple>.linker` into `RUSTC_LINKER` for build scripts.
+fn target_linker(target: &str) -> Option<String> {
+    let var = format!(
+        "CARGO_TARGET_{}_LINKER",
+        target.to_uppercase().replace(['-', '.'], "_")
+    );
+    ["RUSTC_LINKER", var.as_str()].iter().find_map(|var| {
+        println!("cargo:rerun-if-env-changed={var}");
+        std::env::var(var).ok()
+    })
+}
+
+/// Gets the `<triple>-` prefix of a cross toolchain binary, e.g. `aarch64-linux-gnu-`.
+pub(crate) fn tool_prefix(linker: &str) -> Option<String> {
+    let stem = linker
+        .strip_suffix("gcc")
+        .or_else(|| linker.strip_suffix("clang"))
+        .or_else(|| linker.strip_suffix("cc"))?;
+    if stem.ends_with('-') {
+        Some(stem.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Whether the user configured `tool` for the target through the environment,
+/// the way `cc` looks it up.
+fn user_sets(tool: &str, target: &str) -> bool {
+    tool_vars(tool, target).iter().any(|var| {
+        println!("cargo:rerun-if-env-changed={var}");
+        std::env::var_os(var).is_some()
+    })
+}
+
+/// Gets the variables that configure `tool` for `target`, most specific first.
+fn tool_vars(tool: &str, target: &str) -> [String; 4] {
+    [
+        format!("{tool}_{target}"),
+        format!("{tool}_{}", target.replace('-', "_")),
+        format!("TARGET_{tool}"),
+        tool.to_owned(),
+    ]
+}
+
+fn has(env: &[(String, String)], var: &str) -> bool {
+    env.iter().any(|(name, _)| name == var)
+}
@@ -0,0 +1,46 @@
+//! Building inside the containers of cross-rs.
+//!
+//! `cross` runs cargo in a Docker image per target, with the target's toolchain and, through
+//! Debian multiarch, its libraries and `.pc` files, and sets `CROSS_*` variables describing
+//! them. Without adjusting, probes there fail: the pkg-config crate refuses cross builds,
+//! vcpkg isn't installed, and crates defaulting to vendored builds ignore the libraries the
+//! image's Dockerfile installed for them.
+
+use crate::pc_file::PkgConfigEnv;
+use crate::{env_overrides, tools, Error};
+
+/// Whether the build runs in a cross-rs container.
+pub(crate) fn detected() -> bool {
+    [
+        "CROSS_SYSROOT",
+        "CROSS_TOOLCHAIN_PREFIX",
+        "CROSS_RUNNER",
+        "CROSS_TARGET_RUNNER",
+    ]
+    .iter()
+    .any(|var| {
+        println!("cargo:rerun-if-env-changed={var}");
+        std::env::var_os(var).is_some()
+    })
+}
+
+/// Probes with the image's pkg-config wrapper for the target, if it has one and `PKG_CONFIG`
+/// doesn't pick another, and lets the pkg-config crate use the image's `.pc` files, which
+/// are the target's, through `pkg_env`.
+pub(crate) fn configure_pkg_config(pkg_env: &mut PkgConfigEnv) {
+    if !detected() {
+        return;
+    }
+    if let Ok(prefix) = std::env::var("CROSS_TOOLCHAIN_PREFIX") {
+        if let Some(wrapper) = tools::find(&format!("{prefix}pkg-config")) {
+            pkg_env.set_default("PKG_CONFIG", wrapper);
+        }
+    }
+    pkg_env.set_default("PKG_CONFIG_ALLOW_CROSS", "1");
+}
+
+/// Whether crates may default to their vendored build in a cross-rs container, with
+/// `BUILDKIT_CROSS_VENDOR=1`, rather than probe the image's libraries.
+pub(crate) fn vendoring_allowed() -> Result<bool, Error> {
+    env_overrides::flag("BUILDKIT_CROSS_VENDOR")
+}
@@ -0,0 +1,44 @@
+//! Linking the C++ standard library that C++ libraries depend on.
+
+use std::collections::BTreeMap;
+
+use crate::{cross, link::LinkDirectives, TargetInfo};
+
+/// Links the target's C++ standard library.
+///
+/// `CXXSTDLIB` (also per target, like `cc` reads it) takes precedence over `overrides`,
+/// which map target triples to a library name, or to an empty string for none.
+pub(crate) fn link_stdlib(
+    target: &TargetInfo,
+    overrides: &BTreeMap<String, String>,
+    link: &mut LinkDirectives,
+) {
+    let stdlib = match cross::user_setting("CXXSTDLIB") {
+        Some(stdlib) => stdlib,
+        None => match overrides.get(target.triple()) {
+            Some(stdlib) => stdlib.clone(),
+            None => default_stdlib(target).to_owned(),
+        },
+    };
+    if !stdlib.is_empty() {
+        link.libs.push(stdlib);
+    }
+}
+
+fn default_stdlib(target: &TargetInfo) -> &'static str {
+    if target.env() == "msvc" {
+        // The MSVC runtime is linked through the CRT.
+        return "";
+    }
+    if target.is_apple() {
+        "c++"
+    } else {
+        match target.os() {
+            "freebsd" | "openbsd" | "fuchsia" => "c++",
+            "android" => "c++_shared",
+            // Emscripten links libc++ itself; bare targets have no runtime to link.
+            "emscripten" | "wasi" | "unknown" | "none" => "",
+            _ => "stdc++",
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Debian development packages of common pkg-config modules.
+//!
+//! Crates can name their packages with `system-packages.debian` instead,
+//! which also covers modules missing here.
+
+/// pkg-config modules and the Debian packages providing them, sorted by module.
+const DEV_PACKAGES: &[(&str, &str)] = &[
+    ("alsa", "libasound2-dev"),
+    ("bzip2", "libbz2-dev"),
+    ("cairo", "libcairo2-dev"),
+    ("dbus-1", "libdbus-1-dev"),
+    ("egl", "libegl-dev"),
+    ("expat", "libexpat1-dev"),
+    ("fontconfig", "libfontconfig-dev"),
+    ("freetype2", "libfreetype-dev"),
+    ("gbm", "libgbm-dev"),
+    ("gdk-pixbuf-2.0", "libgdk-pixbuf-2.0-dev"),
+    ("gio-2.0", "libglib2.0-dev"),
+    ("gl", "libgl-dev"),
+    ("glib-2.0", "libglib2.0-dev"),
+    ("gmp", "libgmp-dev"),
+    ("gobject-2.0", "libglib2.0-dev"),
+    ("gstreamer-1.0", "libgstreamer1.0-dev"),
+    ("gtk+-3.0", "libgtk-3-dev"),
+    ("gtk4", "libgtk-4-dev"),
+    ("harfbuzz", "libharfbuzz-dev"),
+    ("hdf5", "libhdf5-dev"),
+    ("icu-uc", "libicu-dev"),
+    ("krb5", "libkrb5-dev"),
+    ("lapack", "liblapack-dev"),
+    ("libarchive", "libarchive-dev"),
+    ("libavcodec", "libavcodec-dev"),
+    ("libavformat", "libavformat-dev"),
+    ("libavutil", "libavutil-dev"),
+    ("libbpf", "libbpf-dev"),
+    ("libbrotlidec", "libbrotli-dev"),
+    ("libcap", "libcap-dev"),
+    ("libcrypto", "libssl-dev"),
+    ("libcurl", "libcurl4-openssl-dev"),
+    ("libdrm", "libdrm-dev"),
+    ("libelf", "libelf-dev"),
+    ("libevent", "libevent-dev"),
+    ("libffi", "libffi-dev"),
+    ("libgit2", "libgit2-dev"),
+    ("libidn2", "libidn2-dev"),
+    ("libinput", "libinput-dev"),
+    ("libjpeg", "libjpeg-dev"),
+    ("liblz4", "liblz4-dev"),
+    ("liblzma", "liblzma-dev"),
+    ("libmagic", "libmagic-dev"),
+    ("libnghttp2", "libnghttp2-dev"),
+    ("libpcap", "libpcap-dev"),
+    ("libpcre2-8", "libpcre2-dev"),
+    ("libpng", "libpng-dev"),
+    ("libpq", "libpq-dev"),
+    ("libpulse", "libpulse-dev"),
+    ("libseccomp", "libseccomp-dev"),
+    ("libsecret-1", "libsecret-1-dev"),
+    ("libsodium", "libsodium-dev"),
+    ("libssh2", "libssh2-1-dev"),
+    ("libssl", "libssl-dev"),
+    ("libswscale", "libswscale-dev"),
+    ("libsystemd", "libsystemd-dev"),
+    ("libtiff-4", "libtiff-dev"),
+    ("libudev", "libudev-dev"),
+    ("libusb-1.0", "libusb-1.0-0-dev"),
+    ("libuv", "libuv1-dev"),
+    ("libwebp", "libwebp-dev"),
+    ("libxml-2.0", "libxml2-dev"),
+    ("libzmq", "libzmq3-dev"),
+    ("libzstd", "libzstd-dev"),
+    ("mpfr", "libmpfr-dev"),
+    ("ncurses", "libncurses-dev"),
+    ("ncursesw", "libncurses-dev"),
+    ("ogg", "libogg-dev"),
+    ("oniguruma", "libonig-dev"),
+    ("openblas", "libopenblas-dev"),
+    ("openssl", "libssl-dev"),
+    ("opus", "libopus-dev"),
+    ("pango", "libpango1.0-dev"),
+    ("protobuf", "libprotobuf-dev"),
+    ("readline", "libreadline-dev"),
+    ("sqlite3", "libsqlite3-dev"),
+    ("tinfo", "libncurses-dev"),
+    ("vorbis", "libvorbis-dev"),
+    ("vulkan", "libvulkan-dev"),
+    ("wayland-client", "libwayland-dev"),
+    ("x11", "libx11-dev"),
+    ("xcb", "libxcb1-dev"),
+    ("xkbcommon", "libxkbcommon-dev"),
+    ("zlib", "zlib1g-dev"),
+];
+
+/// Gets the Debian package providing the pkg-config `module`, if known.
+pub(crate) fn dev_package(module: &str) -> Option<&'static str> {
+    DEV_PACKAGES
+        .binary_search_by_key(&module, |(module, _)| module)
+        .ok()
+        .map(|i| DEV_PACKAGES[i].1)
+}
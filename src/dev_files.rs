@@ -0,0 +1,147 @@
+//! Telling which half of a library's installation is missing when pkg-config can't find it.
+//!
+//! Distributions split a library into a runtime package with the versioned shared object,
+//! e.g. `libfoo.so.1`, and a development package with its headers, `.pc` file and the
+//! unversioned `libfoo.so` the linker looks for: `libfoo-dev` on Debian, Ubuntu and Alpine,
+//! `libfoo-devel` on Fedora, RHEL and openSUSE. Having only the runtime package installed, often
+//! pulled in by some program, is the most common reason a library that seems to be there isn't
+//! found.
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{env_var, link, termux, Error, TargetInfo};
+
+/// Describes the part of the library `name` that is installed when some of it is, from its
+/// `libs` and `headers` if given, or else guessing them from its name.
+///
+/// Only looks in the usual directories of native Unix builds.
+pub(crate) fn diagnose(
+    target: &TargetInfo,
+    name: &str,
+    libs: &[String],
+    headers: &[Utf8PathBuf],
+) -> Result<Option<String>, Error> {
+    if !target.families().iter().any(|family| family == "unix")
+        || env_var("HOST")? != target.triple()
+    {
+        return Ok(None);
+    }
+    let stem = name.strip_prefix("lib").unwrap_or(name);
+    let guessed = [stem.to_owned()];
+    let libs = if libs.is_empty() { &guessed[..] } else { libs };
+
+    let lib_dirs = lib_dirs(target)?;
+    let mut dev_lib = None;
+    let mut runtime_lib = None;
+    for dir in &lib_dirs {
+        for lib in libs {
+            let (dev, runtime) = find_lib(dir, lib);
+            dev_lib = dev_lib.or(dev);
+            runtime_lib = runtime_lib.or(runtime);
+        }
+    }
+
+    let include_dirs = include_dirs(target)?;
+    let header_dir = include_dirs.iter().find(|dir| {
+        if headers.is_empty() {
+            dir.join(format!("{stem}.h")).is_file()
+                || dir.join(stem).is_dir()
+                || dir.join(name).is_dir()
+        } else {
+            headers.iter().all(|header| dir.join(header).is_file())
+        }
+    });
+
+    let split = "distributions ship the headers, `.pc` file and unversioned `.so` link of a \
+                 library in a separate `-dev` (Debian, Ubuntu, Alpine) or `-devel` (Fedora, \
+                 RHEL, openSUSE) package";
+    let diagnosis = match (dev_lib, runtime_lib, header_dir) {
+        (None, Some(runtime), _) => format!(
+            "only the runtime library of `{name}`, {runtime}, is installed, \
+             not its development files; {split}"
+        ),
+        (None, None, Some(dir)) => format!(
+            "the headers of `{name}` are installed in {dir}, but not its library; \
+             its runtime package may be missing or for another architecture"
+        ),
+        (Some(lib), _, None) => {
+            format!("the library of `{name}`, {lib}, is installed, but not its headers; {split}")
+        }
+        (Some(lib), _, Some(dir)) => format!(
+            "the library of `{name}`, {lib}, and its headers in {dir} are installed, \
+             but pkg-config has no `.pc` file for it; it may be outside `PKG_CONFIG_PATH` \
+             or not shipped by the distribution"
+        ),
+        (None, None, None) => return Ok(None),
+    };
+    Ok(Some(diagnosis))
+}
+
+/// Finds the development link or static library of `lib` in `dir`,
+/// and the versioned runtime library.
+fn find_lib(dir: &Utf8Path, lib: &str) -> (Option<Utf8PathBuf>, Option<Utf8PathBuf>) {
+    let entries = match dir.read_dir_utf8() {
+        Ok(entries) => entries,
+        Err(_) => return (None, None),
+    };
+    let prefix = format!("lib{lib}.");
+    let mut dev = None;
+    let mut runtime = None;
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let suffix = match file_name.strip_prefix(&prefix) {
+            Some(suffix) => suffix,
+            None => continue,
+        };
+        if matches!(suffix, "so" | "a" | "dylib" | "tbd") {
+            dev = Some(entry.into_path());
+        } else if suffix.starts_with("so.") || suffix.ends_with(".dylib") {
+            runtime = runtime.or_else(|| Some(entry.into_path()));
+        }
+    }
+    (dev, runtime)
+}
+
+/// Gets the linker's default directories, with Debian's multiarch ones and `/usr/local/lib`.
+fn lib_dirs(target: &TargetInfo) -> Result<Vec<Utf8PathBuf>, Error> {
+    let mut dirs: Vec<Utf8PathBuf> = link::system_lib_dirs(target)
+        .into_iter()
+        .map(Utf8PathBuf::from)
+        .collect();
+    if target.os() == "linux" {
+        let arch = match target.arch() {
+            "x86" => "i386",
+            arch => arch,
+        };
+        let multiarch = format!("{arch}-linux-{}", target.env());
+        dirs.push(Utf8Path::new("/usr/lib").join(&multiarch));
+        dirs.push(Utf8Path::new("/lib").join(&multiarch));
+    }
+    dirs.push("/usr/local/lib".into());
+    if let Some(prefix) = prefix(target)? {
+        dirs.push(prefix.join("lib"));
+    }
+    Ok(dirs)
+}
+
+fn include_dirs(target: &TargetInfo) -> Result<Vec<Utf8PathBuf>, Error> {
+    let mut dirs = vec![
+        Utf8PathBuf::from("/usr/include"),
+        Utf8PathBuf::from("/usr/local/include"),
+    ];
+    if let Some(prefix) = prefix(target)? {
+        dirs.push(prefix.join("include"));
+    }
+    Ok(dirs)
+}
+
+/// Gets the prefix of Termux or of Homebrew on Apple silicon, which are outside `/usr`.
+fn prefix(target: &TargetInfo) -> Result<Option<Utf8PathBuf>, Error> {
+    if let Some(prefix) = termux::prefix(target)? {
+        return Ok(Utf8PathBuf::from_path_buf(prefix).ok());
+    }
+    if target.os() == "macos" {
+        return Ok(Some("/opt/homebrew".into()));
+    }
+    Ok(None)
+}
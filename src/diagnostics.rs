@@ -0,0 +1,75 @@
+//! Rendering warnings and errors readably.
+//!
+//! Cargo shows a `cargo:warning` line as one line, and drops the rest of a multi-line message,
+//! like the stderr pkg-config and other tools fail with, into the build script's output where
+//! nobody looks. Each line of a warning is printed as a warning of its own instead, the
+//! continuation lines indented, and in a terminal, long lines are wrapped to its width.
+
+use std::fmt;
+
+/// The columns cargo's `warning: <package>@<version>: ` prefix takes, roughly.
+const PREFIX_WIDTH: usize = 30;
+
+/// Prints `message` as warnings, a line each.
+pub(crate) fn warn(message: &str) {
+    let width = width();
+    let mut first = true;
+    for line in message.lines().filter(|line| !line.trim().is_empty()) {
+        for line in wrap(line, width) {
+            let indent = if first { "" } else { "  " };
+            println!("cargo:warning={indent}{line}");
+            first = false;
+        }
+    }
+}
+
+/// Gets the width to wrap warnings at, if cargo runs in a terminal. CI logs and IDEs
+/// don't wrap at any particular width.
+fn width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+    {
+        return Some(columns.saturating_sub(PREFIX_WIDTH).max(40));
+    }
+    let term = std::env::var("TERM").unwrap_or_default();
+    if term.is_empty() || term == "dumb" || std::env::var_os("CI").is_some() {
+        return None;
+    }
+    Some(100 - PREFIX_WIDTH)
+}
+
+/// Splits `line` at spaces into lines of at most `width` characters, keeping its indentation
+/// and words longer than that, like paths and URLs, whole.
+fn wrap(line: &str, width: Option<usize>) -> Vec<String> {
+    let width = match width {
+        Some(width) if line.chars().count() > width => width,
+        _ => return vec![line.to_owned()],
+    };
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in line.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        if current.is_empty() {
+            current.push_str(indent);
+        } else {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    lines.push(current);
+    lines
+}
+
+/// Indents each line of `text` by two spaces, for tool output quoted in an error.
+pub(crate) fn indent(text: impl fmt::Display) -> String {
+    text.to_string()
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| format!("  {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
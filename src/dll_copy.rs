@@ -0,0 +1,172 @@
+//! Copying the DLLs a Windows build links next to its binaries.
+//!
+//! Windows has no rpath: a program finds DLLs in its own directory or on `PATH`. Tests and
+//! binaries linked against vcpkg or vendored DLLs therefore fail to start with
+//! `STATUS_DLL_NOT_FOUND` until the DLLs are copied or `PATH` is extended by hand. Copying them
+//! into the profile directory, where `cargo run` puts binaries, and its `deps`, where tests
+//! are, makes both work.
+
+use std::collections::BTreeSet;
+use std::fs;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use object::read::archive::ArchiveFile;
+use object::read::coff::ImportFile;
+use object::Object;
+
+use crate::link::LinkDirectives;
+use crate::{io_error, path_var, Error};
+
+/// Copies the DLLs of the libraries in `link`, and the DLLs those import in turn, into the
+/// directories of the target's binaries, tests and examples.
+///
+/// The DLL of a library is named by its import library, and looked for in the search paths
+/// and the `bin` directories next to them, as vcpkg and most installs lay them out.
+/// Imports not found there, like the system's DLLs, are left alone.
+pub(crate) fn copy(link: &LinkDirectives) -> Result<(), Error> {
+    let lib_dirs: Vec<Utf8PathBuf> = link
+        .search_paths
+        .iter()
+        .map(|path| match path.split_once('=') {
+            Some((_, dir)) => dir.into(),
+            None => path.into(),
+        })
+        .collect();
+    let mut dll_dirs = lib_dirs.clone();
+    for dir in &lib_dirs {
+        if let Some(parent) = dir.parent() {
+            dll_dirs.push(parent.join("bin"));
+        }
+    }
+
+    let mut pending: Vec<String> = Vec::new();
+    for lib in &link.libs {
+        let (kind, name) = match lib.split_once('=') {
+            Some((kind, name)) => (kind, name),
+            None => ("dylib", lib.as_str()),
+        };
+        if !kind.starts_with("dylib") {
+            continue;
+        }
+        let import_lib = lib_dirs.iter().find_map(|dir| {
+            [format!("{name}.lib"), format!("lib{name}.dll.a")]
+                .iter()
+                .map(|file| dir.join(file))
+                .find(|path| path.is_file())
+        });
+        if let Some(import_lib) = import_lib {
+            pending.extend(imported_dlls(&import_lib)?);
+        }
+    }
+
+    let mut dlls = BTreeSet::new();
+    while let Some(name) = pending.pop() {
+        let path = match find_dll(&dll_dirs, &name) {
+            Some(path) => path,
+            None => continue,
+        };
+        if dlls.insert(path.clone()) {
+            pending.extend(dll_imports(&path)?);
+        }
+    }
+    if dlls.is_empty() {
+        return Ok(());
+    }
+
+    // `OUT_DIR` is `<profile dir>/build/<package>-<hash>/out`.
+    let out_dir = path_var("OUT_DIR")?;
+    let profile_dir = match out_dir.ancestors().nth(3) {
+        Some(dir) => dir.to_owned(),
+        None => return Ok(()),
+    };
+    for dest_dir in [
+        profile_dir.clone(),
+        profile_dir.join("deps"),
+        profile_dir.join("examples"),
+    ] {
+        fs::create_dir_all(&dest_dir).map_err(io_error(&dest_dir))?;
+        for dll in &dlls {
+            println!("cargo:rerun-if-changed={dll}");
+            let dest = dest_dir.join(dll.file_name().unwrap_or_default());
+            if is_up_to_date(dll, &dest) {
+                continue;
+            }
+            // A running test or binary keeps its DLLs locked.
+            if let Err(err) = fs::copy(dll, &dest) {
+                warning!("couldn't copy {dll} to {dest_dir}: {err}");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn find_dll(dirs: &[Utf8PathBuf], name: &str) -> Option<Utf8PathBuf> {
+    dirs.iter().find_map(|dir| {
+        let entries = dir.read_dir_utf8().ok()?;
+        // Windows file names are case-insensitive, and import tables vary in case.
+        entries
+            .flatten()
+            .find(|entry| entry.file_name().eq_ignore_ascii_case(name))
+            .map(|entry| entry.into_path())
+    })
+}
+
+fn is_up_to_date(source: &Utf8Path, dest: &Utf8Path) -> bool {
+    match (fs::metadata(source), fs::metadata(dest)) {
+        (Ok(source), Ok(dest)) => {
+            source.len() == dest.len()
+                && matches!(
+                    (source.modified(), dest.modified()),
+                    (Ok(source), Ok(dest)) if dest >= source
+                )
+        }
+        _ => false,
+    }
+}
+
+/// Gets the DLLs the import library at `path` links to.
+///
+/// MSVC import libraries have short import members naming the DLL. Those of MinGW are
+/// regular objects with the name in their `.idata$7` section, so any string there ending
+/// in `.dll` is taken.
+fn imported_dlls(path: &Utf8Path) -> Result<BTreeSet<String>, Error> {
+    let data = fs::read(path).map_err(io_error(path))?;
+    let mut dlls = BTreeSet::new();
+    let archive = match ArchiveFile::parse(&*data) {
+        Ok(archive) => archive,
+        Err(_) => return Ok(dlls),
+    };
+    for member in archive.members().flatten() {
+        let member_data = match member.data(&*data) {
+            Ok(member_data) => member_data,
+            Err(_) => continue,
+        };
+        if let Ok(import) = ImportFile::parse(member_data) {
+            dlls.insert(String::from_utf8_lossy(import.dll()).into_owned());
+            continue;
+        }
+        for string in member_data.split(|byte| *byte == 0) {
+            if string.len() > 4 && string[string.len() - 4..].eq_ignore_ascii_case(b".dll") {
+                if let Ok(name) = std::str::from_utf8(string) {
+                    dlls.insert(name.to_owned());
+                }
+            }
+        }
+    }
+    Ok(dlls)
+}
+
+/// Gets the DLLs the DLL at `path` imports.
+fn dll_imports(path: &Utf8Path) -> Result<Vec<String>, Error> {
+    let data = fs::read(path).map_err(io_error(path))?;
+    let file = match object::File::parse(&*data) {
+        Ok(file) => file,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let imports = file.imports().unwrap_or_default();
+    let libraries: BTreeSet<String> = imports
+        .iter()
+        .map(|import| String::from_utf8_lossy(import.library()).into_owned())
+        .collect();
+    Ok(libraries.into_iter().collect())
+}
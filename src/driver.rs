@@ -0,0 +1,333 @@
+//! Drivers for the build systems vendored sources commonly use.
+//!
+//! The drivers build in `OUT_DIR/build`, or the shared directory of `build-both`,
+//! install into the context's [`install_prefix`],
+//! and pass the context's [`cflags`]
+//! so the C code is built with the same profile as the Rust code.
+//! When cross compiling, they also tell the build system the host it builds for.
+//! The paths of the libraries in `package.metadata.buildkit.dependencies`
+//! go into `CPPFLAGS` and `LDFLAGS`, and CMake's include and library search paths.
+//! Options mapped from cargo features in `package.metadata.buildkit.features` come
+//! before those set on the driver, so the driver's take precedence.
+//! They fail up front on paths with spaces or other characters build tools can't handle,
+//! naming the offending directory, unless `BUILDKIT_BUILD_DIR` moved the build elsewhere.
+//!
+//! [`install_prefix`]: crate::VendoredBuildContext::install_prefix
+//! [`cflags`]: crate::VendoredBuildContext::cflags
+
+use std::process::Command;
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+
+use crate::{
+    build_dir, cflags, io_error, run_build_command, Error, TargetInfo, VendoredBuildContext,
+};
+
+/// Builds and installs an autotools project (`configure && make && make install`).
+#[derive(Debug)]
+pub struct Autotools<'a> {
+    ctx: &'a VendoredBuildContext,
+    source_dir: Utf8PathBuf,
+    args: Vec<String>,
+}
+
+impl<'a> Autotools<'a> {
+    /// Creates a driver for the `configure` script in the vendored source.
+    pub fn new(ctx: &'a VendoredBuildContext) -> Autotools<'a> {
+        Autotools {
+            ctx,
+            source_dir: ctx.source_path().clone(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Sets the directory containing `configure`, if it's not the source root.
+    pub fn source_dir(&mut self, dir: impl Into<Utf8PathBuf>) -> &mut Self {
+        self.source_dir = dir.into();
+        self
+    }
+
+    /// Adds an argument to `configure`, e.g. `--disable-shared`.
+    pub fn arg(&mut self, arg: impl Into<String>) -> &mut Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Runs the build, returning the install prefix.
+    ///
+    /// The project is configured for the install prefix, so the paths compiled into it are
+    /// right, and installed with `DESTDIR` into a staging directory, from which it's moved
+    /// into the install prefix. Staging paths some projects record in `.la` and `.pc` files
+    /// and `*-config` scripts are then replaced with the prefix, as they would otherwise
+    /// point dependents at a directory that's gone.
+    pub fn build(&self) -> Result<Utf8PathBuf, Error> {
+        let prefix = self.ctx.install_prefix().clone();
+        if self.ctx.is_built() {
+            return Ok(prefix);
+        }
+        let build_dir = self.ctx.out_dir.join("build");
+        build_dir::check(&[&self.source_dir, &build_dir, &prefix])?;
+        std::fs::create_dir_all(&build_dir).map_err(io_error(&build_dir))?;
+        let staging = self.ctx.out_dir.join("destdir");
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging).map_err(io_error(&staging))?;
+        }
+
+        let flags = with_env_flags(self.ctx.cflags(), "CFLAGS");
+        let cxxflags = with_env_flags(self.ctx.cflags(), "CXXFLAGS");
+        let mut configure = command(self.ctx, "sh");
+        configure
+            .current_dir(&build_dir)
+            .arg(self.source_dir.join("configure"))
+            .arg(format!("--prefix={prefix}"))
+            .arg(format!("--libdir={prefix}/lib"));
+        if let Some(host) = &self.ctx.cross_host {
+            configure.arg(format!("--host={host}"));
+        }
+        if self.ctx.build_both() {
+            configure.args(["--enable-static", "--enable-shared"]);
+        }
+        run_step(
+            "configure",
+            configure
+                .args(&self.ctx.feature_options.configure)
+                .args(&self.args)
+                .env("CFLAGS", flags)
+                .env("CXXFLAGS", cxxflags),
+        )?;
+        run_step(
+            "build",
+            command(self.ctx, "make")
+                .current_dir(&build_dir)
+                .arg(format!("-j{}", num_jobs())),
+        )?;
+        run_step(
+            "install",
+            command(self.ctx, "make")
+                .current_dir(&build_dir)
+                .arg("install")
+                .arg(format!("DESTDIR={staging}")),
+        )?;
+
+        let mut installed = Vec::new();
+        // `make install` puts the files under `DESTDIR` followed by the absolute prefix.
+        let staged_prefix = staging.join(
+            prefix
+                .components()
+                .filter(|component| matches!(component, Utf8Component::Normal(_)))
+                .collect::<Utf8PathBuf>(),
+        );
+        move_tree(&staged_prefix, &prefix, &mut installed)?;
+        for path in installed.iter().filter(|path| has_embedded_prefix(path)) {
+            let contents = match std::fs::read_to_string(path) {
+                Ok(contents) => contents,
+                // A compiled `*-config` program.
+                Err(err) if err.kind() == std::io::ErrorKind::InvalidData => continue,
+                Err(err) => return Err(io_error(path)(err).into()),
+            };
+            if !contents.contains(staged_prefix.as_str()) {
+                continue;
+            }
+            let contents = contents.replace(staged_prefix.as_str(), prefix.as_str());
+            std::fs::write(path, contents).map_err(io_error(path))?;
+        }
+        std::fs::remove_dir_all(&staging).map_err(io_error(&staging))?;
+        Ok(prefix)
+    }
+}
+
+/// Moves the files in `src` into `dest`, merging with what's there, and records them.
+fn move_tree(src: &Utf8Path, dest: &Utf8Path, moved: &mut Vec<Utf8PathBuf>) -> Result<(), Error> {
+    std::fs::create_dir_all(dest).map_err(io_error(dest))?;
+    for entry in src.read_dir_utf8().map_err(io_error(src))? {
+        let entry = entry.map_err(io_error(src))?;
+        let target = dest.join(entry.file_name());
+        if entry.file_type().map_err(io_error(entry.path()))?.is_dir() {
+            move_tree(entry.path(), &target, moved)?;
+        } else {
+            std::fs::rename(entry.path(), &target).map_err(io_error(&target))?;
+            moved.push(target);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `path` is a libtool archive, a pkg-config file or a `*-config` script,
+/// which record the install prefix for dependents.
+fn has_embedded_prefix(path: &Utf8Path) -> bool {
+    let name = path.file_name().unwrap_or_default();
+    let in_bin = path.parent().and_then(Utf8Path::file_name) == Some("bin");
+    matches!(path.extension(), Some("la" | "pc")) || (in_bin && name.ends_with("-config"))
+}
+
+/// Builds and installs a CMake project.
+///
+/// The build type is derived from the cargo profile,
+/// and its per-configuration flags are replaced by the context's.
+#[derive(Debug)]
+pub struct CMake<'a> {
+    ctx: &'a VendoredBuildContext,
+    source_dir: Utf8PathBuf,
+    defines: Vec<(String, String)>,
+}
+
+impl<'a> CMake<'a> {
+    /// Creates a driver for the `CMakeLists.txt` in the vendored source.
+    pub fn new(ctx: &'a VendoredBuildContext) -> CMake<'a> {
+        CMake {
+            ctx,
+            source_dir: ctx.source_path().clone(),
+            defines: Vec::new(),
+        }
+    }
+
+    /// Sets the directory containing `CMakeLists.txt`, if it's not the source root.
+    pub fn source_dir(&mut self, dir: impl Into<Utf8PathBuf>) -> &mut Self {
+        self.source_dir = dir.into();
+        self
+    }
+
+    /// Adds a `-D<name>=<value>` cache entry.
+    pub fn define(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.defines.push((name.into(), value.into()));
+        self
+    }
+
+    /// Runs the build, returning the install prefix.
+    pub fn build(&self) -> Result<Utf8PathBuf, Error> {
+        let prefix = self.ctx.install_prefix().clone();
+        if self.ctx.is_built() {
+            return Ok(prefix);
+        }
+        let build_dir = self.ctx.out_dir.join("build");
+        build_dir::check(&[&self.source_dir, &build_dir, &prefix])?;
+        let build_type = cflags::cmake_build_type()?;
+        let config = build_type.to_uppercase();
+
+        let mut configure = command(self.ctx, "cmake");
+        configure
+            .arg("-S")
+            .arg(&self.source_dir)
+            .arg("-B")
+            .arg(&build_dir)
+            .arg(format!("-DCMAKE_INSTALL_PREFIX={prefix}"))
+            .arg("-DCMAKE_INSTALL_LIBDIR=lib")
+            .arg("-DCMAKE_INSTALL_INCLUDEDIR=include")
+            .arg("-DCMAKE_INSTALL_BINDIR=bin")
+            .arg(format!("-DCMAKE_BUILD_TYPE={build_type}"))
+            .arg(format!(
+                "-DCMAKE_C_FLAGS_{config}={}",
+                with_env_flags(self.ctx.cflags(), "CFLAGS")
+            ))
+            .arg(format!(
+                "-DCMAKE_CXX_FLAGS_{config}={}",
+                with_env_flags(self.ctx.cflags(), "CXXFLAGS")
+            ));
+        let target = self.ctx.target();
+        if cflags::Flavor::detect(target) == cflags::Flavor::Msvc {
+            // Projects using policy CMP0091 select the CRT with this rather than flags.
+            let runtime = if cflags::crt_static(target) {
+                "MultiThreaded"
+            } else {
+                "MultiThreadedDLL"
+            };
+            configure.arg(format!("-DCMAKE_MSVC_RUNTIME_LIBRARY={runtime}"));
+        }
+        if let Some(toolchain_file) = &self.ctx.cmake_toolchain_file {
+            configure.arg(format!("-DCMAKE_TOOLCHAIN_FILE={toolchain_file}"));
+        } else if self.ctx.cross_host.is_some() {
+            configure
+                .arg(format!("-DCMAKE_SYSTEM_NAME={}", cmake_system_name(target)))
+                .arg(format!("-DCMAKE_SYSTEM_PROCESSOR={}", target.arch()));
+        }
+        if self.ctx.build_both() {
+            // There's no standard switch for both; many projects take these.
+            configure
+                .arg("-DBUILD_SHARED_LIBS=ON")
+                .arg("-DBUILD_STATIC_LIBS=ON");
+        }
+        for (var, paths) in [
+            ("CMAKE_INCLUDE_PATH", self.ctx.dependency_include_paths()),
+            ("CMAKE_LIBRARY_PATH", self.ctx.dependency_link_paths()),
+        ] {
+            if !paths.is_empty() {
+                let paths: Vec<&str> = paths.iter().map(|path| path.as_str()).collect();
+                configure.arg(format!("-D{var}={}", paths.join(";")));
+            }
+        }
+        let feature_defines = self.ctx.feature_options.cmake.iter();
+        for (name, value) in feature_defines.chain(self.defines.iter().map(|(n, v)| (n, v))) {
+            configure.arg(format!("-D{name}={value}"));
+        }
+        run_step("configure", &mut configure)?;
+        run_step(
+            "build",
+            command(self.ctx, "cmake")
+                .arg("--build")
+                .arg(&build_dir)
+                .args(["--config", build_type])
+                .arg("--parallel")
+                .arg(num_jobs()),
+        )?;
+        run_step(
+            "install",
+            command(self.ctx, "cmake")
+                .arg("--install")
+                .arg(&build_dir)
+                .args(["--config", build_type]),
+        )?;
+        Ok(prefix)
+    }
+}
+
+/// Runs the configure, build or install `step` of a driver.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn run_step(step: &'static str, cmd: &mut Command) -> Result<(), Error> {
+    span!("build_step", step);
+    run_build_command(cmd)
+}
+
+/// Creates a command with the context's tool environment.
+fn command(ctx: &VendoredBuildContext, program: &str) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.envs(ctx.env().iter().map(|(var, value)| (var, value)));
+    cmd
+}
+
+/// Gets CMake's name for the target OS, which it needs to know it's cross compiling.
+fn cmake_system_name(target: &TargetInfo) -> &str {
+    match target.os() {
+        "macos" => "Darwin",
+        "ios" => "iOS",
+        "tvos" => "tvOS",
+        "watchos" => "watchOS",
+        "freebsd" => "FreeBSD",
+        "netbsd" => "NetBSD",
+        "openbsd" => "OpenBSD",
+        "dragonfly" => "DragonFly",
+        "solaris" | "illumos" => "SunOS",
+        "none" => "Generic",
+        "linux" => "Linux",
+        "windows" => "Windows",
+        "android" => "Android",
+        "emscripten" => "Emscripten",
+        "wasi" => "WASI",
+        os => os,
+    }
+}
+
+/// Joins `flags` with the user's own flags from the environment,
+/// which come last so they take precedence.
+fn with_env_flags(flags: &[String], var: &str) -> String {
+    println!("cargo:rerun-if-env-changed={var}");
+    let mut all = flags.join(" ");
+    if let Ok(extra) = std::env::var(var) {
+        all.push(' ');
+        all.push_str(&extra);
+    }
+    all
+}
+
+fn num_jobs() -> String {
+    std::env::var("NUM_JOBS").unwrap_or_else(|_| "1".to_owned())
+}
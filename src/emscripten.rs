@@ -0,0 +1,69 @@
+//! Building for Emscripten.
+//!
+//! Emscripten targets have no system libraries to probe: those of the build machine are
+//! native code. Common libraries are Emscripten ports instead, which `emcc` builds into its
+//! cache on first use when linking with a setting like `-sUSE_ZLIB=1`. Others are installed
+//! into the sysroot in that cache, with their `.pc` files, by `emmake make install` and the
+//! like.
+
+use camino::Utf8PathBuf;
+
+use crate::link::LinkDirectives;
+use crate::pc_file::PkgConfigEnv;
+use crate::TargetInfo;
+
+/// Whether the build targets Emscripten.
+pub(crate) fn is_target(target: &TargetInfo) -> bool {
+    target.os() == "emscripten"
+}
+
+/// Links the Emscripten port `port` if the build targets Emscripten.
+///
+/// A port is named like `zlib`, which is linked with `-sUSE_ZLIB=1`, or given as the setting,
+/// like `USE_SDL=2`, for ports with versions or options.
+pub(crate) fn port_link(port: Option<&str>, target: &TargetInfo) -> Option<LinkDirectives> {
+    let port = match port {
+        Some(port) if is_target(target) => port,
+        _ => return None,
+    };
+    let setting = if port.contains('=') {
+        port.to_owned()
+    } else {
+        format!("USE_{}=1", port.to_ascii_uppercase().replace('-', "_"))
+    };
+    let mut link = LinkDirectives::default();
+    link.args.push(format!("-s{setting}"));
+    Some(link)
+}
+
+/// Gets the sysroot in Emscripten's cache, from `EM_CACHE` or the emsdk's default cache.
+fn sysroot() -> Option<Utf8PathBuf> {
+    let var = |key| {
+        println!("cargo:rerun-if-env-changed={key}");
+        std::env::var(key).ok().filter(|path| !path.is_empty())
+    };
+    let cache = match var("EM_CACHE") {
+        Some(cache) => Utf8PathBuf::from(cache),
+        None => Utf8PathBuf::from(var("EMSDK")?).join("upstream/emscripten/cache"),
+    };
+    Some(cache.join("sysroot")).filter(|sysroot| sysroot.is_dir())
+}
+
+/// Probes the `.pc` files in Emscripten's sysroot, and only those, through `pkg_env` if the
+/// build targets Emscripten. Returns whether it does.
+pub(crate) fn configure_pkg_config(target: &TargetInfo, pkg_env: &mut PkgConfigEnv) -> bool {
+    if !is_target(target) {
+        return false;
+    }
+    if let Some(sysroot) = sysroot() {
+        let dirs = ["local/lib/pkgconfig", "lib/pkgconfig", "share/pkgconfig"]
+            .map(|dir| sysroot.join(dir).into_string());
+        pkg_env.set_default("PKG_CONFIG_LIBDIR", dirs.join(":"));
+    }
+    // Without a sysroot, the pkg-config crate refuses to cross compile with the build
+    // machine's `.pc` files.
+    if pkg_env.is_set("PKG_CONFIG_LIBDIR") {
+        pkg_env.set_default("PKG_CONFIG_ALLOW_CROSS", "1");
+    }
+    true
+}
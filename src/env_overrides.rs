@@ -0,0 +1,76 @@
+//! Reading the `BUILDKIT_*` environment variables.
+//!
+//! External build systems configure buildkit through these, so they are all read the same
+//! way: each read is tracked with `rerun-if-env-changed`, an empty value counts as unset, and
+//! a value that can't be used fails the build instead of being ignored.
+
+use std::env::VarError;
+
+use camino::Utf8PathBuf;
+
+use crate::{env_var, BuildKitMode, Error, ErrorKind};
+
+/// Gets the variable `key`, if set to something other than whitespace.
+pub(crate) fn var(key: &str) -> Result<Option<String>, Error> {
+    println!("cargo:rerun-if-env-changed={key}");
+    match std::env::var(key) {
+        Ok(value) if value.trim().is_empty() => Ok(None),
+        Ok(value) => Ok(Some(value)),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(VarError::NotUnicode(_)) => Err(invalid(key, "it isn't valid UTF-8".to_owned())),
+    }
+}
+
+/// Gets whether the switch `key` is on: `1` or `true`, as opposed to `0`, `false` or unset.
+pub(crate) fn flag(key: &str) -> Result<bool, Error> {
+    match var(key)?.as_deref().map(str::trim) {
+        None | Some("0") | Some("false") => Ok(false),
+        Some("1") | Some("true") => Ok(true),
+        Some(value) => Err(invalid(key, format!("`{value}` is not `1` or `0`"))),
+    }
+}
+
+/// Gets the absolute path in `key`.
+pub(crate) fn path(key: &str) -> Result<Option<Utf8PathBuf>, Error> {
+    match var(key)? {
+        Some(path) if Utf8PathBuf::from(&path).is_relative() => {
+            Err(invalid(key, format!("`{path}` is not an absolute path")))
+        }
+        path => Ok(path.map(Utf8PathBuf::from)),
+    }
+}
+
+/// Gets the mode in `key`: `pkg-config`, `vcpkg` or `vendored`.
+pub(crate) fn mode(key: &str) -> Result<Option<BuildKitMode>, Error> {
+    let mode = match var(key)? {
+        Some(mode) => mode,
+        None => return Ok(None),
+    };
+    match mode.trim() {
+        "pkg-config" => Ok(Some(BuildKitMode::PkgConfig)),
+        "vcpkg" => Ok(Some(BuildKitMode::Vcpkg)),
+        "vendored" | "vendored-build" => Ok(Some(BuildKitMode::VendoredBuild)),
+        _ => Err(invalid(
+            key,
+            format!("`{mode}` is not `pkg-config`, `vcpkg` or `vendored`"),
+        )),
+    }
+}
+
+/// Gets the name of the variable `BUILDKIT_<PACKAGE>_<suffix>` of the package being built,
+/// e.g. `BUILDKIT_LIBZ_SYS_PROVIDERS`.
+pub(crate) fn package_key(suffix: &str) -> Result<String, Error> {
+    let package = env_var("CARGO_PKG_NAME")?;
+    Ok(format!(
+        "BUILDKIT_{}_{suffix}",
+        package.to_uppercase().replace('-', "_")
+    ))
+}
+
+pub(crate) fn invalid(key: &str, reason: String) -> Error {
+    ErrorKind::InvalidEnvVar {
+        key: key.to_owned(),
+        reason,
+    }
+    .into()
+}
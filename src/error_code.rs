@@ -0,0 +1,110 @@
+//! Stable codes of errors, for tools classifying failures without parsing messages.
+//!
+//! Codes are never reused or renumbered; new kinds of errors take the next free code of
+//! their group. With `BUILDKIT_ERROR_JSON=1`, a failed build also leaves its error as
+//! `buildkit-error.json` in `OUT_DIR`.
+
+use serde_json::json;
+
+use crate::{env_overrides, env_var, path_var, Error, ErrorKind};
+
+impl ErrorKind {
+    /// Gets the code of the error and a name for its kind.
+    pub(crate) fn code(&self) -> (&'static str, &'static str) {
+        match self {
+            // Sources: fetching, verifying and unpacking.
+            ErrorKind::MissingHash(_) => ("BK0101", "missing-hash"),
+            ErrorKind::HashMismatch { .. } => ("BK0102", "hash-mismatch"),
+            // BK0103, `not-pinned`, is retired: `buildkit.lock` pins sources instead.
+            ErrorKind::UnverifiedNotAcknowledged(_) => ("BK0104", "unverified-not-acknowledged"),
+            ErrorKind::Download { .. } => ("BK0105", "download-failed"),
+            ErrorKind::Offline(_) => ("BK0106", "offline"),
+            ErrorKind::NetworkDenied(_) => ("BK0107", "network-denied"),
+            ErrorKind::UnsupportedUrl { .. } => ("BK0108", "unsupported-url"),
+            ErrorKind::UnsupportedArchive(_) => ("BK0109", "unsupported-archive"),
+            ErrorKind::Unpack { .. } => ("BK0110", "unpack-failed"),
+            ErrorKind::NoPrebuiltForTarget(_) => ("BK0111", "no-prebuilt-for-target"),
+            ErrorKind::InvalidUrlTemplate { .. } => ("BK0112", "invalid-url-template"),
+            ErrorKind::HermeticViolation { .. } => ("BK0113", "hermetic-violation"),
+            ErrorKind::LockMismatch { .. } => ("BK0114", "lock-mismatch"),
+            ErrorKind::NoLockfile(_) => ("BK0115", "no-lockfile"),
+            ErrorKind::NotLocked { .. } => ("BK0116", "not-locked"),
+            // Probing: system libraries and where they come from.
+            ErrorKind::PkgConfigProbe { .. } => ("BK0201", "pkg-config-not-found"),
+            ErrorKind::PkgConfigError(_) => ("BK0202", "pkg-config-failed"),
+            ErrorKind::VcpkgProbe { .. } => ("BK0203", "vcpkg-not-found"),
+            ErrorKind::VcpkgError(_) => ("BK0204", "vcpkg-failed"),
+            ErrorKind::VcpkgVersionMismatch { .. } => ("BK0205", "vcpkg-version-mismatch"),
+            ErrorKind::MissingVcpkgFeatures { .. } => ("BK0206", "missing-vcpkg-features"),
+            ErrorKind::CrtMismatch { .. } => ("BK0207", "crt-mismatch"),
+            ErrorKind::NoProviderFound(_) => ("BK0208", "no-provider-found"),
+            ErrorKind::MissingSysCrate { .. } => ("BK0209", "missing-sys-crate"),
+            ErrorKind::NotInLdconfigCache(_) => ("BK0210", "not-in-ldconfig-cache"),
+            ErrorKind::MissingHeader(_) => ("BK0211", "missing-header"),
+            ErrorKind::MissingGir(_) => ("BK0212", "missing-gir"),
+            ErrorKind::OutsideSysroot { .. } => ("BK0213", "outside-sysroot"),
+            ErrorKind::OutsideAllowedRoots { .. } => ("BK0214", "outside-allowed-roots"),
+            ErrorKind::AbiMismatch { .. } => ("BK0215", "abi-mismatch"),
+            ErrorKind::MissingVersionMacro { .. } => ("BK0216", "missing-version-macro"),
+            ErrorKind::InvalidLibrary { .. } => ("BK0217", "invalid-library"),
+            ErrorKind::ExcludedVersion { .. } => ("BK0218", "excluded-version"),
+            ErrorKind::VersionMismatch { .. } => ("BK0219", "version-mismatch"),
+            // The vendored build.
+            ErrorKind::MissingTool { .. } => ("BK0301", "missing-tool"),
+            ErrorKind::ToolTooOld { .. } => ("BK0302", "tool-too-old"),
+            ErrorKind::UnsafeBuildPath { .. } => ("BK0303", "unsafe-build-path"),
+            ErrorKind::IncompatibleLto { .. } => ("BK0304", "incompatible-lto"),
+            ErrorKind::MissingSymbols { .. } => ("BK0305", "missing-symbols"),
+            ErrorKind::CommandFailed { .. } => ("BK0306", "command-failed"),
+            ErrorKind::Custom(_) => ("BK0307", "custom"),
+            // Configuration: metadata, environment variables and config files.
+            ErrorKind::CargoMetadataError(_) => ("BK0401", "cargo-metadata-failed"),
+            ErrorKind::InvalidCargoMetadata(_) => ("BK0402", "invalid-cargo-metadata"),
+            ErrorKind::Json(_) => ("BK0403", "invalid-metadata"),
+            ErrorKind::NoVendoredSourceSpecified => ("BK0404", "no-vendored-source"),
+            ErrorKind::NoPkgConfigRequirementSpecified => ("BK0405", "no-pkg-config-requirement"),
+            ErrorKind::NoVcpkgRequirementSpecified => ("BK0406", "no-vcpkg-requirement"),
+            ErrorKind::InvalidVersion(_) => ("BK0407", "invalid-version"),
+            ErrorKind::UnknownBackend(_) => ("BK0408", "unknown-backend"),
+            ErrorKind::UnknownProvider(_) => ("BK0409", "unknown-provider"),
+            ErrorKind::UnsupportedBlasProvider { .. } => ("BK0410", "unsupported-blas-provider"),
+            ErrorKind::InvalidEnvVar { .. } => ("BK0411", "invalid-env-var"),
+            ErrorKind::InvalidCargoConfig { .. } => ("BK0412", "invalid-cargo-config"),
+            ErrorKind::InvalidLinkModifier { .. } => ("BK0413", "invalid-link-modifier"),
+            ErrorKind::InvalidLinkArg { .. } => ("BK0414", "invalid-link-arg"),
+            ErrorKind::InvalidCfg { .. } => ("BK0415", "invalid-cfg"),
+            ErrorKind::InvalidWorkspaceConfig { .. } => ("BK0416", "invalid-workspace-config"),
+            ErrorKind::InvalidLockfile { .. } => ("BK0417", "invalid-lockfile"),
+            // The build environment.
+            ErrorKind::EnvVarError { .. } => ("BK0501", "env-var-error"),
+            ErrorKind::Io { .. } => ("BK0502", "io-error"),
+            ErrorKind::NonUtf8Path { .. } => ("BK0503", "non-utf8-path"),
+            // The guidance doesn't change what failed.
+            ErrorKind::WithHelp { err, .. } => err.code(),
+        }
+    }
+}
+
+/// Writes `err` to `OUT_DIR/buildkit-error.json` with `BUILDKIT_ERROR_JSON=1`, as
+/// `{"code": "BK0102", "kind": "hash-mismatch", "package": "...", "message": "..."}`.
+///
+/// This is best effort: the build fails with `err` either way.
+pub(crate) fn write_json(err: &Error) {
+    if !matches!(env_overrides::flag("BUILDKIT_ERROR_JSON"), Ok(true)) {
+        return;
+    }
+    let (code, kind) = err.0.code();
+    let report = json!({
+        "code": code,
+        "kind": kind,
+        "package": env_var("CARGO_PKG_NAME").ok(),
+        "message": err.0.to_string(),
+    });
+    if let Ok(out_dir) = path_var("OUT_DIR") {
+        let path = out_dir.join("buildkit-error.json");
+        let contents = serde_json::to_string_pretty(&report).expect("JSON values always serialize");
+        if let Err(write_err) = std::fs::write(&path, contents) {
+            warning!("couldn't write {path}: {write_err}");
+        }
+    }
+}
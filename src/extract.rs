@@ -0,0 +1,73 @@
+//! Unpacking of downloaded archives.
+//!
+//! Deep source trees like boost's or ICU's exceed Windows' 260 character `MAX_PATH` once
+//! unpacked under `OUT_DIR`, so they're unpacked through extended-length `\\?\` paths.
+
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use flate2::read::GzDecoder;
+
+use crate::{io_error, Error, ErrorKind};
+
+/// Unpacks a `.tar.gz`, `.tgz`, `.tar` or `.zip` archive into `dest`.
+pub(crate) fn unpack(archive: &Utf8Path, dest: &Utf8Path) -> Result<(), Error> {
+    span!("extract", %archive);
+    let name = archive.file_name().unwrap_or_default();
+    let file = File::open(archive).map_err(io_error(archive))?;
+    let result = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        tar::Archive::new(GzDecoder::new(file)).unpack(long_path(dest))
+    } else if name.ends_with(".tar") {
+        tar::Archive::new(file).unpack(long_path(dest))
+    } else if name.ends_with(".zip") {
+        zip::ZipArchive::new(file)
+            .and_then(|mut zip| zip.extract(long_path(dest)))
+            .map_err(io::Error::from)
+    } else {
+        return Err(ErrorKind::UnsupportedArchive(name.to_owned()).into());
+    };
+    result.map_err(|err| {
+        ErrorKind::Unpack {
+            path: archive.to_owned(),
+            err,
+        }
+        .into()
+    })
+}
+
+/// Gets `path` as an extended-length `\\?\` path on Windows, which isn't limited to
+/// `MAX_PATH`, or unchanged elsewhere.
+///
+/// Extended-length paths are used as they are, so `path` has to be absolute and normalized,
+/// like those under `OUT_DIR`.
+pub(crate) fn long_path(path: &Utf8Path) -> PathBuf {
+    if !cfg!(windows) || !path.is_absolute() {
+        return path.into();
+    }
+    let path = path.as_str().replace('/', "\\");
+    if path.starts_with(r"\\?\") {
+        path.into()
+    } else if let Some(share) = path.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{share}").into()
+    } else {
+        format!(r"\\?\{path}").into()
+    }
+}
+
+/// Gets the root of an unpacked archive.
+///
+/// Most archives wrap everything in a single `name-version/` directory,
+/// in which case that directory is the root.
+pub(crate) fn archive_root(dir: &Utf8Path) -> Result<Utf8PathBuf, Error> {
+    let mut entries = dir
+        .read_dir_utf8()
+        .map_err(io_error(dir))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_error(dir))?;
+    match entries.pop() {
+        Some(entry) if entries.is_empty() && entry.path().is_dir() => Ok(entry.into_path()),
+        _ => Ok(dir.to_owned()),
+    }
+}
@@ -0,0 +1,80 @@
+//! Mapping cargo features to options of the vendored build.
+//!
+//! ```toml
+//! [package.metadata.buildkit.features.ssl]
+//! configure = ["--with-openssl"]
+//! cmake = { USE_OPENSSL = "ON" }
+//! cc = { USE_OPENSSL = "1" }
+//! disabled = { configure = ["--without-openssl"], cmake = { USE_OPENSSL = "OFF" } }
+//!
+//! [package.metadata.buildkit.features.wayland]
+//! pkg-config = [{ name = "wayland-client", version-req = { min = "1.20" } }]
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::{linkage, PkgConfigRequirement};
+
+/// Options of the vendored build for a cargo feature.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct FeatureOptions {
+    /// Options applied when the feature is enabled.
+    #[serde(flatten)]
+    enabled: BuildOptions,
+    /// Options applied when the feature is disabled.
+    #[serde(default)]
+    disabled: BuildOptions,
+    /// pkg-config modules probed along with the library when the feature is enabled.
+    #[serde(default)]
+    pkg_config: Vec<PkgConfigRequirement>,
+}
+
+/// Options for the build systems the drivers run.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct BuildOptions {
+    /// Arguments to `configure`.
+    #[serde(default)]
+    pub(crate) configure: Vec<String>,
+    /// CMake cache entries.
+    #[serde(default)]
+    pub(crate) cmake: BTreeMap<String, String>,
+    /// Preprocessor definitions for `cc` builds.
+    #[serde(default)]
+    pub(crate) cc: BTreeMap<String, String>,
+}
+
+impl BuildOptions {
+    /// Adds `other`'s options, which take precedence.
+    pub(crate) fn extend(&mut self, other: &BuildOptions) {
+        self.configure.extend(other.configure.iter().cloned());
+        self.cmake.extend(other.cmake.clone());
+        self.cc.extend(other.cc.clone());
+    }
+}
+
+/// Collects the options for the cargo features as they are enabled or not.
+pub(crate) fn resolve(features: &BTreeMap<String, FeatureOptions>) -> BuildOptions {
+    let mut resolved = BuildOptions::default();
+    for (feature, options) in features {
+        if linkage::feature_enabled(feature) {
+            resolved.extend(&options.enabled);
+        } else {
+            resolved.extend(&options.disabled);
+        }
+    }
+    resolved
+}
+
+/// Lists the extra pkg-config modules of the enabled cargo features.
+pub(crate) fn pkg_config_requirements(
+    features: &BTreeMap<String, FeatureOptions>,
+) -> impl Iterator<Item = &PkgConfigRequirement> {
+    features
+        .iter()
+        .filter(|(feature, _)| linkage::feature_enabled(feature))
+        .flat_map(|(_, options)| &options.pkg_config)
+}
@@ -0,0 +1,897 @@
+//! Fetching remote sources into `OUT_DIR`.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use sha2::{Digest, Sha256};
+
+use crate::cargo_config::{self, CargoConfig};
+use crate::lockfile;
+use crate::verify::{self, Digests};
+use crate::workspace_config::WorkspaceConfig;
+use crate::{
+    build_dir, env_overrides, env_var, extract, hermetic, io_error, run_command, Error, ErrorKind,
+};
+
+/// Downloads sources from places buildkit doesn't know, e.g. an internal artifact server.
+///
+/// Register it with [`BuildKit::fetcher`](crate::BuildKit::fetcher). buildkit still
+/// verifies, caches and unpacks what the fetcher downloads.
+pub trait SourceFetcher {
+    /// Whether this fetcher handles `url`, e.g. by its scheme.
+    fn handles(&self, url: &str) -> bool;
+
+    /// Downloads `url` to the file `dest`.
+    fn fetch(&self, url: &str, dest: &Utf8Path) -> Result<(), Error>;
+
+    /// Checks out `git_ref` of the git repository at `url` into the empty directory `dest`,
+    /// for `git-repo` sources. If the source pins a commit, `dest` has to be a git checkout
+    /// of it, so the commit can be checked.
+    ///
+    /// Fetchers of archives only don't need it; the default fails.
+    fn fetch_git(&self, url: &str, git_ref: &str, dest: &Utf8Path) -> Result<(), Error> {
+        let _ = (git_ref, dest);
+        Err(ErrorKind::UnsupportedUrl {
+            url: url.to_owned(),
+            hint: "implement `SourceFetcher::fetch_git` to fetch git sources",
+        }
+        .into())
+    }
+}
+
+/// How sources may be fetched, from the builder and the metadata.
+pub(crate) struct FetchContext<'a> {
+    pub(crate) fetchers: &'a [Box<dyn SourceFetcher>],
+    pub(crate) allow_unverified: bool,
+    /// The hash of the source's unpacked tree, which verifies any kind of source.
+    pub(crate) tree_hash: Option<&'a Digests>,
+    /// The workspace's `buildkit.toml`, if any.
+    pub(crate) workspace: Option<&'a WorkspaceConfig>,
+}
+
+impl FetchContext<'_> {
+    /// Lets `what` through without a hash if the crate allows unverified sources
+    /// and the user acknowledged it with `BUILDKIT_ALLOW_UNVERIFIED=1`.
+    fn allow_unverified(&self, what: &str) -> Result<(), Error> {
+        if !self.allow_unverified {
+            return Err(ErrorKind::MissingHash(what.to_owned()).into());
+        }
+        if !env_overrides::flag("BUILDKIT_ALLOW_UNVERIFIED")? {
+            return Err(ErrorKind::UnverifiedNotAcknowledged(what.to_owned()).into());
+        }
+        warning!(
+            "!!! USING UNVERIFIED SOURCE `{what}` !!! \
+             Its contents are not pinned by a hash; never release a build made from it."
+        );
+        Ok(())
+    }
+}
+
+/// Downloads, verifies and unpacks an archive, returning the root of the unpacked tree.
+///
+/// Either the archive's hash or the context's tree hash, or both, pins the contents.
+/// Unpacked archives are keyed by their hash and reused across builds.
+/// Unverified archives are keyed by their URL and downloaded again every time,
+/// since their contents may change.
+/// Custom fetchers take precedence over the built-in HTTP(S) download.
+/// Whatever it's fetched from, the tree is checked against `buildkit.lock` under `source_url`.
+pub(crate) fn fetch_archive(
+    source_url: &str,
+    hash: Option<&Digests>,
+    cx: &FetchContext<'_>,
+) -> Result<Utf8PathBuf, Error> {
+    span!("fetch", url = source_url);
+    let url = &match vendor_dir_lookup(source_url, cx)? {
+        Some(dir) if dir.is_dir() => {
+            if let Some(tree_hash) = cx.tree_hash {
+                tree_hash.verify_local_tree(&dir)?;
+            }
+            lockfile::check(source_url, || verify::tree_digest(&dir))?;
+            return stage(&dir);
+        }
+        Some(archive) => archive.into_string(),
+        None => mirrored(source_url, cx)?,
+    };
+    let cache = cache_dir()?;
+    let key = match hash.or(cx.tree_hash) {
+        Some(hash) => hash.cache_key(),
+        None => {
+            cx.allow_unverified(url)?;
+            unverified_key(&[url])
+        }
+    };
+    let unpacked = cache.join("sources").join(dir_name(&key)?);
+    let marker = unpacked.with_extension("ok");
+    event!(debug, %unpacked, hit = marker.exists(), "looking up the source cache");
+    if !marker.exists() {
+        remove_digest(&marker)?;
+        let archive = downloads_dir(cx)?
+            .unwrap_or_else(|| cache.clone())
+            .join("downloads")
+            .join(format!("{key}-{}", file_name(url)));
+        // Only pinned contents can be resumed or reused safely; an unverified source may have
+        // changed. A pinned archive left by an earlier build is verified like a new one.
+        let pinned = hash.or(cx.tree_hash).is_some();
+        let reused = pinned && archive.exists();
+        if !reused {
+            download(url, &archive, cx.fetchers, pinned)?;
+        }
+        let mut result = unpack_verified(&archive, &unpacked, hash, cx);
+        if result.is_err() && reused {
+            // A truncated or corrupted archive in the cache would fail every later build.
+            warning!("the cached `{archive}` doesn't verify, downloading it again");
+            fs::remove_file(&archive).map_err(io_error(&archive))?;
+            download(url, &archive, cx.fetchers, pinned)?;
+            result = unpack_verified(&archive, &unpacked, hash, cx);
+        }
+        if let Err(err) = result {
+            // An archive that doesn't verify is of no use to later builds either.
+            if archive.exists() {
+                fs::remove_file(&archive).map_err(io_error(&archive))?;
+            }
+            return Err(err);
+        }
+        if pinned {
+            fs::write(&marker, url).map_err(io_error(&marker))?;
+        }
+    }
+    let root = extract::archive_root(&unpacked)?;
+    lockfile::check(source_url, || cached_digest(&root, &marker))?;
+    Ok(root)
+}
+
+/// Gets the tree digest of the source fetched to `root`, computing it once and keeping it
+/// next to its `marker`.
+fn cached_digest(root: &Utf8Path, marker: &Utf8Path) -> Result<String, Error> {
+    let path = marker.with_extension("digest");
+    if let Ok(digest) = fs::read_to_string(&path) {
+        return Ok(digest);
+    }
+    let digest = verify::tree_digest(root)?;
+    fs::write(&path, &digest).map_err(io_error(&path))?;
+    Ok(digest)
+}
+
+/// Removes the digest kept next to `marker`, before the source is fetched again.
+fn remove_digest(marker: &Utf8Path) -> Result<(), Error> {
+    let path = marker.with_extension("digest");
+    if path.exists() {
+        fs::remove_file(&path).map_err(io_error(&path))?;
+    }
+    Ok(())
+}
+
+/// Verifies `archive` against `hash` and unpacks it into `unpacked`, verifying the tree
+/// against the context's tree hash.
+fn unpack_verified(
+    archive: &Utf8Path,
+    unpacked: &Utf8Path,
+    hash: Option<&Digests>,
+    cx: &FetchContext<'_>,
+) -> Result<(), Error> {
+    if unpacked.exists() {
+        fs::remove_dir_all(extract::long_path(unpacked)).map_err(io_error(unpacked))?;
+    }
+    if let Some(hash) = hash {
+        hash.verify_file(archive)?;
+    }
+    extract::unpack(archive, unpacked)?;
+    if let Some(tree_hash) = cx.tree_hash {
+        tree_hash.verify_tree(&extract::archive_root(unpacked)?)?;
+    }
+    Ok(())
+}
+
+/// Checks out `git_ref` of a git repository, verifying it resolves to the commit `hash`
+/// and the tree hash of the context, if given, and checking the tree against `buildkit.lock`.
+/// Like for archives, custom fetchers take precedence over the built-in `git` checkout.
+pub(crate) fn fetch_git(
+    url: &str,
+    git_ref: &str,
+    hash: Option<&str>,
+    cx: &FetchContext<'_>,
+) -> Result<Utf8PathBuf, Error> {
+    span!("fetch", url, git_ref);
+    // The checkout is locked under the ref it's of, as the URL alone doesn't pin one.
+    let locked_url = format!("{url}#{git_ref}");
+    if let Some(dir) = vendor_dir_lookup(url, cx)?.filter(|path| path.is_dir()) {
+        if let (Some(hash), true) = (hash, dir.join(".git").exists()) {
+            verify::check(url, hash, git(Some(&dir), &["rev-parse", "HEAD"])?)?;
+        }
+        if let Some(tree_hash) = cx.tree_hash {
+            tree_hash.verify_local_tree(&dir)?;
+        }
+        lockfile::check(&locked_url, || verify::tree_digest(&dir))?;
+        return stage(&dir);
+    }
+    let url = &mirrored(url, cx)?;
+    let key = match (hash, cx.tree_hash) {
+        (Some(hash), _) => cache_key(hash).to_owned(),
+        (None, Some(tree_hash)) => tree_hash.cache_key(),
+        (None, None) => {
+            cx.allow_unverified(&format!("{url}#{git_ref}"))?;
+            unverified_key(&[url, git_ref])
+        }
+    };
+    let checkout = cache_dir()?.join("git").join(dir_name(&key)?);
+    let marker = checkout.with_extension("ok");
+    event!(debug, %checkout, hit = marker.exists(), "looking up the checkout cache");
+    if !marker.exists() {
+        remove_digest(&marker)?;
+        if checkout.exists() {
+            fs::remove_dir_all(extract::long_path(&checkout)).map_err(io_error(&checkout))?;
+        }
+        if let Some(fetcher) = cx.fetchers.iter().find(|fetcher| fetcher.handles(url)) {
+            check_network_allowed(url)?;
+            event!(info, url, git_ref, "fetching");
+            fs::create_dir_all(&checkout).map_err(io_error(&checkout))?;
+            fetcher.fetch_git(url, git_ref, &checkout)?;
+        } else {
+            check_online(url)?;
+            git(None, &["init", "-q", checkout.as_str()])?;
+            git(
+                Some(&checkout),
+                &["fetch", "-q", "--depth", "1", url, git_ref],
+            )?;
+            git(Some(&checkout), &["checkout", "-q", "FETCH_HEAD"])?;
+        }
+        if let Some(hash) = hash {
+            // Not a repository the checkout is in, if a fetcher didn't make it one.
+            let head = git(Some(&checkout), &["--git-dir", ".git", "rev-parse", "HEAD"])?;
+            verify::check(url, hash, head)?;
+        }
+        if let Some(tree_hash) = cx.tree_hash {
+            tree_hash.verify_tree(&checkout)?;
+        }
+        if hash.is_some() || cx.tree_hash.is_some() {
+            fs::write(&marker, url).map_err(io_error(&marker))?;
+        }
+    }
+    lockfile::check(&locked_url, || cached_digest(&checkout, &marker))?;
+    Ok(checkout)
+}
+
+/// Looks `url` up in `BUILDKIT_VENDOR_DIR`, a directory of pre-fetched sources that is
+/// consulted before any network access, e.g. for offline builds from a source tarball.
+///
+/// It holds a directory per crate with the file named like the last segment of `url`,
+/// e.g. `libz-sys/zlib-1.3.1.tar.gz`, or the unpacked source in a directory of that name
+/// without the extension, e.g. `libz-sys/zlib-1.3.1`. Archives are verified as if
+/// downloaded; unpacked sources only if there is a tree hash, as the user vouches for them,
+/// and against `buildkit.lock` with `BUILDKIT_LOCKED=1`.
+///
+/// The workspace's `buildkit.toml` can set `vendor-dir` instead.
+fn vendor_dir_lookup(url: &str, cx: &FetchContext<'_>) -> Result<Option<Utf8PathBuf>, Error> {
+    let dir = match env_overrides::path("BUILDKIT_VENDOR_DIR")? {
+        Some(dir) => dir,
+        None => match cx.workspace.and_then(WorkspaceConfig::vendor_dir) {
+            Some(dir) => dir,
+            None => return Ok(None),
+        },
+    };
+    let crate_dir = dir.join(env_var("CARGO_PKG_NAME")?);
+    let name = file_name(url);
+    let stem = [".tar.gz", ".tgz", ".tar", ".zip", ".git"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name);
+    Ok([crate_dir.join(name), crate_dir.join(stem)]
+        .into_iter()
+        .find(|path| path.exists()))
+}
+
+/// Copies a source inside the crate to `OUT_DIR`, so builds can write into it.
+///
+/// Crates unpacked by `cargo vendor` or into the registry are checksummed by cargo
+/// and must not be modified, but many build systems write into their source tree.
+/// Local checkouts are staged too when they have to be relocated, and copied again once
+/// they're edited.
+pub(crate) fn stage(path: &Utf8Path) -> Result<Utf8PathBuf, Error> {
+    println!("cargo:rerun-if-changed={path}");
+    let key = &hex::encode(Sha256::digest(path.as_str()))[..16];
+    let staged = cache_dir()?.join("staged").join(key);
+    let marker = staged.with_extension("ok");
+    let mut hasher = Sha256::new();
+    fingerprint(path, path, &mut hasher)?;
+    let fingerprint = hex::encode(hasher.finalize());
+    if fs::read_to_string(&marker).ok().as_deref() != Some(fingerprint.as_str()) {
+        if staged.exists() {
+            fs::remove_dir_all(&staged).map_err(io_error(&staged))?;
+        }
+        copy_tree(path, &staged)?;
+        fs::write(&marker, fingerprint).map_err(io_error(&marker))?;
+    }
+    Ok(staged)
+}
+
+/// Hashes the names, sizes and modification times of the files under `dir`, which change
+/// whenever one of them is edited, added or removed, without reading their contents.
+pub(crate) fn fingerprint(
+    root: &Utf8Path,
+    dir: &Utf8Path,
+    hasher: &mut Sha256,
+) -> Result<(), Error> {
+    let mut entries = dir
+        .read_dir_utf8()
+        .map_err(io_error(dir))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_error(dir))?;
+    entries.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    for entry in entries {
+        let path = entry.path();
+        let metadata = fs::symlink_metadata(path).map_err(io_error(path))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        hasher.update(format!(
+            "{relative}\0{}\0{}\n",
+            metadata.len(),
+            modified.as_nanos()
+        ));
+        if metadata.is_dir() {
+            fingerprint(root, path, hasher)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the directory `src` to `dest`, keeping permissions and symlinks.
+fn copy_tree(src: &Utf8Path, dest: &Utf8Path) -> Result<(), Error> {
+    fs::create_dir_all(dest).map_err(io_error(dest))?;
+    for entry in src.read_dir_utf8().map_err(io_error(src))? {
+        let entry = entry.map_err(io_error(src))?;
+        let target = dest.join(entry.file_name());
+        let file_type = entry.file_type().map_err(io_error(entry.path()))?;
+        if file_type.is_dir() {
+            copy_tree(entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target).map_err(io_error(entry.path()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Utf8Path, dest: &Utf8Path) -> Result<(), Error> {
+    let link = fs::read_link(src).map_err(io_error(src))?;
+    std::os::unix::fs::symlink(link, dest).map_err(io_error(dest))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Utf8Path, dest: &Utf8Path) -> Result<(), Error> {
+    if src.is_dir() {
+        copy_tree(src, dest)
+    } else {
+        fs::copy(src, dest).map_err(io_error(src))?;
+        Ok(())
+    }
+}
+
+/// Fails the fetch of `url` if cargo is configured to stay offline with `net.offline`, e.g.
+/// for a build from `cargo vendor` sources, or `BUILDKIT_OFFLINE=1`, unless `url` is local.
+fn check_online(url: &str) -> Result<(), Error> {
+    check_network_allowed(url)?;
+    if local_path(url).is_some() {
+        return Ok(());
+    }
+    if env_overrides::flag("BUILDKIT_OFFLINE")?
+        || CargoConfig::load()?.bool("net.offline")? == Some(true)
+    {
+        return Err(ErrorKind::Offline(url.to_owned()).into());
+    }
+    Ok(())
+}
+
+/// Fails the fetch of `url` with `BUILDKIT_DENY_NETWORK=1` unless it's local, for sandboxes
+/// where a network access would hang or break reproducibility. Unlike being offline,
+/// this applies to registered fetchers too.
+fn check_network_allowed(url: &str) -> Result<(), Error> {
+    if local_path(url).is_some() {
+        return Ok(());
+    }
+    hermetic::check_fetch(url)?;
+    if env_overrides::flag("BUILDKIT_DENY_NETWORK")? {
+        return Err(ErrorKind::NetworkDenied(url.to_owned()).into());
+    }
+    Ok(())
+}
+
+/// Rewrites an upstream URL to the mirror in `BUILDKIT_SOURCE_MIRROR`, if any.
+///
+/// The variable is either a base URL, under which the upstream host and path are looked up
+/// (`https://github.com/a/b.tar.gz` becomes `<base>/github.com/a/b.tar.gz`),
+/// or `;`-separated `<upstream prefix>=<mirror prefix>` rules, the first match of which applies.
+/// It can also be set in the `[env]` table of `.cargo/config.toml`, or as `source-mirror`
+/// in the workspace's `buildkit.toml`.
+fn mirrored(url: &str, cx: &FetchContext<'_>) -> Result<String, Error> {
+    let mirror = match env_overrides::var("BUILDKIT_SOURCE_MIRROR")? {
+        Some(mirror) => mirror,
+        None => match cx.workspace.and_then(WorkspaceConfig::source_mirror) {
+            Some(mirror) if !mirror.trim().is_empty() => mirror.to_owned(),
+            _ => return Ok(url.to_owned()),
+        },
+    };
+    let rewritten = if mirror.contains('=') {
+        mirror.split(';').find_map(|rule| {
+            let (upstream, replacement) = rule.trim().split_once('=')?;
+            let rest = url.strip_prefix(upstream.trim())?;
+            Some(format!("{}{rest}", replacement.trim()))
+        })
+    } else {
+        url.split_once("://")
+            .filter(|(scheme, _)| *scheme != "file")
+            .map(|(_, rest)| format!("{}/{rest}", mirror.trim().trim_end_matches('/')))
+    };
+    match rewritten {
+        Some(rewritten) => {
+            eprintln!("buildkit: fetching {url} from {rewritten}");
+            Ok(rewritten)
+        }
+        None => Ok(url.to_owned()),
+    }
+}
+
+/// Downloads `url` to `dest`, going through a temporary file so that
+/// an interrupted download never looks complete.
+///
+/// `file://` URLs and absolute paths are copied, e.g. from a local mirror in an air-gapped
+/// environment. `s3://` and `gs://` URLs need the `cloud-storage` feature.
+///
+/// Transient failures are retried with backoff, like cargo does. With `resume`,
+/// HTTP downloads continue from an earlier interrupted attempt using range requests.
+fn download(
+    url: &str,
+    dest: &Utf8Path,
+    fetchers: &[Box<dyn SourceFetcher>],
+    resume: bool,
+) -> Result<(), Error> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(io_error(parent))?;
+    }
+    let partial = dest.with_extension("part");
+    check_network_allowed(url)?;
+    event!(info, url, "downloading");
+    if let Some(fetcher) = fetchers.iter().find(|fetcher| fetcher.handles(url)) {
+        fetcher.fetch(url, &partial)?;
+        fs::rename(&partial, dest).map_err(io_error(dest))?;
+        return Ok(());
+    }
+    check_online(url)?;
+    if url.starts_with("s3://") || url.starts_with("gs://") {
+        #[cfg(feature = "cloud-storage")]
+        {
+            crate::cloud_storage::download(url, &partial)?;
+            fs::rename(&partial, dest).map_err(io_error(dest))?;
+            return Ok(());
+        }
+        #[cfg(not(feature = "cloud-storage"))]
+        return Err(ErrorKind::UnsupportedUrl {
+            url: url.to_owned(),
+            hint: "enable buildkit's `cloud-storage` feature",
+        }
+        .into());
+    }
+    if let Some(path) = local_path(url) {
+        fs::copy(&path, &partial).map_err(io_error(&path))?;
+        fs::rename(&partial, dest).map_err(io_error(dest))?;
+        return Ok(());
+    }
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(ErrorKind::UnsupportedUrl {
+            url: url.to_owned(),
+            hint: "use an http(s), file, s3 or gs URL, or register a `SourceFetcher`",
+        }
+        .into());
+    }
+    let agent = agent()?;
+    let retries = net_retries();
+    let mut attempt = 0;
+    while let Err(err) = http_get(&agent, url, &partial, resume) {
+        if attempt == retries || !is_transient(&err) {
+            return Err(ErrorKind::Download {
+                url: url.to_owned(),
+                err,
+            }
+            .into());
+        }
+        attempt += 1;
+        let delay = backoff(attempt);
+        warning!(
+            "download failed: {err}; retrying in {:.1}s ({attempt}/{retries})",
+            delay.as_secs_f64()
+        );
+        thread::sleep(delay);
+    }
+    fs::rename(&partial, dest).map_err(io_error(dest))?;
+    Ok(())
+}
+
+/// Gets the local path of a `file://` URL or an absolute path.
+fn local_path(url: &str) -> Option<Utf8PathBuf> {
+    if let Some(path) = url.strip_prefix("file://") {
+        // `file:///C:/src.tar.gz` is `C:/src.tar.gz` on Windows.
+        let path = match path.strip_prefix('/') {
+            Some(rest) if cfg!(windows) && rest.get(1..2) == Some(":") => rest,
+            _ => path,
+        };
+        return Some(percent_decode(path).into());
+    }
+    let path = Utf8Path::new(url);
+    if path.is_absolute() {
+        Some(path.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Decodes `%XX` escapes in a URL path, leaving invalid ones as they are.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Downloads `url` into `dest`, resuming from where a previous attempt left off
+/// if `resume` is set and the server supports range requests.
+fn http_get(
+    agent: &ureq::Agent,
+    url: &str,
+    dest: &Utf8Path,
+    resume: bool,
+) -> Result<(), Box<ureq::Error>> {
+    let offset = if resume {
+        fs::metadata(dest).map_or(0, |metadata| metadata.len())
+    } else {
+        0
+    };
+    let mut request = agent.get(url);
+    if offset > 0 {
+        request = request.set("Range", &format!("bytes={offset}-"));
+    }
+    let response = match request.call() {
+        // The partial file is complete or stale, so start over.
+        Err(ureq::Error::Status(416, _)) if offset > 0 => {
+            fs::remove_file(dest).map_err(ureq::Error::from)?;
+            return http_get(agent, url, dest, false);
+        }
+        result => result?,
+    };
+    let resumed = offset > 0 && response.status() == 206;
+    let length: Option<u64> = response
+        .header("Content-Length")
+        .and_then(|len| len.parse().ok());
+    let (file, done, total) = if resumed {
+        eprintln!("buildkit: resuming download of {url} at {offset} bytes");
+        let file = OpenOptions::new().append(true).open(dest);
+        (file, offset, length.map(|len| offset + len))
+    } else {
+        (File::create(dest), 0, length)
+    };
+    let mut file = file.map_err(ureq::Error::from)?;
+    let mut reader = Progress::new(url, done, total, response.into_reader());
+    io::copy(&mut reader, &mut file).map_err(ureq::Error::from)?;
+    Ok(())
+}
+
+/// Creates the HTTP client, with timeouts so a hung mirror can't stall the build.
+///
+/// `BUILDKIT_HTTP_CONNECT_TIMEOUT` and `BUILDKIT_HTTP_TIMEOUT` set the connect timeout
+/// and the timeout for each read in seconds, both cargo's `http.timeout` or else 30.
+///
+/// The rest of cargo's `[http]` configuration applies too, so that proxies and
+/// custom CAs set up for cargo work for buildkit's downloads.
+fn agent() -> Result<ureq::Agent, Error> {
+    let config = CargoConfig::load()?;
+    let http_timeout = config.integer("http.timeout")?.map(Duration::from_secs);
+    let mut builder = ureq::AgentBuilder::new()
+        .timeout_connect(timeout("BUILDKIT_HTTP_CONNECT_TIMEOUT", http_timeout)?)
+        .timeout_read(timeout("BUILDKIT_HTTP_TIMEOUT", http_timeout)?)
+        // Like cargo, fall back to `HTTPS_PROXY` and friends.
+        .try_proxy_from_env(true);
+    if let Some(proxy) = config
+        .string("http.proxy")?
+        .filter(|proxy| !proxy.is_empty())
+    {
+        let proxy = ureq::Proxy::new(&proxy).map_err(|err| ErrorKind::InvalidCargoConfig {
+            origin: "`http.proxy`".to_owned(),
+            reason: err.to_string(),
+        })?;
+        builder = builder.proxy(proxy);
+    }
+    if config.bool("http.check-revoke")? == Some(true) {
+        warning!(
+            "`http.check-revoke` is not supported for buildkit's downloads \
+             and is ignored"
+        );
+    }
+    if let Some(tls) = tls_config(&config)? {
+        builder = builder.tls_config(tls);
+    }
+    Ok(builder.build())
+}
+
+fn timeout(var: &str, http_timeout: Option<Duration>) -> Result<Duration, Error> {
+    let secs = match env_overrides::var(var)? {
+        Some(secs) => secs,
+        None => return Ok(http_timeout.unwrap_or(Duration::from_secs(30))),
+    };
+    let secs = secs.trim().parse().map_err(|err| {
+        env_overrides::invalid(var, format!("`{secs}` is not a number of seconds: {err}"))
+    })?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// Builds a TLS configuration for `http.cainfo` and `http.ssl-version`,
+/// or returns `None` to keep the defaults.
+fn tls_config(config: &CargoConfig) -> Result<Option<Arc<rustls::ClientConfig>>, Error> {
+    let cainfo = config.path("http.cainfo")?;
+    let versions = ssl_versions(config)?;
+    if cainfo.is_none() && versions.is_none() {
+        return Ok(None);
+    }
+    let invalid = |origin: &str, reason: String| ErrorKind::InvalidCargoConfig {
+        origin: origin.to_owned(),
+        reason,
+    };
+
+    let mut roots = rustls::RootCertStore::empty();
+    match &cainfo {
+        Some(path) => {
+            let origin = format!("`http.cainfo` (`{path}`)");
+            let certs = CertificateDer::pem_file_iter(path)
+                .map_err(|err| invalid(&origin, err.to_string()))?;
+            for cert in certs {
+                let cert = cert.map_err(|err| invalid(&origin, err.to_string()))?;
+                roots
+                    .add(cert)
+                    .map_err(|err| invalid(&origin, err.to_string()))?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    let versions = versions.unwrap_or_else(|| rustls::ALL_VERSIONS.to_vec());
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let tls = rustls::ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&versions)
+        .map_err(|err| invalid("`http.ssl-version`", err.to_string()))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Some(Arc::new(tls)))
+}
+
+/// Gets the TLS versions `http.ssl-version` allows, either a single version
+/// or a `{ min, max }` range.
+fn ssl_versions(
+    config: &CargoConfig,
+) -> Result<Option<Vec<&'static rustls::SupportedProtocolVersion>>, Error> {
+    let (min, max) = match config.value("http.ssl-version") {
+        Some((toml::Value::Table(range), file))
+            if std::env::var_os("CARGO_HTTP_SSL_VERSION").is_none() =>
+        {
+            let bound = |key: &str| match range.get(key) {
+                Some(value) => value
+                    .as_str()
+                    .map(|version| Some(version.to_owned()))
+                    .ok_or_else(|| cargo_config::invalid("http.ssl-version", file, "a string")),
+                None => Ok(None),
+            };
+            (bound("min")?, bound("max")?)
+        }
+        _ => match config.string("http.ssl-version")? {
+            Some(version) => (Some(version.clone()), Some(version)),
+            None => return Ok(None),
+        },
+    };
+
+    let minor = |version: Option<String>, default: u8| -> Result<u8, Error> {
+        Ok(match version.as_deref() {
+            None | Some("default") => default,
+            Some("tlsv1") => 0,
+            Some("tlsv1.1") => 1,
+            Some("tlsv1.2") => 2,
+            Some("tlsv1.3") => 3,
+            Some(other) => {
+                return Err(ErrorKind::InvalidCargoConfig {
+                    origin: "`http.ssl-version`".to_owned(),
+                    reason: format!("unknown TLS version `{other}`"),
+                }
+                .into())
+            }
+        })
+    };
+    let (min, max) = (minor(min, 0)?, minor(max, 3)?);
+    // rustls only speaks TLS 1.2 and 1.3.
+    let versions: Vec<_> = [(2, &rustls::version::TLS12), (3, &rustls::version::TLS13)]
+        .into_iter()
+        .filter(|(minor, _)| (min..=max).contains(minor))
+        .map(|(_, version)| version)
+        .collect();
+    if versions.is_empty() {
+        return Err(ErrorKind::InvalidCargoConfig {
+            origin: "`http.ssl-version`".to_owned(),
+            reason: "only TLS 1.2 and 1.3 are supported".to_owned(),
+        }
+        .into());
+    }
+    Ok(Some(versions))
+}
+
+/// Reports the progress of a download on stderr every few seconds,
+/// which `cargo build -vv` shows as it happens.
+struct Progress<'a, R> {
+    url: &'a str,
+    total: Option<u64>,
+    read: u64,
+    inner: R,
+    last_report: Instant,
+}
+
+impl<'a, R> Progress<'a, R> {
+    const INTERVAL: Duration = Duration::from_secs(5);
+
+    fn new(url: &'a str, read: u64, total: Option<u64>, inner: R) -> Progress<'a, R> {
+        Progress {
+            url,
+            total,
+            read,
+            inner,
+            last_report: Instant::now(),
+        }
+    }
+}
+
+impl<R: Read> Read for Progress<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        if self.last_report.elapsed() >= Self::INTERVAL {
+            self.last_report = Instant::now();
+            let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+            match self.total {
+                Some(total) => eprintln!(
+                    "buildkit: downloading {}: {:.1}/{:.1} MiB",
+                    self.url,
+                    mib(self.read),
+                    mib(total)
+                ),
+                None => eprintln!(
+                    "buildkit: downloading {}: {:.1} MiB",
+                    self.url,
+                    mib(self.read)
+                ),
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Gets how often to retry a failed download, from cargo's `CARGO_NET_RETRY`.
+fn net_retries() -> u32 {
+    println!("cargo:rerun-if-env-changed=CARGO_NET_RETRY");
+    std::env::var("CARGO_NET_RETRY")
+        .ok()
+        .and_then(|retries| retries.parse().ok())
+        .unwrap_or(3)
+}
+
+/// Whether a failed download may succeed when retried,
+/// like after a dropped connection or a server error.
+fn is_transient(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::Status(status, _) => *status >= 500 || *status == 408 || *status == 429,
+        ureq::Error::Transport(transport) => matches!(
+            transport.kind(),
+            ureq::ErrorKind::Dns
+                | ureq::ErrorKind::ConnectionFailed
+                | ureq::ErrorKind::Io
+                | ureq::ErrorKind::ProxyConnect
+        ),
+    }
+}
+
+/// Gets the delay before the `attempt`th retry, counting from 1: exponential, 1, 2, 4 and 8
+/// seconds and then capped at 10, plus up to a second of jitter so parallel builds don't retry
+/// in lockstep.
+fn backoff(attempt: u32) -> Duration {
+    let base =
+        Duration::from_secs(1 << attempt.saturating_sub(1).min(4)).min(Duration::from_secs(10));
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.subsec_nanos());
+    base + Duration::from_millis(u64::from(nanos % 1000))
+}
+
+/// Runs git, returning its trimmed stdout.
+fn git(dir: Option<&Utf8Path>, args: &[&str]) -> Result<String, Error> {
+    let mut cmd = Command::new("git");
+    if cfg!(windows) {
+        // Git for Windows is limited to `MAX_PATH` otherwise.
+        cmd.args(["-c", "core.longpaths=true"]);
+    }
+    if let Some(dir) = dir {
+        cmd.arg("-C").arg(dir);
+    }
+    cmd.args(args);
+    run_command(&mut cmd)
+}
+
+/// Where fetched sources are kept.
+pub(crate) fn cache_dir() -> Result<Utf8PathBuf, Error> {
+    build_dir::work_dir().map(|dir| dir.join("buildkit"))
+}
+
+/// Gets the directory keeping downloads across builds, from `BUILDKIT_CACHE_DIR`
+/// or the workspace's `buildkit.toml`, if any.
+fn downloads_dir(cx: &FetchContext<'_>) -> Result<Option<Utf8PathBuf>, Error> {
+    let dir = match env_overrides::path("BUILDKIT_CACHE_DIR")? {
+        Some(dir) => Some(dir),
+        None => cx.workspace.and_then(WorkspaceConfig::cache_dir),
+    };
+    if let Some(dir) = &dir {
+        hermetic::check_write("keep downloads in", dir)?;
+    }
+    Ok(dir)
+}
+
+/// Gets the name of the directory a source with the cache `key` is unpacked or checked out
+/// into, shortened with `BUILDKIT_SHORT_PATHS=1` for deep trees on Windows.
+fn dir_name(key: &str) -> Result<String, Error> {
+    if env_overrides::flag("BUILDKIT_SHORT_PATHS")? {
+        Ok(hex::encode(Sha256::digest(key))[..12].to_owned())
+    } else {
+        Ok(key.to_owned())
+    }
+}
+
+/// Keys an unverified source by where it comes from.
+fn unverified_key(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!("unverified-{}", &hex::encode(hasher.finalize())[..16])
+}
+
+fn cache_key(hash: &str) -> &str {
+    let hash = hash.trim();
+    hash.get(..16).unwrap_or(hash)
+}
+
+/// The last path segment of a URL, without query or fragment.
+fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
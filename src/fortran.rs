@@ -0,0 +1,48 @@
+//! Linking the Fortran runtime that Fortran libraries depend on.
+
+use std::process::Command;
+
+use camino::Utf8Path;
+use serde::Deserialize;
+
+use crate::{cross, link::LinkDirectives, run_command, Error};
+
+/// A Fortran compiler's runtime library.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum FortranRuntime {
+    /// GCC's `libgfortran`.
+    Gfortran,
+    /// LLVM Flang's `libFortranRuntime` and `libFortranDecimal`.
+    Flang,
+}
+
+impl FortranRuntime {
+    /// Links the runtime, asking the compiler in `FC` where it lives
+    /// since it's often outside the default search path, e.g. in Homebrew's GCC.
+    pub(crate) fn link(self, link: &mut LinkDirectives) -> Result<(), Error> {
+        let (compiler, libs): (&str, &[&str]) = match self {
+            FortranRuntime::Gfortran => ("gfortran", &["gfortran"]),
+            FortranRuntime::Flang => ("flang-new", &["FortranRuntime", "FortranDecimal"]),
+        };
+        let compiler = cross::target_tool(&[], "FC", compiler);
+        let file_name = format!("lib{}.a", libs[0]);
+        // Compilers echo the bare name back when they don't know the file.
+        let located =
+            run_command(Command::new(&compiler).arg(format!("-print-file-name={file_name}")));
+        match located {
+            Ok(path) if path != file_name => {
+                if let Some(dir) = Utf8Path::new(&path).parent() {
+                    link.search_paths.push(format!("native={dir}"));
+                }
+            }
+            _ => warning!(
+                "couldn't locate the {} runtime with `{compiler}`, \
+                 relying on the default library search path",
+                libs[0]
+            ),
+        }
+        link.libs.extend(libs.iter().map(|lib| lib.to_string()));
+        Ok(())
+    }
+}
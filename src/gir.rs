@@ -0,0 +1,57 @@
+//! Locating the GObject introspection data of GNOME-stack libraries.
+//!
+//! Binding generators like gir read a library's `.gir` files and, at run time, its
+//! `.typelib` files. They're installed next to the library, in `$datadir/gir-1.0` and
+//! `$libdir/girepository-1.0`, unless its `.pc` file says otherwise with `girdir` and
+//! `typelibdir`, or else in gobject-introspection's own directories.
+
+use camino::Utf8PathBuf;
+
+use crate::{BuildOutcome, Error, ErrorKind};
+
+/// Finds the introspection directories of the pkg-config module `name`, checks that they
+/// have the files of its `namespaces`, e.g. `Gtk-4.0`, and passes them on as `girdir` and
+/// `typelibdir`.
+pub(crate) fn probe(
+    name: &str,
+    namespaces: &[String],
+    outcome: &mut BuildOutcome,
+) -> Result<(), Error> {
+    let girdir = find_dir(name, "girdir", "datadir", "gir-1.0")?;
+    let typelibdir = find_dir(name, "typelibdir", "libdir", "girepository-1.0")?;
+    for namespace in namespaces {
+        for (dir, extension) in [(&girdir, "gir"), (&typelibdir, "typelib")] {
+            let file = dir.join(format!("{namespace}.{extension}"));
+            if !file.is_file() {
+                return Err(ErrorKind::MissingGir(file).into());
+            }
+        }
+    }
+    for (variable, dir) in [("girdir", girdir), ("typelibdir", typelibdir)] {
+        println!("cargo:{variable}={dir}");
+        outcome
+            .variables
+            .insert(variable.to_owned(), dir.into_string());
+    }
+    Ok(())
+}
+
+/// Gets the module's `variable`, or else `subdir` of its `base` directory if that exists,
+/// or else gobject-introspection's `variable`.
+fn find_dir(name: &str, variable: &str, base: &str, subdir: &str) -> Result<Utf8PathBuf, Error> {
+    let get = |module, variable| {
+        pkg_config::get_variable(module, variable).map_err(ErrorKind::PkgConfigError)
+    };
+    let own = get(name, variable)?;
+    if !own.is_empty() {
+        return Ok(own.into());
+    }
+    let base = get(name, base)?;
+    if !base.is_empty() {
+        let dir = Utf8PathBuf::from(base).join(subdir);
+        if dir.is_dir() {
+            return Ok(dir);
+        }
+    }
+    Ok(get("gobject-introspection-1.0", variable)?.into())
+}
@@ -0,0 +1,65 @@
+//! Guidance crate authors attach to failures.
+//!
+//! Build scripts used to catch buildkit's errors only to add where to get the library, e.g. a
+//! link to the crate's install instructions. The `help` table holds that text instead, keyed
+//! by the kind of error it's for, as in `BUILDKIT_ERROR_JSON`, or `default` for any error, and
+//! optionally by the target OS.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::target_info::TargetInfo;
+use crate::{Error, ErrorKind};
+
+/// The guidance for one kind of error in `package.metadata.buildkit.help`.
+///
+/// ```toml
+/// [package.metadata.buildkit.help]
+/// default = "See https://crate.example/install for platform instructions."
+/// no-provider-found = { windows = "Install it with `vcpkg install foo`.", default = "Install libfoo." }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Help {
+    Text(String),
+    /// By `target_os` or `target_family`, e.g. `macos` or `unix`, or `default`.
+    PerOs(BTreeMap<String, String>),
+}
+
+impl Help {
+    fn text(&self, target: &TargetInfo) -> Option<&str> {
+        match self {
+            Help::Text(text) => Some(text),
+            Help::PerOs(texts) => std::iter::once(target.os())
+                .chain(target.families().iter().map(String::as_str))
+                .chain(["default"])
+                .find_map(|key| texts.get(key))
+                .map(String::as_str),
+        }
+    }
+}
+
+/// Appends the guidance of `help` for the kind of `err` to it, if there's any.
+pub(crate) fn apply(help: &BTreeMap<String, Help>, err: Error) -> Error {
+    if help.is_empty() {
+        return err;
+    }
+    let target = match TargetInfo::from_env() {
+        Ok(target) => target,
+        Err(_) => return err,
+    };
+    let (_, kind) = err.0.code();
+    let text = [kind, "default"]
+        .iter()
+        .filter_map(|key| help.get(*key))
+        .find_map(|help| help.text(&target));
+    match text {
+        Some(text) => ErrorKind::WithHelp {
+            err: Box::new(err.0),
+            help: text.trim().to_owned(),
+        }
+        .into(),
+        None => err,
+    }
+}
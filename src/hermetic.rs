@@ -0,0 +1,55 @@
+//! Building in sandboxes and on remote executors.
+//!
+//! Bazel, Buck2 and remote execution services run build scripts with only the inputs they
+//! declared, and keep only what's written to the declared outputs. A build reaching past
+//! them works on a developer's machine and breaks, or worse, silently differs, in the
+//! sandbox. With `BUILDKIT_HERMETIC=1`, buildkit writes only into `OUT_DIR`, including the
+//! temporary files of vendored builds, and never uses the network, so sources come from
+//! the crate, `BUILDKIT_VENDOR_DIR` or local paths. Whatever would break that fails the
+//! build instead.
+
+use camino::Utf8Path;
+
+use crate::{env_overrides, io_error, path_var, Error, ErrorKind};
+
+/// Whether the build is to stay within `OUT_DIR` and its declared inputs.
+pub(crate) fn enabled() -> Result<bool, Error> {
+    env_overrides::flag("BUILDKIT_HERMETIC")
+}
+
+/// Fails if buildkit is to `action` `path`, outside `OUT_DIR`, in a hermetic build.
+pub(crate) fn check_write(action: &str, path: &Utf8Path) -> Result<(), Error> {
+    if !enabled()? || path.starts_with(path_var("OUT_DIR")?) {
+        return Ok(());
+    }
+    Err(ErrorKind::HermeticViolation {
+        action: format!("{action} `{path}`"),
+        hint: "hermetic builds write only to `OUT_DIR`",
+    }
+    .into())
+}
+
+/// Fails the fetch of the remote `url` in a hermetic build.
+pub(crate) fn check_fetch(url: &str) -> Result<(), Error> {
+    if !enabled()? {
+        return Ok(());
+    }
+    Err(ErrorKind::HermeticViolation {
+        action: format!("fetch `{url}`"),
+        hint: "put it in `BUILDKIT_VENDOR_DIR` or point `BUILDKIT_SOURCE_MIRROR` at a local copy",
+    }
+    .into())
+}
+
+/// Keeps the temporary files of the build tools in `out_dir` in a hermetic build.
+pub(crate) fn configure(out_dir: &Utf8Path, env: &mut Vec<(String, String)>) -> Result<(), Error> {
+    if !enabled()? {
+        return Ok(());
+    }
+    let tmp = out_dir.join("tmp");
+    std::fs::create_dir_all(&tmp).map_err(io_error(&tmp))?;
+    for var in ["TMPDIR", "TMP", "TEMP"] {
+        env.push((var.to_owned(), tmp.to_string()));
+    }
+    Ok(())
+}
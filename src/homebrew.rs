@@ -0,0 +1,63 @@
+//! Finding keg-only Homebrew formulas.
+//!
+//! Homebrew doesn't link some formulas, e.g. openssl, icu4c and libpq, into its prefix,
+//! because macOS ships its own copies or they'd clash with other formulas. Their `.pc` files
+//! are only in `$(brew --prefix <formula>)/lib/pkgconfig`, where pkg-config doesn't look.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::link::LinkDirectives;
+use crate::pc_file::PkgConfigEnv;
+use crate::{env_var, run_command, Error};
+
+/// Searches the `lib/pkgconfig` directories of the installed `formulas` with the probes of
+/// `pkg_env`, after the user's `PKG_CONFIG_PATH`, and returns the formulas' prefixes.
+///
+/// Does nothing unless building natively on macOS with `brew` installed.
+pub(crate) fn add_keg_paths(
+    formulas: &[&str],
+    pkg_env: &mut PkgConfigEnv,
+) -> Result<Vec<PathBuf>, Error> {
+    if !is_native_macos()? {
+        return Ok(Vec::new());
+    }
+    let mut prefixes = Vec::new();
+    for formula in formulas {
+        // `brew --prefix` prints the `opt` link even for formulas that aren't installed.
+        let prefix = match run_command(Command::new("brew").args(["--prefix", formula])) {
+            Ok(prefix) => PathBuf::from(prefix),
+            Err(_) => continue,
+        };
+        if prefix.join("lib").join("pkgconfig").is_dir() {
+            prefixes.push(prefix);
+        }
+    }
+    pkg_env.append_dirs(
+        prefixes
+            .iter()
+            .map(|prefix| prefix.join("lib").join("pkgconfig")),
+    );
+    Ok(prefixes)
+}
+
+/// Whether the build script builds for the macOS it runs on.
+pub(crate) fn is_native_macos() -> Result<bool, Error> {
+    let target = env_var("TARGET")?;
+    Ok(target.contains("-apple-darwin") && env_var("HOST")? == target)
+}
+
+/// Whether the library links from one of the keg `prefixes`.
+pub(crate) fn links_from_keg(prefixes: &[PathBuf], link: &LinkDirectives) -> bool {
+    // The `.pc` files point into the Cellar, while `brew --prefix` gives the `opt` link.
+    let prefixes: Vec<PathBuf> = prefixes
+        .iter()
+        .filter_map(|prefix| prefix.canonicalize().ok())
+        .collect();
+    link.search_paths.iter().any(|path| {
+        let path = path.split_once('=').map_or(path.as_str(), |(_, path)| path);
+        std::fs::canonicalize(path).map_or(false, |path| {
+            prefixes.iter().any(|prefix| path.starts_with(prefix))
+        })
+    })
+}
@@ -0,0 +1,61 @@
+//! Building on illumos distributions, like OpenIndiana and OmniOS, and Solaris.
+//!
+//! 64-bit libraries live in an ISA subdirectory, `/usr/lib/64` (a link to `amd64` or
+//! `sparcv9`), with their `.pc` files in its `pkgconfig`, which not every pkg-config searches.
+//! OmniOS's extra repository installs into `/opt/ooce`. GCC there may still default to 32-bit
+//! code, and the system linker only finds libraries outside the defaults at run time with
+//! a `-R` runpath.
+
+use std::path::PathBuf;
+
+use camino::Utf8PathBuf;
+
+use crate::pc_file::PkgConfigEnv;
+use crate::{append_env_flags, env_var, Error, TargetInfo};
+
+/// Whether the target is illumos or Solaris.
+pub(crate) fn is_target(target: &TargetInfo) -> bool {
+    matches!(target.os(), "illumos" | "solaris")
+}
+
+/// Searches the 64-bit `pkgconfig` directories with the probes of `pkg_env`, after the user's
+/// `PKG_CONFIG_PATH`, when building natively for 64-bit illumos or Solaris.
+pub(crate) fn add_pkg_config_paths(
+    target: &TargetInfo,
+    pkg_env: &mut PkgConfigEnv,
+) -> Result<(), Error> {
+    if !is_target(target) || env_var("HOST")? != target.triple() || target.pointer_width() != 64 {
+        return Ok(());
+    }
+    let mut dirs = vec![PathBuf::from("/usr/lib/64/pkgconfig")];
+    if target.arch() == "x86_64" {
+        dirs.push(PathBuf::from("/opt/ooce/lib/amd64/pkgconfig"));
+    }
+    pkg_env.append_dirs(dirs);
+    Ok(())
+}
+
+/// Adds `-m64` to the compiler flags and `LDFLAGS` of 64-bit builds, and runpaths for the
+/// dependencies' `link_paths` to `LDFLAGS`, so configure checks can run what they link.
+pub(crate) fn configure(
+    target: &TargetInfo,
+    flags: &mut Vec<String>,
+    env: &mut Vec<(String, String)>,
+    link_paths: &[Utf8PathBuf],
+) {
+    if !is_target(target) {
+        return;
+    }
+    let mut ldflags = Vec::new();
+    if target.pointer_width() == 64 {
+        flags.push("-m64".to_owned());
+        ldflags.push("-m64".to_owned());
+    }
+    ldflags.extend(link_paths.iter().map(|path| format!("-Wl,-R{path}")));
+    if !ldflags.is_empty() {
+        append_env_flags(env, "LDFLAGS", ldflags.join(" "));
+    }
+}
+
+/// The directories the 64-bit runtime linker searches by default.
+pub(crate) const SYSTEM_LIB_DIRS: [&str; 2] = ["/lib/64", "/usr/lib/64"];
@@ -0,0 +1,124 @@
+//! Finding shared libraries through the `ldconfig` cache, for libraries without `.pc` files.
+//!
+//! Some libraries never shipped pkg-config support, or a distribution leaves it out. On Linux,
+//! `ldconfig -p` still lists where their shared objects are, so with a header check that their
+//! development files are installed too, they can be linked without a `.pc` file. Nothing says
+//! which version that is or which flags it needs, which is why it's opt-in and warned about.
+
+use std::process::Command;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+
+use crate::link::LinkDirectives;
+use crate::{env_var, run_command, BuildOutcome, Error, ErrorKind, TargetInfo};
+
+/// Where to look when pkg-config doesn't know the library, from the pkg-config requirement.
+///
+/// ```toml
+/// pkg-config = { name = "foo", ldconfig-fallback = { libs = ["foo"], headers = ["foo.h"] } }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct LdconfigFallback {
+    /// Libraries to find and link, without the `lib` prefix or file extension.
+    pub(crate) libs: Vec<String>,
+    /// Headers that have to be installed with them, relative to an include directory.
+    #[serde(default)]
+    pub(crate) headers: Vec<Utf8PathBuf>,
+    /// Where to look for the headers, by default `/usr/include` and `/usr/local/include`.
+    #[serde(default)]
+    include_dirs: Vec<Utf8PathBuf>,
+}
+
+impl LdconfigFallback {
+    /// Finds the libraries in the `ldconfig` cache and their headers,
+    /// or returns `None` unless building natively on Linux.
+    pub(crate) fn probe(&self, target: &TargetInfo) -> Result<Option<BuildOutcome>, Error> {
+        if target.os() != "linux" || env_var("HOST")? != target.triple() {
+            return Ok(None);
+        }
+        let cache = run_command(Command::new("ldconfig").arg("-p"))
+            .or_else(|_| run_command(Command::new("/sbin/ldconfig").arg("-p")))?;
+        let arch = abi_tag(target.arch());
+        let mut link = LinkDirectives::default();
+        for lib in &self.libs {
+            let path = find(&cache, lib, arch)
+                .ok_or_else(|| ErrorKind::NotInLdconfigCache(lib.clone()))?;
+            let file_name = path.file_name().unwrap_or_default();
+            if let Some(dir) = path.parent() {
+                link.search_paths.push(format!("native={dir}"));
+            }
+            if file_name == format!("lib{lib}.so") {
+                link.libs.push(lib.clone());
+            } else {
+                // Only the runtime `libfoo.so.1` is there, without the `libfoo.so` link
+                // that `-lfoo` needs.
+                link.args.push(format!("-l:{file_name}"));
+            }
+        }
+
+        let default_dirs = [
+            Utf8PathBuf::from("/usr/include"),
+            Utf8PathBuf::from("/usr/local/include"),
+        ];
+        let include_dirs = if self.include_dirs.is_empty() {
+            &default_dirs[..]
+        } else {
+            &self.include_dirs[..]
+        };
+        for header in &self.headers {
+            let dir = include_dirs
+                .iter()
+                .find(|dir| dir.join(header).is_file())
+                .ok_or_else(|| ErrorKind::MissingHeader(header.clone()))?;
+            println!("cargo:include={dir}");
+        }
+
+        warning!(
+            "linking {} from the ldconfig cache, as pkg-config doesn't know it; \
+             its version and any flags it needs are unchecked",
+            self.libs.join(", ")
+        );
+        let mut outcome = BuildOutcome::found(None);
+        outcome.link = link;
+        Ok(Some(outcome))
+    }
+}
+
+/// Gets the shared object of `lib` from `ldconfig -p` output, preferring the unversioned
+/// development link.
+fn find(cache: &str, lib: &str, arch: Option<&str>) -> Option<Utf8PathBuf> {
+    let dev_name = format!("lib{lib}.so");
+    let runtime_prefix = format!("lib{lib}.so.");
+    let mut runtime = None;
+    // Lines look like `	libz.so.1 (libc6,x86-64) => /lib/x86_64-linux-gnu/libz.so.1`.
+    for line in cache.lines().skip(1) {
+        let (name_tag, path) = match line.trim().split_once(" => ") {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let (name, tag) = name_tag.split_once(' ').unwrap_or((name_tag, ""));
+        if !arch.map_or(true, |arch| tag.contains(arch)) {
+            continue;
+        }
+        if name == dev_name {
+            return Some(path.into());
+        }
+        if name.starts_with(&runtime_prefix) && runtime.is_none() {
+            runtime = Some(Utf8Path::new(path).to_owned());
+        }
+    }
+    runtime
+}
+
+/// Gets the part of ldconfig's ABI tag that tells 64-bit libraries of an architecture apart,
+/// if there is one.
+fn abi_tag(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86_64" => Some("x86-64"),
+        "aarch64" => Some("AArch64"),
+        "powerpc64" | "riscv64" | "s390x" | "mips64" | "sparc64" => Some("64bit"),
+        _ => None,
+    }
+}
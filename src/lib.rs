@@ -5,22 +5,607 @@
 //! vendored-source = "..."
 //! pkg-config = "..."
 //! vcpkg = "..."
+//! optional = true
+//! too-old = "vendor"
+//! windows-gnu-mode = "vcpkg"
+//! rpath = ["$ORIGIN"]
+//! dev-rpath = true
+//! copy-dlls = true
+//! emscripten-port = "zlib"
+//! filter-system-paths = true
+//! cross-lang-lto = true
+//! required-symbols = ["foo_init", "foo_frobnicate"]
+//! public-symbols = ["foo_*"]
+//! symbol-prefix = "mycrate_"
+//! tree-hash = "sha256-..."
+//! cxx = true
+//! fortran = "gfortran"
+//! dependencies = [{ name = "openssl", version-req = { min = "1.1.1" } }]
+//! tools = [{ name = "cmake", min-version = "3.20" }, { name = "nasm" }]
+//! needs = ["perl", { name = "python3", min-version = "3.8" }]
+//! reproducible = true
+//! remap-paths = true
+//!
+//! [package.metadata.buildkit.features.ssl]
+//! cmake = { USE_OPENSSL = "ON" }
+//! disabled = { cmake = { USE_OPENSSL = "OFF" } }
+//!
+//! [package.metadata.buildkit.generate-pc]
+//! libs = ["foo"]
+//!
+//! [package.metadata.buildkit.vcpkg-config]
+//! root = "../third_party/vcpkg"
+//!
+//! [package.metadata.buildkit.system-packages]
+//! nix = ["zlib"]
+//! debian = ["zlib1g-dev"]
+//! rpm = ["zlib-devel"]
+//! homebrew = ["zlib"]
+//! macports = ["zlib"]
+//! ips = ["library/zlib"]
+//! apk = ["zlib-dev", "zlib-static"]
+//! termux = ["zlib"]
+//!
+//! [[package.metadata.buildkit.providers]]
+//! name = "zlib-ng"
+//! pkg-config = { name = "zlib-ng" }
+//!
+//! [package.metadata.buildkit.blas]
+//! default = "openblas"
+//! lapack = true
+//!
+//! [package.metadata.buildkit.target-modes]
+//! "*-unknown-linux-*" = "pkg-config"
+//! "*-pc-windows-*" = "vcpkg"
+//!
+//! [package.metadata.buildkit.cxx-stdlib]
+//! x86_64-unknown-linux-musl = "c++"
+//!
+//! [package.metadata.buildkit.linkage]
+//! release = "static"
+//! dev = "dylib"
+//! static-feature = "static"
+//! build-both = true
+//!
+//! [package.metadata.buildkit.weak-link]
+//! frameworks = ["UserNotifications"]
+//!
+//! [package.metadata.buildkit.link-args]
+//! 'cfg(all(windows, target_env = "gnu"))' = ["-Wl,--allow-multiple-definition"]
+//!
+//! [package.metadata.buildkit.runtime-compat]
+//! glibc = "2.17"
+//!
+//! [package.metadata.buildkit.help]
+//! default = "See https://crate.example/install for platform instructions."
+//! no-provider-found = { windows = "Install it with vcpkg.", default = "Install libfoo." }
+//!
+//! [[package.metadata.buildkit.link-modifiers]]
+//! lib = "foo_plugins"
+//! modifiers = ["+whole-archive"]
+//! targets = ["*-linux-*"]
 //! ```
+//!
+//! The mode is vcpkg for `*-windows-msvc` targets and pkg-config for the others, unless
+//! `default-mode` is `vendored-build`. `target-modes` maps target triple patterns, with `*` and
+//! `?` wildcards, to the mode instead, taking precedence over both; if several patterns match,
+//! the longest wins.
+//!
+//! `linkage` picks static or shared libraries by a cargo feature, the profile or a default.
+//! Cargo builds each feature set separately, so flipping the feature rebuilds the vendored
+//! library from scratch. With `build-both`, the vendored build produces both kinds at once,
+//! in a directory shared by the builds differing only in the linkage features, and a build
+//! selecting the other kind links the libraries already there. The [`Autotools`] driver
+//! passes `--enable-static --enable-shared`, and [`CMake`] `BUILD_SHARED_LIBS` and
+//! `BUILD_STATIC_LIBS`, which projects building one kind at a time don't all honor.
+//!
+//! A `buildkit.toml` in the workspace building the crate, or at `BUILDKIT_CONFIG`, overrides
+//! the metadata of its `-sys` crates with settings integrators can review: the `mode` of all
+//! crates or, under `[crates.<name>]`, of one, a local `source-path` to build instead of the
+//! vendored source, a `source-mirror`, a `vendor-dir` of pre-fetched sources and a `cache-dir`
+//! keeping downloads across builds. Environment variables still take precedence. Next to it,
+//! `buildkit.lock`, as `cargo buildkit lock` prints it, pins every source the builds fetch
+//! for `BUILDKIT_LOCKED=1`.
+//!
+//! External build systems configure buildkit through `BUILDKIT_*` environment variables,
+//! which rerun the build script when they change. Switches take `1` or `0`; paths must be
+//! absolute.
+//!
+//! | Variable | Effect |
+//! |---|---|
+//! | `BUILDKIT_FORCE_MODE` | The mode of every crate: `pkg-config`, `vcpkg` or `vendored`. |
+//! | `BUILDKIT_<PACKAGE>_MODE` | The mode of one crate, e.g. `BUILDKIT_LIBZ_SYS_MODE`, over the above. |
+//! | `BUILDKIT_OFFLINE` | Fail downloads, like cargo's `net.offline`. |
+//! | `BUILDKIT_DENY_NETWORK` | Fail any fetch of a non-local source, even by a registered [`SourceFetcher`]. |
+//! | `BUILDKIT_HERMETIC` | Write only to `OUT_DIR` and never use the network, for sandboxes. |
+//! | `BUILDKIT_ALLOWED_ROOTS` | Fail on libraries and headers found outside these directories. |
+//! | `BUILDKIT_CACHE_DIR` | Keep downloads there across builds. |
+//! | `BUILDKIT_CONFIG` | The `buildkit.toml` to use instead of the workspace's. |
+//! | `BUILDKIT_VENDOR_DIR` | A directory of pre-fetched sources. |
+//! | `BUILDKIT_SOURCE_MIRROR` | A mirror of the upstream source URLs. |
+//! | `BUILDKIT_ALLOW_UNVERIFIED` | Fetch sources without a hash, for crates that allow it. |
+//! | `BUILDKIT_LOCKED` | Fail on any source missing from `buildkit.lock` or not matching it, e.g. in CI. |
+//! | `BUILDKIT_LOCKFILE` | The `buildkit.lock` to use instead of the workspace's. |
+//! | `BUILDKIT_RECORD_DIGESTS` | Hash every source for `cargo buildkit lock`, which sets it. |
+//! | `BUILDKIT_BOOTSTRAP_TOOLS` | Download the pinned builds of missing or outdated `tools`. |
+//! | `BUILDKIT_BUILD_DIR` | Build there when `OUT_DIR` has characters build tools can't handle. |
+//! | `BUILDKIT_REMAP_PATHS` | Keep build paths out of every vendored library, as `remap-paths` does. |
+//! | `BUILDKIT_REPRODUCIBLE` | Make every vendored build reproducible, as `reproducible` does. |
+//! | `BUILDKIT_SHORT_PATHS` | Shorten the directory names of sources, for Windows' path length limit. |
+//! | `BUILDKIT_HTTP_TIMEOUT`, `BUILDKIT_HTTP_CONNECT_TIMEOUT` | The download timeouts in seconds. |
+//! | `BUILDKIT_<PACKAGE>_PROVIDERS` | The order of a crate's `providers`. |
+//! | `BUILDKIT_ERROR_JSON` | Write a failed build's error code and message to `OUT_DIR/buildkit-error.json`. |
+//! | `BUILDKIT_TIMINGS` | Show how long each phase of the build script took as a warning. |
+//! | `BUILDKIT_CROSS_VENDOR` | Let crates default to their vendored build in cross-rs containers. |
+//!
+//! When `optional` is set and probing fails, the build continues without the library
+//! and `cargo:rustc-cfg=buildkit_missing` is emitted,
+//! so the crate can gate the corresponding code with `#[cfg(not(buildkit_missing))]`.
+//!
+//! With `too-old = "vendor"`, a system library older than the pkg-config version requirement
+//! falls through to the vendored build instead of failing.
+//!
+//! `required-symbols` lists symbols the vendored libraries must define.
+//! They're checked after the vendored build, or after fetching a prebuilt binary,
+//! so a misconfigured build fails with a clear error rather than at link time.
+//!
+//! `public-symbols` localizes every other symbol of the vendored static libraries,
+//! so a bundled copy of a common library doesn't clash with other copies in the final binary.
+//! `symbol-prefix` renames the remaining global symbols instead, so the vendored copy
+//! can coexist with a system copy in one process. Rust bindings then need `#[link_name]`
+//! with the prefixed names; for C code, `DEP_<links>_SYMBOL_PREFIX_HEADER` points to a header
+//! that `#define`s the original names to the prefixed ones.
+//!
+//! With `remap-paths`, the source and out directories of the vendored build become
+//! `/buildkit/src` and `/buildkit/out` in the debug info and `__FILE__` of its libraries, with
+//! `-ffile-prefix-map` (GCC 8 or Clang 10 and later), so user names and CI paths don't ship
+//! with them. It's on by default when rustc is given `--remap-path-prefix`, whose remappings
+//! apply to the C code as well. MSVC has no equivalent.
+//!
+//! With `reproducible`, the vendored build also leaves out what differs between otherwise
+//! identical builds, so its libraries are bit-identical when repeated: `SOURCE_DATE_EPOCH` is
+//! set, to 1980 unless it already is, ELF libraries are linked without build IDs, and the
+//! dates, owners and permissions in static archives are normalized. MSVC builds get
+//! `/Brepro`.
+//!
+//! With `cxx`, the target's C++ standard library is linked once the library is found:
+//! `stdc++` on GNU targets, `c++` on Apple and BSD targets, and nothing on MSVC.
+//! `cxx-stdlib` overrides it per target, with an empty name to link nothing,
+//! and the `CXXSTDLIB` environment variable overrides both.
+//! Likewise, `fortran` links the `gfortran` or `flang` runtime, located through `FC`.
+//!
+//! The `blas` table links a BLAS/LAPACK provider: OpenBLAS, Intel MKL, Accelerate or
+//! Netlib, selected with the cargo feature of the same name (`openblas`, `intel-mkl`,
+//! `accelerate`, `netlib`) as the `blas-src` crate does, or else `default`.
+//!
+//! When `providers` lists alternative implementations of the library, e.g. zlib and
+//! zlib-ng, the first one found is used and reported as `cfg(buildkit_provider = "<name>")`
+//! and through [`BuildOutcome::provider`]. `BUILDKIT_<PACKAGE>_PROVIDERS` reorders them.
+//!
+//! The pkg-config requirement's `variables` lists variables of the `.pc` file to query,
+//! e.g. `plugindir` or `girdir`. Their values are available from [`BuildOutcome::variable`]
+//! and, for crates with `links`, to dependents as `DEP_<links>_PC_<VARIABLE>`. Names are
+//! letters, digits, `_` and `.`, as in `.pc` files.
+//!
+//! For GNOME-stack libraries, the requirement's `gir = ["Gtk-4.0"]` checks that the `.gir`
+//! and `.typelib` files of those introspection namespaces are installed, and passes their
+//! directories on as `DEP_<links>_GIRDIR` and `DEP_<links>_TYPELIBDIR` (and through
+//! [`BuildOutcome::variable`]), so binding generators in dependents don't probe again.
+//! The directories are the `.pc` file's `girdir` and `typelibdir`, or else the library's
+//! `gir-1.0` and `girepository-1.0`, or else gobject-introspection's.
+//!
+//! The requirement's `pkg-config-path` lists directories of `.pc` files in the package, e.g.
+//! a fallback for distributions whose package forgets one. They're searched after
+//! `PKG_CONFIG_PATH`, for this probe only, without changing what other probes or the vendored
+//! build see.
+//!
+//! For libraries that don't ship a `.pc` file everywhere, the requirement's
+//! `ldconfig-fallback = { libs = ["foo"], headers = ["foo.h"] }` links them from the
+//! `ldconfig -p` cache when building natively on Linux and pkg-config doesn't know the module
+//! at all. Their headers have to be installed too, and a warning notes that nothing checked
+//! the version or flags.
+//!
+//! When pkg-config doesn't know the module, a warning tells which of the library's files are
+//! there in the usual directories of a native build: only the versioned runtime library, as
+//! when the distribution's `-dev` or `-devel` package is missing, headers without a library,
+//! a library without headers, or both without a `.pc` file. The `ldconfig-fallback` libraries
+//! and headers are looked for if given, or else ones named after the module.
+//!
+//! `abi-check = { header = "zlib.h", macro = "ZLIB_VERSION" }` on a pkg-config requirement
+//! reads the version the header the compiler finds declares and fails the build unless it
+//! matches pkg-config's, catching headers from one installation used with the library of
+//! another. With `so-version = true`, the version in the shared library's file name is
+//! compared as well, for libraries whose `.so` versions follow their releases.
+//!
+//! The libraries and search paths buildkit links, from pkg-config, vcpkg, prebuilt binaries
+//! and runtimes like the C++ standard library, are collected and emitted once at the end,
+//! canonicalized and without duplicates. [`BuildKit::post_probe`] can adjust them before that.
+//! With `filter-system-paths`, directories the linker searches anyway, such as `/usr/lib`,
+//! are dropped, as pkg-config reports them and an explicit `-L` for them can make the
+//! linker pick a system copy of an unrelated library over the intended one.
+//! `link-modifiers` adds `rustc-link-lib` modifiers to libraries, on the targets matching
+//! `targets` or all of them, e.g. `+whole-archive` for libraries whose objects register
+//! themselves from static constructors that nothing references.
+//!
+//! On Apple targets, the `weak-link` frameworks and libraries are linked with
+//! `-weak_framework` and `-weak-l` instead, so a library using APIs of newer OS versions
+//! still loads on older ones. Like all linker arguments, these apply to the crate's own
+//! binaries, tests and examples, not to those of its dependents.
+//!
+//! `link-args` passes extra linker arguments, under `cfg(...)` expressions evaluated like
+//! rustc does or target triple patterns, for platforms that need one no probe reports.
+//! Each argument is passed as one, so those with whitespace are rejected as likely meant to
+//! be several. Arguments given more than once, also by probes, are passed once.
+//!
+//! With a `runtime-compat` version for glibc or macOS, the shared libraries found are checked
+//! for the newest glibc symbol version or the minimum macOS version they require, which is
+//! passed on to dependents as `DEP_<links>_MIN_GLIBC` or `DEP_<links>_MIN_MACOS`, with a
+//! warning if it's newer than declared: binaries linking them won't load on older systems.
+//!
+//! A `version-req` on the vcpkg requirement, like pkg-config's, is checked against the
+//! port version in vcpkg's status database, since vcpkg-rs ignores versions.
+//! The port's `copyright` and `usage` files are passed on to dependents as
+//! `DEP_<links>_COPYRIGHT` and `DEP_<links>_USAGE`, for packaging and attribution.
+//!
+//! Either requirement's `exclude` rejects versions its `version-req` accepts, like releases
+//! with known ABI bugs: `exclude = ["1.1.0", ">=2.0.0-beta, <2.0.0"]` lists versions, or
+//! comparators that all have to hold. Finding an excluded version fails the probe, and with
+//! `providers`, moves on to the next one.
+//!
+//! A `version-req` of `{ any = ["1.2.x", ">=2.1"] }` accepts versions in any of several
+//! ranges, written like `exclude`'s, with `1.2.x` or `1.2.*` for the releases starting with
+//! `1.2`. pkg-config can't take such a requirement, so the version is checked after probing.
+//!
+//! `vcpkg-config` points vcpkg at a checked-in instance with `root`, or at a manifest-mode
+//! tree with `installed-dir`; `VCPKG_ROOT` and `VCPKG_INSTALLED_DIR` override them.
+//! Its `overlay-ports` and `overlay-triplets`, or `VCPKG_OVERLAY_PORTS` and
+//! `VCPKG_OVERLAY_TRIPLETS`, go into the `vcpkg install` commands buildkit suggests.
+//!
+//! On MSVC targets, vendored builds get `/MT` or `/MD` to match `+crt-static`,
+//! and vcpkg triplets linking the other CRT are rejected up front rather than failing
+//! the final link with LNK2038.
+//!
+//! Windows programs find DLLs only next to themselves or on `PATH`. With `copy-dlls`, the DLLs
+//! of the libraries linked dynamically, and the DLLs those import from the same installation,
+//! are copied into the target directory where `cargo run`, `cargo test` and examples start
+//! from.
+//!
+//! `backend` selects a custom [`ProbeBackend`] registered with [`BuildKit::backend`]
+//! in place of pkg-config and vcpkg, configured by the `backend-config` table.
+//!
+//! Vendored sources must be pinned by a hash. To develop against an unreleased snapshot,
+//! `allow-unverified = true` lets sources without a hash through, but only when
+//! `BUILDKIT_ALLOW_UNVERIFIED=1` is also set, and never quietly.
+//!
+//! `generate-pc` installs a pkg-config file for a vendored build, unless the build installed
+//! one itself, and passes its directory on as `DEP_<links>_PKG_CONFIG_PATH`. buildkit adds
+//! these directories from dependencies to `PKG_CONFIG_PATH`, both for probing and for
+//! vendored builds, so other libraries find the vendored copy.
+//!
+//! The `features` table maps cargo features to `configure` arguments, `cmake` cache entries
+//! and `cc` definitions, which the drivers and `VendoredBuildContext::cc_build` apply;
+//! its `disabled` table holds those for when the feature is off. A feature's `pkg-config` list
+//! names extra modules to probe along with the library when it's on, e.g. `wayland-client`
+//! for a `wayland` feature, which are linked the same way.
+//!
+//! The vcpkg requirement's `features` table maps cargo features to port features, e.g.
+//! `features = { ssl = "openssl" }`. When such a cargo feature is enabled, the port must have
+//! been installed with its port feature, which vcpkg's status database records; otherwise
+//! probing fails with the `vcpkg install` command that adds it.
+//!
+//! `tree-hash` pins the unpacked source tree rather than the archive, the way
+//! `nix hash path` hashes it. It verifies a tarball, a git checkout or a directory alike,
+//! and can stand in for an archive's own hash.
+//!
+//! `dependencies` lists system libraries the vendored build links against, e.g. a vendored
+//! libcurl using the system OpenSSL, as pkg-config requirements. They're probed before the
+//! build, linked, and their paths passed to it, in `CPPFLAGS` and `LDFLAGS` for autotools and
+//! as `CMAKE_INCLUDE_PATH` and `CMAKE_LIBRARY_PATH` for CMake. A dependency can instead be
+//! another `-sys` crate's library, as `{ links = "z" }`: its paths come from the
+//! `DEP_Z_INCLUDE` and `DEP_Z_ROOT` of the crate with that `links`, e.g. libz-sys, which also
+//! links it, rather than from the system or a second vendored copy. Its `libs` are linked
+//! too, for crates that only build them.
+//!
+//! The whole vendored library can come from another crate the same way, with
+//! `vendored-source.sys-crate = { links = "ssh2" }`: nothing is fetched or built, the crate's
+//! library directories are searched, and its `include` and `root` are passed on as this
+//! package's, e.g. for bindings split from the crate that builds the library.
+//!
+//! `tools` lists the executables the vendored build runs, checked on `PATH` before fetching
+//! or building anything, with the version `<tool> --version` prints checked against
+//! `min-version`. A missing or outdated tool fails the build with its name up front, rather
+//! than as a "command not found" deep in the build output. A tool's `bootstrap` archive,
+//! pinned by `hash` or by `hashes` keyed by the host triple, is downloaded into the cache
+//! instead when the tool is missing or too old and `BUILDKIT_BOOTSTRAP_TOOLS=1` allows it,
+//! checked against `min-version` too, and put first on the build's `PATH`.
+//!
+//! `needs` lists the interpreters the build's scripts run, like perl for OpenSSL's `Configure`
+//! or python3 for Meson, as names or with a `min-version`. They're checked the same way, and
+//! a missing or outdated one comes with a hint how to install it on the host, e.g. Strawberry
+//! Perl on Windows. `python3` is also found as `python`, as long as it's Python 3.
+//!
+//! With an `assembler` section, vendored builds for x86 targets look for nasm or yasm (or its
+//! `assemblers`), on the `target-os` listed or all. Without one, its `no-asm` options, e.g.
+//! `{ configure = ["no-asm"] }`, select the portable C code instead of failing, and the others
+//! apply otherwise. `cfg(buildkit_asm)` is set to the assembler found or `"none"`.
+//!
+//! When the crate itself comes from `cargo vendor` or the registry, a `crate-path` source is
+//! copied to `OUT_DIR` before building, as cargo checksums those trees. With cargo's
+//! `net.offline` set, remote sources that aren't cached fail up front instead of hanging.
+//! `BUILDKIT_VENDOR_DIR` provides them instead, with a directory per crate holding each
+//! source archive, or its unpacked tree, under the file name of its URL.
+//!
+//! In the sandboxes of Bazel, Buck2 and remote executors, `BUILDKIT_HERMETIC=1` keeps builds
+//! to their declared inputs and outputs: sources must be local, `BUILDKIT_VENDOR_DIR` or
+//! the crate's, vendored builds keep their temporary files and work in `OUT_DIR`, and a
+//! download, a `BUILDKIT_CACHE_DIR` or a `BUILDKIT_BUILD_DIR` outside it fails the build.
+//! DLLs aren't copied next to binaries, nor builds of both library kinds shared.
+//!
+//! To catch libraries of the build machine or container leaking into a build,
+//! `BUILDKIT_ALLOWED_ROOTS` lists the only directories, e.g. the sysroot, probes may find
+//! libraries and headers in. A `.pc` file, search path, library file or include directory
+//! outside them, or outside cargo's build directories and the crate's own, fails the build.
+//!
+//! On Windows, archives are unpacked through extended-length `\\?\` paths and git checks out
+//! with `core.longpaths`, so deep source trees don't fail past `MAX_PATH`.
+//! `BUILDKIT_SHORT_PATHS=1` also shortens the hashed directory names they go into, for build
+//! tools that can't handle long paths themselves.
+//!
+//! When `OUT_DIR` has spaces, non-ASCII or other characters make and configure scripts
+//! trip over, e.g. from a Windows user name, `BUILDKIT_BUILD_DIR` names a directory to
+//! build in instead, under a hash of `OUT_DIR`, with local sources copied there too.
+//! Without it, the [`Autotools`] and [`CMake`] drivers fail before running anything,
+//! naming the offending directory. Paths that aren't valid UTF-8, in `OUT_DIR`, the package's
+//! directory or from pkg-config, vcpkg and other crates, fail with an error showing them
+//! rather than being dropped or mangled on their way to the build tools.
+//!
+//! For packagers, `cargo buildkit nix` prints the pkg-config modules and sources of every
+//! crate in the dependency graph as JSON (see [`Requirements`]), ready to be turned into
+//! `buildInputs` and fixed-output derivations. `cargo buildkit debian` and
+//! `cargo buildkit rpm` print `Build-Depends` and `BuildRequires` for distro packages.
+//! `system-packages` names the library's packages for such tools where they can't be derived
+//! from pkg-config, e.g. its Nix attributes under `nix`, or under `debian` and `rpm`.
+//!
+//! Each build passes on how it and the crates it depends on resolved their libraries, vendored
+//! or from the system, as `DEP_<links>_RESOLUTIONS`, and warns when one library is resolved
+//! both ways, or vendored twice, which tends to end in duplicate symbols or ABI mismatches.
+//! `cargo buildkit conflicts` reports the same from the build outputs in the target directory
+//! (see [`Resolutions`]).
+//!
+//! Programs linked against shared libraries outside the system's directories need those on
+//! the loader's search path to run. Each build passes on the directories of its shared
+//! libraries and those of its dependencies, separated by `;`, as `DEP_<links>_RUNTIME_PATH`,
+//! and the variable they belong in, `PATH`, `LD_LIBRARY_PATH` or `DYLD_FALLBACK_LIBRARY_PATH`,
+//! as `DEP_<links>_RUNTIME_PATH_VAR`. `cargo buildkit runtime-env` prints them for a whole
+//! build as JSON (see [`RuntimeEnv`]).
+//!
+//! The time spent parsing metadata, fetching and verifying sources, probing and building the
+//! vendored source is measured per phase and passed on as `DEP_<links>_TIMINGS`, shown as a
+//! warning with `BUILDKIT_TIMINGS=1`. `cargo buildkit timings` prints it for a whole build
+//! as JSON (see [`Timings`]).
+//!
+//! On macOS, the `lib/pkgconfig` directories of keg-only Homebrew formulas like openssl,
+//! icu4c and libpq, which Homebrew doesn't link into its prefix, are added to
+//! `PKG_CONFIG_PATH` before probing. The formulas are those under `system-packages.homebrew`,
+//! or else the pkg-config module's name. When the library is linked from one of them,
+//! `cfg(buildkit_homebrew_keg)` is set. MacPorts' `/opt/local/lib/pkgconfig` is tried if
+//! the library isn't found otherwise. If it isn't found at all, a warning suggests installing
+//! the formulas or the ports under `system-packages.macports`, named like the module by default.
+//!
+//! On illumos and Solaris, 64-bit `.pc` files in `/usr/lib/64/pkgconfig`, and on OmniOS
+//! in `/opt/ooce`, are found too, and the warning suggests the IPS packages under
+//! `system-packages.ips`. Vendored builds there get `-m64` and `-R` runpaths for the
+//! `dependencies`, as GCC may default to 32-bit code and the system linker needs them.
+//!
+//! Targets with a statically linked musl, like Rust's `*-linux-musl` by default, can't load
+//! shared libraries, so libraries are linked statically there unless `linkage` says otherwise.
+//! On Alpine the warning suggests `apk add` of `system-packages.apk`, or else the module's
+//! `-dev` package, and prebuilt binaries built against glibc are warned about on musl targets.
+//!
+//! In the containers of cross-rs, recognized by its `CROSS_*` variables, pkg-config probes
+//! use the image's `<CROSS_TOOLCHAIN_PREFIX>pkg-config` wrapper if it has one, and are allowed
+//! to cross compile, as the image's `.pc` files are the target's. vcpkg, which the images
+//! don't have, is never picked by default, and crates defaulting to their vendored build
+//! probe the image's libraries instead if they have a pkg-config requirement, also when a
+//! library is too old, unless `BUILDKIT_CROSS_VENDOR=1` allows vendoring.
+//!
+//! In a Yocto/OpenEmbedded SDK, recognized by `OECORE_TARGET_SYSROOT`, pkg-config searches
+//! only the target sysroot's `.pc` files, with `PKG_CONFIG_SYSROOT_DIR` defaulting to it, and
+//! a library found outside the sysroot fails the build rather than linking the build
+//! machine's copy; the `ldconfig-fallback` isn't tried. Vendored builds keep the SDK's `CC`
+//! and flags, configure for the host in its `TARGET_PREFIX`, and use its CMake toolchain file.
+//! Buildroot package builds are handled the same, with `STAGING_DIR` as the sysroot, the host
+//! named by the toolchain's `CC` and Buildroot's `toolchainfile.cmake`.
+//!
+//! When targeting Emscripten, a library with an `emscripten-port` is linked as that port, e.g.
+//! `-sUSE_ZLIB=1` for `zlib`, or with the setting given like `USE_SDL=2`, and `emcc` builds
+//! it; the port is preferred to a vendored build by default. Otherwise pkg-config searches
+//! only the `.pc` files in the sysroot of Emscripten's cache, under `EM_CACHE` or the emsdk
+//! in `EMSDK`, and the `ldconfig-fallback` isn't tried.
+//!
+//! In Termux on Android, libraries are probed with Termux's own pkg-config and `.pc` files
+//! under its `$PREFIX`, and the warning suggests `pkg install` of `system-packages.termux`.
+//!
+//! With the `tracing` feature, build orchestration collecting telemetry gets a span for the
+//! build script and each phase of it, for fetching, extracting and verifying each source,
+//! for the configure, build and install steps of the drivers and for the `buildkit.lock`
+//! check, and events for the mode, probes, downloads, cache hits and misses, lockfile
+//! decisions, commands and the outcome, under the `buildkit` target. They go nowhere unless the build script
+//! installs a subscriber.
+//!
+//! The `help` table appends the crate's own guidance, like a link to its install instructions,
+//! to the errors [`BuildKit::build`] fails with, so build scripts don't wrap them for that.
+//! Its keys are the kinds of errors, as `BUILDKIT_ERROR_JSON` names them, e.g.
+//! `no-provider-found` or `hash-mismatch`, and `default` for the others. The text can differ
+//! by target: a table keyed by `target_os` or `target_family`, e.g. `macos` or `unix`,
+//! takes its `default` for the rest.
+
+/// Prints a warning like `format!`, a `cargo:warning` per line of it.
+macro_rules! warning {
+    ($($arg:tt)+) => {
+        $crate::diagnostics::warn(&format!($($arg)+))
+    };
+}
 
-use camino::Utf8PathBuf;
+/// Emits a `tracing` event under the `buildkit` target with the `tracing` feature, e.g.
+/// `event!(info, url, "downloading")`, and nothing without it.
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!(target: "buildkit", $($arg)+);
+    };
+}
+
+/// Enters a `tracing` span under the `buildkit` target until the end of the block with the
+/// `tracing` feature, e.g. `span!("extract", %archive)`, and does nothing without it.
+macro_rules! span {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(target: "buildkit", $($arg)+).entered();
+    };
+}
+
+mod abi_check;
+mod allowed_roots;
+mod assembler;
+mod backend;
+mod blas;
+mod build_dir;
+mod cargo_config;
+mod cflags;
+#[cfg(feature = "cloud-storage")]
+mod cloud_storage;
+mod conflicts;
+mod cross;
+mod cross_rs;
+mod cxx;
+mod debian;
+mod dev_files;
+mod diagnostics;
+mod dll_copy;
+mod driver;
+mod emscripten;
+mod env_overrides;
+mod error_code;
+mod extract;
+mod features;
+mod fetch;
+mod fortran;
+mod gir;
+mod help;
+mod hermetic;
+mod homebrew;
+mod illumos;
+mod ldconfig;
+mod link;
+mod link_args;
+mod link_modifiers;
+mod linkage;
+mod localize;
+mod lockfile;
+mod lto;
+mod macports;
+mod musl;
+mod path_remap;
+mod pc_file;
+mod provider;
+mod reproducible;
+mod requirements;
+mod rpath;
+mod runtime_compat;
+mod runtime_env;
+mod rustflags;
+mod sanitizer;
+mod source;
+mod symbols;
+mod sys_crate;
+mod sysroot;
+mod target_cfg;
+mod target_info;
+mod target_modes;
+mod termux;
+mod timings;
+mod tools;
+mod vcpkg_port;
+mod verify;
+mod version;
+mod version_range;
+mod weak_link;
+mod workspace_config;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use camino::{Utf8Path, Utf8PathBuf};
 use cargo_metadata::MetadataCommand;
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+pub use backend::{ProbeBackend, ProbeRequest};
+pub use conflicts::Resolutions;
+pub use driver::{Autotools, CMake};
+pub use fetch::SourceFetcher;
+pub use linkage::LinkKind;
+pub use lockfile::Lockfile;
+pub use requirements::Requirements;
+pub use runtime_env::RuntimeEnv;
+pub use target_info::TargetInfo;
+pub use timings::Timings;
+pub use version::Version;
+
+use abi_check::AbiCheck;
+use assembler::Assembler;
+use blas::Blas;
+use features::{BuildOptions, FeatureOptions};
+use fetch::FetchContext;
+use fortran::FortranRuntime;
+use help::Help;
+use ldconfig::LdconfigFallback;
+use link::LinkDirectives;
+use link_modifiers::LinkModifiers;
+use linkage::Linkage;
+use pc_file::{PcFile, PkgConfigEnv};
+use provider::Provider;
+use runtime_compat::RuntimeCompat;
+use source::VendoredSource;
+use sys_crate::SysCrate;
+use sysroot::Sysroot;
+use tools::{Prerequisite, ToolRequirement};
+use vcpkg_port::VcpkgConfig;
+use verify::Digests;
+use version_range::VersionRange;
+use weak_link::WeakLink;
+use workspace_config::WorkspaceConfig;
 
 /// This will be the builder pattern thing that people interact with in their build.rs
 pub struct BuildKit {
     metadata: BuildKitMetadata,
+    backends: Vec<Box<dyn ProbeBackend>>,
+    fetchers: Vec<Box<dyn SourceFetcher>>,
+    post_probe: Option<PostProbeHook>,
+    /// The workspace's `buildkit.toml`, whose settings take precedence over the metadata.
+    workspace: Option<WorkspaceConfig>,
+    target: TargetInfo,
 }
 
+/// A hook adjusting a found library's link directives, see [`BuildKit::post_probe`].
+type PostProbeHook = Box<dyn Fn(&mut BuildOutcome)>;
+
 impl BuildKit {
     /// Collects information from the `package.metadata.buildkit`
     /// section of the Cargo.toml file for the package being built.
     pub fn from_metadata() -> Result<Self, Error> {
-        let manifest_dir = env_var("CARGO_MANIFEST_DIR").map(Utf8PathBuf::from)?;
+        let result = timings::time("metadata", BuildKit::read_metadata);
+        if let Err(err) = &result {
+            error_code::write_json(err);
+        }
+        result
+    }
+
+    fn read_metadata() -> Result<Self, Error> {
+        let manifest_dir = path_var("CARGO_MANIFEST_DIR")?;
         let manifest_path = manifest_dir.join("Cargo.toml");
         let metadata = MetadataCommand::new()
             .manifest_path(&manifest_path)
@@ -45,27 +630,104 @@ impl BuildKit {
                 ErrorKind::InvalidCargoMetadata(format!("metadata.buildkit for {name}@{version}"))
             })?
             .clone();
-        let metadata = serde_json::from_value(value).map_err(ErrorKind::Json)?;
-        Ok(BuildKit { metadata })
+        let digest = hex::encode(Sha256::digest(value.to_string()));
+        let mut metadata: BuildKitMetadata =
+            serde_json::from_value(value).map_err(ErrorKind::Json)?;
+        metadata.digest = digest;
+        let workspace = WorkspaceConfig::load()?;
+        if let Some(path) = workspace.as_ref().and_then(|w| w.source_path(&name)) {
+            // The integrator vouches for their checkout, which the tree hash can't match.
+            // It's theirs to edit, so edits rebuild it.
+            println!("cargo:rerun-if-changed={path}");
+            metadata.vendored_source = Some(VendoredSource::SystemPath { path });
+            metadata.tree_hash = None;
+        }
+        Ok(BuildKit {
+            metadata,
+            backends: Vec::new(),
+            fetchers: Vec::new(),
+            post_probe: None,
+            workspace,
+            target: TargetInfo::from_env()?,
+        })
+    }
+
+    /// Registers a custom probe backend, which `package.metadata.buildkit.backend`
+    /// can then select in place of pkg-config and vcpkg.
+    pub fn backend(mut self, backend: impl ProbeBackend + 'static) -> Self {
+        self.backends.push(Box::new(backend));
+        self
+    }
+
+    /// Registers a custom source fetcher for the URLs it [handles](SourceFetcher::handles),
+    /// taking precedence over the built-in download and git checkout.
+    pub fn fetcher(mut self, fetcher: impl SourceFetcher + 'static) -> Self {
+        self.fetchers.push(Box::new(fetcher));
+        self
+    }
+
+    /// Sets a hook that runs once the library is found, before its link directives are emitted.
+    ///
+    /// It can drop, reorder or add the libraries and search paths buildkit collected, through [`BuildOutcome::link_libs_mut`] and
+    /// [`BuildOutcome::link_search_paths_mut`], for platforms where they're wrong.
+    pub fn post_probe(mut self, hook: impl Fn(&mut BuildOutcome) + 'static) -> Self {
+        self.post_probe = Some(Box::new(hook));
+        self
     }
 
     /// Builds the library.
     ///
     /// The `try_vendor` closure is for building from vendoered source
     /// if the `package.metadata.buildkit.vendored-source` section is specified.
-    pub fn build<F>(&self, try_vendor: F) -> Result<(), Error>
+    pub fn build<F>(&self, try_vendor: F) -> Result<BuildOutcome, Error>
     where
         F: Fn(VendoredBuildContext) -> Result<(), Error>,
     {
-        match self.mode()? {
+        let result = self
+            .resolve(try_vendor)
+            .map_err(|err| help::apply(&self.metadata.help, err));
+        if let Err(err) = &result {
+            error_code::write_json(err);
+        }
+        result
+    }
+
+    fn resolve<F>(&self, try_vendor: F) -> Result<BuildOutcome, Error>
+    where
+        F: Fn(VendoredBuildContext) -> Result<(), Error>,
+    {
+        let target = &self.target;
+        let link_kind = match self.metadata.linkage.resolve()? {
+            Some(link_kind) => Some(link_kind),
+            None => musl::default_link_kind(target),
+        };
+        let port_link = emscripten::port_link(self.metadata.emscripten_port.as_deref(), target);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            target: "buildkit",
+            "build_script",
+            package = %env_var("CARGO_PKG_NAME")?
+        )
+        .entered();
+        let mode = self.mode()?;
+        event!(info, ?mode, "resolving the library");
+        let mut outcome = timings::time("probe", || match mode {
             BuildKitMode::VendoredBuild => {
-                let vendored_source = self
-                    .metadata
-                    .vendored_source
-                    .as_ref()
-                    .ok_or_else(|| ErrorKind::NoVendoredSourceSpecified)?;
-                let ctx = VendoredBuildContext::new(vendored_source);
-                try_vendor(ctx)
+                timings::time("build", || self.vendor(&try_vendor, link_kind))
+            }
+            BuildKitMode::PkgConfig if port_link.is_some() => {
+                let mut outcome = BuildOutcome::found(None);
+                outcome.link = port_link.unwrap_or_default();
+                Ok(outcome)
+            }
+            BuildKitMode::PkgConfig | BuildKitMode::Vcpkg if self.metadata.backend.is_some() => {
+                self.probed(self.try_backend(link_kind))
+            }
+            BuildKitMode::PkgConfig if !self.metadata.providers.is_empty() => {
+                self.probed(self.try_providers(|provider| {
+                    let req = provider.pkg_config.as_ref()?;
+                    Some(self.try_pkg_config(req, link_kind))
+                }))
             }
             BuildKitMode::PkgConfig => {
                 let req = self
@@ -73,7 +735,36 @@ impl BuildKit {
                     .pkg_config
                     .as_ref()
                     .ok_or_else(|| ErrorKind::NoPkgConfigRequirementSpecified)?;
-                try_pkg_config(req)
+                let vendor_too_old = matches!(self.metadata.too_old, TooOldPolicy::Vendor)
+                    && !self.prefers_cross_rs_libraries()?;
+                match self.try_pkg_config(req, link_kind) {
+                    Err(err) if vendor_too_old => {
+                        let pkg_env = self.pkg_config_platform(req)?.env;
+                        match outdated_version(req, &pkg_env) {
+                            Some((found, required)) => {
+                                warning!(
+                                    "system {} {found} is older than the required {required}, \
+                                     building the vendored copy instead",
+                                    req.name
+                                );
+                                timings::time("build", || self.vendor(&try_vendor, link_kind))
+                            }
+                            None => self.probed(Err(err)),
+                        }
+                    }
+                    result => self.probed(result),
+                }
+            }
+            BuildKitMode::Vcpkg if !self.metadata.providers.is_empty() => {
+                self.probed(self.try_providers(|provider| {
+                    let req = provider.vcpkg.as_ref()?;
+                    Some(try_vcpkg(
+                        target,
+                        req,
+                        &self.metadata.vcpkg_config,
+                        link_kind,
+                    ))
+                }))
             }
             BuildKitMode::Vcpkg => {
                 let req = self
@@ -81,43 +772,560 @@ impl BuildKit {
                     .vcpkg
                     .as_ref()
                     .ok_or_else(|| ErrorKind::NoVcpkgRequirementSpecified)?;
-                try_vcpkg(req)
+                self.probed(try_vcpkg(
+                    target,
+                    req,
+                    &self.metadata.vcpkg_config,
+                    link_kind,
+                ))
+            }
+        })?;
+        if outcome.is_found() {
+            if self.metadata.cxx {
+                cxx::link_stdlib(target, &self.metadata.cxx_stdlib, &mut outcome.link);
+            }
+            if let Some(runtime) = self.metadata.fortran {
+                runtime.link(&mut outcome.link)?;
+            }
+            if let Some(blas) = &self.metadata.blas {
+                blas.link(target, link_kind, &mut outcome.link)?;
+            }
+            if self.metadata.filter_system_paths {
+                outcome.link.filter_system_paths(target);
+            }
+            link_modifiers::apply(&self.metadata.link_modifiers, target, &mut outcome.link)?;
+            self.metadata.weak_link.apply(target, &mut outcome.link);
+            link_args::apply(&self.metadata.link_args, target, &mut outcome.link)?;
+            if let Some(hook) = &self.post_probe {
+                hook(&mut outcome);
+            }
+            allowed_roots::check_link(&self.library_name(&outcome)?, &outcome.link)?;
+            self.metadata.runtime_compat.check(target, &outcome.link)?;
+            // Hermetic builds keep nothing outside `OUT_DIR`; the build system provides
+            // the runtime files.
+            if self.metadata.copy_dlls && target.os() == "windows" && !hermetic::enabled()? {
+                dll_copy::copy(&outcome.link)?;
             }
+            outcome.link.emit();
+            runtime_env::report(target, &outcome.link)?;
+            // A library taken from another crate is that crate's resolution.
+            let reused = outcome.vendored
+                && matches!(
+                    self.metadata.vendored_source,
+                    Some(VendoredSource::SysCrate(_))
+                );
+            let library = self.library_name(&outcome)?;
+            event!(
+                info,
+                %library,
+                vendored = outcome.vendored,
+                version = ?outcome.version,
+                provider = ?outcome.provider,
+                "found the library"
+            );
+            conflicts::report((!reused).then(|| (library.as_str(), outcome.vendored)))?;
         }
+        timings::report()?;
+        Ok(outcome)
+    }
+
+    /// Probes the library with pkg-config, then the extra modules of enabled cargo features.
+    fn try_pkg_config(
+        &self,
+        req: &PkgConfigRequirement,
+        link_kind: Option<LinkKind>,
+    ) -> Result<BuildOutcome, Error> {
+        let PkgConfigPlatform {
+            env: mut pkg_env,
+            sysroot,
+            emscripten,
+            kegs,
+        } = self.pkg_config_platform(req)?;
+        println!("cargo:rustc-check-cfg=cfg(buildkit_homebrew_keg)");
+        let mut outcome = match try_pkg_config(&self.target, req, link_kind, &pkg_env) {
+            Err(_) if macports::add_pkg_config_path(&mut pkg_env)? => {
+                try_pkg_config(&self.target, req, link_kind, &pkg_env)
+            }
+            result => result,
+        }
+        .or_else(|err| match &req.ldconfig_fallback {
+            // A `.pc` file that doesn't match the requirement isn't overridden, and the
+            // build machine's libraries aren't the sysroot's or Emscripten's.
+            Some(fallback)
+                if !pkg_config_knows(req, &pkg_env) && sysroot.is_none() && !emscripten =>
+            {
+                match fallback.probe(&self.target) {
+                    Ok(Some(outcome)) => Ok(outcome),
+                    Ok(None) => Err(err),
+                    Err(fallback_err) => {
+                        warning!("ldconfig fallback failed: {fallback_err}");
+                        Err(err)
+                    }
+                }
+            }
+            _ => Err(err),
+        })
+        .map_err(|err| {
+            if !pkg_config_knows(req, &pkg_env) {
+                warn_partial_install(&self.target, req);
+            }
+            self.warn_install_hint(req);
+            err
+        })?;
+        if homebrew::links_from_keg(&kegs, &outcome.link) {
+            println!("cargo:rustc-cfg=buildkit_homebrew_keg");
+        }
+        for extra in features::pkg_config_requirements(&self.metadata.features) {
+            homebrew::add_keg_paths(&[extra.name.as_str()], &mut pkg_env)?;
+            outcome
+                .link
+                .extend(try_pkg_config(&self.target, extra, link_kind, &pkg_env)?.link);
+        }
+        if let Some(sysroot) = &sysroot {
+            sysroot.check(&req.name, &outcome.link)?;
+        }
+        Ok(outcome)
+    }
+
+    /// Gathers how pkg-config probes for `req` on the platform the build runs on and for.
+    fn pkg_config_platform(&self, req: &PkgConfigRequirement) -> Result<PkgConfigPlatform, Error> {
+        let mut env = PkgConfigEnv::default();
+        let kegs = homebrew::add_keg_paths(&self.system_packages("homebrew", req), &mut env)?;
+        illumos::add_pkg_config_paths(&self.target, &mut env)?;
+        termux::configure_pkg_config(&self.target, &mut env)?;
+        cross_rs::configure_pkg_config(&mut env);
+        let sysroot = Sysroot::detect()?;
+        if let Some(sysroot) = &sysroot {
+            sysroot.configure_pkg_config(&mut env);
+        }
+        let emscripten = emscripten::configure_pkg_config(&self.target, &mut env);
+        Ok(PkgConfigPlatform {
+            env,
+            sysroot,
+            emscripten,
+            kegs,
+        })
+    }
+
+    /// Suggests how to install the library with the package manager of the build machine.
+    fn warn_install_hint(&self, req: &PkgConfigRequirement) {
+        let packages = |manager| self.system_packages(manager, req).join(" ");
+        let command = if let Ok(true) = homebrew::is_native_macos() {
+            format!(
+                "`brew install {}` or `sudo port install {}`",
+                packages("homebrew"),
+                packages("macports")
+            )
+        } else if illumos::is_target(&self.target) {
+            format!("`pfexec pkg install {}`", packages("ips"))
+        } else if let Ok(Some(_)) = termux::prefix(&self.target) {
+            format!("`pkg install {}`", packages("termux"))
+        } else if let Ok(true) = musl::is_native_alpine(&self.target) {
+            match self.metadata.system_packages.get("apk") {
+                Some(packages) => format!("`apk add {}`", packages.join(" ")),
+                None => format!("`apk add {}-dev`", req.name),
+            }
+        } else {
+            return;
+        };
+        warning!("install {} with {command}", req.name);
+    }
+
+    /// Gets the name other crates' buildkit metadata likely knows the library by:
+    /// its pkg-config or vcpkg name, the chosen provider's, or else the package's.
+    fn library_name(&self, outcome: &BuildOutcome) -> Result<String, Error> {
+        let pkg_config = self.metadata.pkg_config.as_ref().map(|req| &req.name);
+        let vcpkg = self.metadata.vcpkg.as_ref().map(|req| &req.name);
+        match pkg_config.or(vcpkg).or(outcome.provider.as_ref()) {
+            Some(name) => Ok(name.clone()),
+            None => env_var("CARGO_PKG_NAME"),
+        }
+    }
+
+    /// Gets the library's packages for a package manager from `system-packages`,
+    /// or else assumes they're named like the pkg-config module.
+    fn system_packages<'a>(&'a self, manager: &str, req: &'a PkgConfigRequirement) -> Vec<&'a str> {
+        match self.metadata.system_packages.get(manager) {
+            Some(packages) => packages.iter().map(String::as_str).collect(),
+            None => vec![req.name.as_str()],
+        }
+    }
+
+    /// Builds the library from the vendored source.
+    fn vendor<F>(&self, try_vendor: &F, link_kind: Option<LinkKind>) -> Result<BuildOutcome, Error>
+    where
+        F: Fn(VendoredBuildContext) -> Result<(), Error>,
+    {
+        let vendored_source = self
+            .metadata
+            .vendored_source
+            .as_ref()
+            .ok_or_else(|| ErrorKind::NoVendoredSourceSpecified)?;
+        let rpath = &self.metadata.rpath;
+        let mut outcome = if let VendoredSource::SysCrate(sys_crate) = vendored_source {
+            // The other crate's build decides how its library is found at runtime.
+            let mut outcome = sys_crate.link()?;
+            outcome.vendored = true;
+            return Ok(outcome);
+        } else if let VendoredSource::PrebuiltBinary(prebuilt) = vendored_source {
+            let root = vendored_source.fetch(&self.fetch_context())?;
+            let link_kind = link_kind.unwrap_or(LinkKind::Dylib);
+            symbols::verify(&[prebuilt.lib_dir(&root)], &self.metadata.required_symbols)?;
+            musl::warn_glibc_prebuilt(&self.target, &prebuilt.lib_dir(&root))?;
+            let mut outcome = prebuilt.link(&root, link_kind)?;
+            if link_kind == LinkKind::Dylib {
+                let lib_dir = prebuilt.lib_dir(&root);
+                if !rpath.is_empty() {
+                    rpath::fix_install_names(&self.target, &lib_dir)?;
+                    rpath::add_rpaths(&self.target, rpath, &mut outcome.link);
+                }
+                if self.metadata.dev_rpath {
+                    rpath::add_dev_rpath(&self.target, &lib_dir, &mut outcome.link)?;
+                }
+            }
+            outcome
+        } else {
+            let mut ctx = VendoredBuildContext::new(
+                vendored_source,
+                &self.metadata,
+                self.target.clone(),
+                link_kind,
+                &self.fetch_context(),
+            )?;
+            let install_prefix = ctx.install_prefix.clone();
+            let env = ctx.env.clone();
+            let (build_both, built) = (ctx.build_both, ctx.built);
+            let stamp = ctx.out_dir.join(build_dir::BUILT_STAMP);
+            let dependency_link = std::mem::take(&mut ctx.dependency_link);
+            let dev_link = Rc::clone(&ctx.dev_link);
+            // Released once the libraries are processed and the stamp is written.
+            let _work_dir_lock = ctx.work_dir_lock.take();
+            try_vendor(ctx)?;
+            let version = vendored_source.version().map(Version::parse).transpose()?;
+            let mut outcome = BuildOutcome::found(version);
+            emit_install_prefix(&install_prefix, &mut outcome.link);
+            outcome.link.extend(dependency_link);
+            outcome.link.extend(dev_link.take());
+            // Static libraries need no runtime search path.
+            if link_kind != Some(LinkKind::Static) {
+                rpath::add_rpaths(&self.target, rpath, &mut outcome.link);
+            }
+            // `cc` builds put their libraries directly into `OUT_DIR`.
+            let lib_dirs = [
+                path_var("OUT_DIR")?,
+                install_prefix.join("lib"),
+                install_prefix.join("lib64"),
+                install_prefix.join("bin"),
+            ];
+            // Checked before the other symbols are localized, which would hide them.
+            symbols::verify(&lib_dirs, &self.metadata.required_symbols)?;
+            // The libraries of a build reused with `build-both` are processed already.
+            if !built {
+                localize::localize(&self.target, &lib_dirs, &self.metadata.public_symbols, &env)?;
+            }
+            if let Some(prefix) = &self.metadata.symbol_prefix {
+                let header = if built {
+                    let header = install_prefix.join("include/buildkit_prefix_symbols.h");
+                    Some(header).filter(|header| header.is_file())
+                } else {
+                    localize::prefix(&self.target, &lib_dirs, prefix, &env, &install_prefix)?
+                };
+                if let Some(header) = header {
+                    println!("cargo:symbol-prefix={prefix}");
+                    println!("cargo:symbol-prefix-header={header}");
+                }
+            }
+            if !built && reproducible::enabled(self.metadata.reproducible)? {
+                reproducible::normalize_archives(&lib_dirs)?;
+            }
+            if let Some(pc) = &self.metadata.generate_pc {
+                let name = self
+                    .metadata
+                    .pkg_config
+                    .as_ref()
+                    .map(|req| req.name.as_str());
+                let dir = pc.install(name, vendored_source.version(), &install_prefix)?;
+                println!("cargo:pkg-config-path={dir}");
+            }
+            if build_both && !built {
+                std::fs::write(&stamp, "").map_err(io_error(&stamp))?;
+            }
+            outcome
+        };
+        outcome.vendored = true;
+        Ok(outcome)
+    }
+
+    fn fetch_context(&self) -> FetchContext<'_> {
+        FetchContext {
+            fetchers: &self.fetchers,
+            allow_unverified: self.metadata.allow_unverified,
+            tree_hash: self.metadata.tree_hash.as_ref(),
+            workspace: self.workspace.as_ref(),
+        }
+    }
+
+    /// Probes with the custom backend selected by `package.metadata.buildkit.backend`.
+    fn try_backend(&self, link_kind: Option<LinkKind>) -> Result<BuildOutcome, Error> {
+        let name = self.metadata.backend.as_deref().unwrap_or_default();
+        let backend = self
+            .backends
+            .iter()
+            .find(|backend| backend.name() == name)
+            .ok_or_else(|| ErrorKind::UnknownBackend(name.to_owned()))?;
+        let package = env_var("CARGO_PKG_NAME")?;
+        event!(debug, backend = name, "probing with a backend");
+        backend.probe(&ProbeRequest {
+            package: &package,
+            link_kind,
+            config: self.metadata.backend_config.as_ref(),
+        })
+    }
+
+    /// Probes the providers in order of preference, reporting the first one found.
+    ///
+    /// `probe` returns `None` for providers the current mode can't probe.
+    fn try_providers<P>(&self, probe: P) -> Result<BuildOutcome, Error>
+    where
+        P: Fn(&Provider) -> Option<Result<BuildOutcome, Error>>,
+    {
+        provider::emit_check_cfg(&self.metadata.providers);
+        let mut failures = Vec::new();
+        for provider in provider::ordered(&self.metadata.providers)? {
+            match probe(provider) {
+                Some(Ok(mut outcome)) => {
+                    provider::report(&provider.name);
+                    outcome.provider = Some(provider.name.clone());
+                    return Ok(outcome);
+                }
+                Some(Err(err)) => {
+                    failures.push(diagnostics::indent(format!("{}: {err}", provider.name)))
+                }
+                None => {}
+            }
+        }
+        Err(ErrorKind::NoProviderFound(failures.join("\n")).into())
+    }
+
+    /// Turns a failed probe into a missing library if the requirement is optional.
+    fn probed(&self, result: Result<BuildOutcome, Error>) -> Result<BuildOutcome, Error> {
+        if !self.metadata.optional {
+            return result;
+        }
+        println!("cargo:rustc-check-cfg=cfg(buildkit_missing)");
+        result.or_else(|err| {
+            event!(warn, error = %err, "optional library not found");
+            warning!("optional library not found, building without it: {err}");
+            println!("cargo:rustc-cfg=buildkit_missing");
+            Ok(BuildOutcome::missing())
+        })
     }
 
     /// Gets the mode we're going to build in.
     ///
-    /// TODO: ways for external build systems to override
+    /// `BUILDKIT_<PACKAGE>_MODE` and then `BUILDKIT_FORCE_MODE` take precedence over the
+    /// workspace's `buildkit.toml`, which takes precedence over the crate's metadata.
     fn mode(&self) -> Result<BuildKitMode, Error> {
+        let crate_mode = env_overrides::mode(&env_overrides::package_key("MODE")?)?;
+        let mode = match crate_mode {
+            Some(mode) => Some(mode),
+            None => env_overrides::mode("BUILDKIT_FORCE_MODE")?,
+        };
+        if let Some(mode) = mode {
+            return Ok(mode);
+        }
+        if let Some(workspace) = &self.workspace {
+            if let Some(mode) = workspace.mode(&env_var("CARGO_PKG_NAME")?) {
+                return Ok(mode);
+            }
+        }
+        let mode = self.default_mode()?;
+        // cross-rs images have no vcpkg, but the target's libraries from Debian multiarch.
+        if cross_rs::detected() && matches!(mode, BuildKitMode::Vcpkg) {
+            return Ok(BuildKitMode::PkgConfig);
+        }
+        Ok(mode)
+    }
+
+    /// Decides the mode from the crate's metadata and the target.
+    fn default_mode(&self) -> Result<BuildKitMode, Error> {
+        let target = env_var("TARGET")?;
+        if let Some(mode) = target_modes::mode_for(&self.metadata.target_modes, &target) {
+            return Ok(mode);
+        }
+        // A port is built by `emcc` for the target, like the vendored source would be.
+        if self.metadata.emscripten_port.is_some() && emscripten::is_target(&self.target) {
+            return Ok(BuildKitMode::PkgConfig);
+        }
         if matches!(self.metadata.default_mode, BuildKitMode::VendoredBuild) {
+            if self.prefers_cross_rs_libraries()? {
+                return Ok(BuildKitMode::PkgConfig);
+            }
             return Ok(BuildKitMode::VendoredBuild);
         }
-        let target = env_var("TARGET")?;
-        // TODO: should we relax it to `-windows-`?
-        // Some people seems to use vcpkg with mingw: https://www.reddit.com/r/cpp/comments/p1655e/comment/h8bly7v
-        //
         // TODO: should we retry if vcpkg found nothing?
         // curl-sys falls back to pkg_config when vcpkg failed.
         // https://github.com/alexcrichton/curl-rust/blob/c01261310f13c85dc70d4e8a1ef87504662a1154/curl-sys/build.rs#L30-L37
         if target.ends_with("-windows-msvc") {
             Ok(BuildKitMode::Vcpkg)
+        } else if is_windows_gnu(&target) {
+            match self.windows_gnu_mode() {
+                BuildKitMode::VendoredBuild if self.prefers_cross_rs_libraries()? => {
+                    Ok(BuildKitMode::PkgConfig)
+                }
+                mode => Ok(mode),
+            }
         } else {
             Ok(BuildKitMode::PkgConfig)
         }
     }
+
+    /// Whether the build runs in a cross-rs container, whose libraries are used rather than
+    /// building the vendored source unless `BUILDKIT_CROSS_VENDOR=1`, and the crate can
+    /// find them with pkg-config.
+    fn prefers_cross_rs_libraries(&self) -> Result<bool, Error> {
+        let probes_pkg_config = self.metadata.pkg_config.is_some()
+            || self
+                .metadata
+                .providers
+                .iter()
+                .any(|provider| provider.pkg_config.is_some());
+        Ok(probes_pkg_config && cross_rs::detected() && !cross_rs::vendoring_allowed()?)
+    }
+
+    /// Gets the mode for `*-windows-gnu` and `*-windows-gnullvm` targets.
+    ///
+    /// Unless `windows-gnu-mode` is set, pkg-config is used from an MSYS2 shell
+    /// (which ships `.pc` files for its mingw packages),
+    /// otherwise the vendored build is preferred when one is available.
+    fn windows_gnu_mode(&self) -> BuildKitMode {
+        if let Some(mode) = self.metadata.windows_gnu_mode {
+            return mode;
+        }
+        println!("cargo:rerun-if-env-changed=MSYSTEM");
+        if std::env::var_os("MSYSTEM").is_some() || self.metadata.vendored_source.is_none() {
+            BuildKitMode::PkgConfig
+        } else {
+            BuildKitMode::VendoredBuild
+        }
+    }
 }
 
 fn env_var(key: &'static str) -> Result<String, Error> {
     std::env::var(key).map_err(|err| ErrorKind::EnvVarError { key, err }.into())
 }
 
+/// Gets a path from the environment, like `OUT_DIR`, failing with the path if it isn't UTF-8.
+fn path_var(key: &'static str) -> Result<Utf8PathBuf, Error> {
+    match std::env::var_os(key) {
+        Some(path) => utf8_path(path.into(), key),
+        None => env_var(key).map(Utf8PathBuf::from),
+    }
+}
+
+/// Gets the environment variables whose name and value are UTF-8, skipping the others,
+/// which `std::env::vars` would panic on.
+fn utf8_vars() -> impl Iterator<Item = (String, String)> {
+    std::env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+}
+
+/// Converts the path `what` is to UTF-8, failing with the path if it isn't.
+fn utf8_path(path: std::path::PathBuf, what: &str) -> Result<Utf8PathBuf, Error> {
+    Utf8PathBuf::from_path_buf(path).map_err(|path| {
+        ErrorKind::NonUtf8Path {
+            what: what.to_owned(),
+            path,
+        }
+        .into()
+    })
+}
+
+/// Adds the link search path for the libraries installed into `prefix`,
+/// and emits its `root` and `include` directories for dependents as `DEP_<links>_ROOT`
+/// and `DEP_<links>_INCLUDE`.
+fn emit_install_prefix(prefix: &Utf8Path, link: &mut LinkDirectives) {
+    for lib_dir in ["lib", "lib64"].map(|dir| prefix.join(dir)) {
+        if lib_dir.is_dir() {
+            link.search_paths.push(format!("native={lib_dir}"));
+        }
+    }
+    let include = prefix.join("include");
+    if include.is_dir() {
+        println!("cargo:include={include}");
+    }
+    if prefix.is_dir() {
+        println!("cargo:root={prefix}");
+    }
+}
+
+/// Runs a command to completion, returning its trimmed stdout.
+fn run_command(cmd: &mut std::process::Command) -> Result<String, Error> {
+    let command = format!("{cmd:?}");
+    event!(debug, %command, "running a command");
+    let output = cmd.output().map_err(|err| ErrorKind::CommandFailed {
+        command: command.clone(),
+        reason: err.to_string(),
+    })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ErrorKind::CommandFailed {
+            command,
+            reason: format!("{}: {}", output.status, stderr.trim()),
+        }
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// Runs a build step with its output passed through to the build log.
+fn run_build_command(cmd: &mut std::process::Command) -> Result<(), Error> {
+    event!(info, command = ?cmd, "running a build step");
+    let status = cmd.status().map_err(|err| ErrorKind::CommandFailed {
+        command: format!("{cmd:?}"),
+        reason: err.to_string(),
+    })?;
+    if !status.success() {
+        return Err(ErrorKind::CommandFailed {
+            command: format!("{cmd:?}"),
+            reason: status.to_string(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn io_error(path: &Utf8Path) -> impl FnOnce(std::io::Error) -> ErrorKind + '_ {
+    move |err| ErrorKind::Io {
+        path: path.to_owned(),
+        err,
+    }
+}
+
 /// Represents possible errors that can occur when build libraries
-#[derive(Debug, thiserror::Error)]
+#[derive(thiserror::Error)]
 #[error(transparent)]
 pub struct Error(#[from] ErrorKind);
 
+/// Shows the code and message, so a build script's `unwrap()` or `main` returning the error
+/// prints the lines of tool output in it as lines.
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code(), self.0)
+    }
+}
+
 impl Error {
+    /// Gets the stable code of the error, e.g. `BK0102` for a hash mismatch, for tools
+    /// classifying failures.
+    ///
+    /// Codes are grouped by what failed: `BK01xx` fetching and verifying sources, `BK02xx`
+    /// probing, `BK03xx` the vendored build, `BK04xx` the configuration and `BK05xx` the
+    /// build environment. A code always means the same kind of error.
+    pub fn code(&self) -> &'static str {
+        self.0.code().0
+    }
+
     /// Creates a custom error.
     ///
     /// This is useful during a vendor build and you want to return your own error.
@@ -148,12 +1356,239 @@ enum ErrorKind {
     #[error("vcpkg mode is set but no vcpkg requirement specified")]
     NoVcpkgRequirementSpecified,
 
+    #[error("Invalid version `{0}`")]
+    InvalidVersion(String),
+
     #[error("vcpkg failed to probe: {0}")]
     VcpkgError(#[from] vcpkg::Error),
 
+    #[error("vcpkg failed to probe `{port}`: {err}")]
+    VcpkgProbe {
+        port: String,
+        #[source]
+        err: Box<vcpkg::Error>,
+    },
+
+    #[error("`{library}` {found} was found, which isn't {required}")]
+    VersionMismatch {
+        library: String,
+        found: String,
+        required: String,
+    },
+
+    #[error("`{library}` {version} is a release known to be broken, excluded by `{range}`")]
+    ExcludedVersion {
+        library: String,
+        version: String,
+        range: String,
+    },
+
+    #[error("vcpkg port `{port}` is installed for {triplet} at {found}, which isn't {required}")]
+    VcpkgVersionMismatch {
+        port: String,
+        triplet: String,
+        found: String,
+        required: String,
+    },
+
+    #[error(
+        "vcpkg triplet `{triplet}` links the {} CRT, but the target {}; \
+         use a {} triplet or {} `-C target-feature=+crt-static`",
+        if *crt_static { "dynamic" } else { "static" },
+        if *crt_static { "has `+crt-static`" } else { "links the dynamic CRT" },
+        if *crt_static { "`*-windows-static`" } else { "`*-windows-static-md` or `*-windows`" },
+        if *crt_static { "drop" } else { "set" }
+    )]
+    CrtMismatch { triplet: String, crt_static: bool },
+
     #[error("pkg-config failed to probe: {0}")]
     PkgConfigError(#[from] pkg_config::Error),
 
+    #[error(
+        "pkg-config failed to probe `{library}`:\n{}",
+        diagnostics::indent(err)
+    )]
+    PkgConfigProbe {
+        library: String,
+        #[source]
+        err: Box<pkg_config::Error>,
+    },
+
+    #[error("No {vars} from a dependency with `links = \"{links}\"`; is it in `[dependencies]`?")]
+    MissingSysCrate { links: String, vars: String },
+
+    #[error("GObject introspection file `{0}` not found")]
+    MissingGir(Utf8PathBuf),
+
+    #[error("`{0}` isn't in the ldconfig cache")]
+    NotInLdconfigCache(String),
+
+    #[error("Header `{0}` not found")]
+    MissingHeader(Utf8PathBuf),
+
+    #[error("`{0}` is needed to build the vendored library but isn't on PATH")]
+    MissingTool(String),
+
+    #[error("`{name}` {found} is older than the {required} needed to build the vendored library")]
+    ToolTooOld {
+        name: String,
+        found: String,
+        required: String,
+    },
+
+    #[error(
+        "`{component}` in `{path}` has spaces or characters build tools can't handle; \
+         set `BUILDKIT_BUILD_DIR` to a directory without them to build there instead"
+    )]
+    UnsafeBuildPath {
+        path: Utf8PathBuf,
+        component: String,
+    },
+
+    #[error("No prebuilt binary available for target `{0}`")]
+    NoPrebuiltForTarget(String),
+
+    #[error(
+        "No hash for `{0}`; pin one, or set `allow-unverified = true` to develop against \
+         an unreleased snapshot"
+    )]
+    MissingHash(String),
+
+    #[error(
+        "No hash for `{0}`, and `allow-unverified` requires acknowledging that with \
+         `BUILDKIT_ALLOW_UNVERIFIED=1`"
+    )]
+    UnverifiedNotAcknowledged(String),
+
+    #[error(
+        "No entry for `{url}` in `{lockfile}`, which `BUILDKIT_LOCKED=1` requires of every source"
+    )]
+    NotLocked { url: String, lockfile: Utf8PathBuf },
+
+    #[error("`{url}` doesn't match `{lockfile}`: locked {expected}, got {actual}")]
+    LockMismatch {
+        url: String,
+        lockfile: Utf8PathBuf,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("`BUILDKIT_LOCKED=1` needs a `buildkit.lock` above `{0}` or at `BUILDKIT_LOCKFILE`")]
+    NoLockfile(Utf8PathBuf),
+
+    #[error("Invalid URL template `{template}`: {reason}")]
+    InvalidUrlTemplate { template: String, reason: String },
+
+    #[error(
+        "Cross-language LTO needs clang with the same LLVM major version as rustc \
+         (LLVM {rustc_llvm}), but `{cc}` is {clang}"
+    )]
+    IncompatibleLto {
+        rustc_llvm: String,
+        cc: String,
+        clang: String,
+    },
+
+    #[error("Failed to download `{url}`: {err}")]
+    Download {
+        url: String,
+        #[source]
+        err: Box<ureq::Error>,
+    },
+
+    #[error(
+        "Cannot fetch `{0}` while offline; \
+         put it in `BUILDKIT_VENDOR_DIR` or point `BUILDKIT_SOURCE_MIRROR` at a local copy"
+    )]
+    Offline(String),
+
+    #[error(
+        "Cannot fetch `{0}` with `BUILDKIT_DENY_NETWORK=1`; \
+         put it in `BUILDKIT_VENDOR_DIR` or point `BUILDKIT_SOURCE_MIRROR` at a local copy"
+    )]
+    NetworkDenied(String),
+
+    #[error("Cannot {action} with `BUILDKIT_HERMETIC=1`; {hint}")]
+    HermeticViolation { action: String, hint: &'static str },
+
+    #[error("Cannot fetch `{url}`; {hint}")]
+    UnsupportedUrl { url: String, hint: &'static str },
+
+    #[error("Hash mismatch for `{what}`: expected {expected}, got {actual}")]
+    HashMismatch {
+        what: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("Unsupported archive format: `{0}`")]
+    UnsupportedArchive(String),
+
+    #[error("Failed to unpack `{path}`: {err}")]
+    Unpack {
+        path: Utf8PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error(
+        "Vendored libraries are missing required symbols {missing:?} \
+         (searched {libraries:?}); is a feature of the vendored build disabled?"
+    )]
+    MissingSymbols {
+        missing: Vec<String>,
+        libraries: Vec<String>,
+    },
+
+    #[error("Failed to read symbols from `{path}`: {reason}")]
+    InvalidLibrary { path: Utf8PathBuf, reason: String },
+
+    #[error(
+        "vcpkg port `{port}` is installed for {triplet} without the features {missing:?}; \
+         reinstall it with them, e.g. `vcpkg install {port}[{}]:{triplet}{overlay_args}`",
+        missing.join(",")
+    )]
+    MissingVcpkgFeatures {
+        port: String,
+        triplet: String,
+        missing: Vec<String>,
+        overlay_args: String,
+    },
+
+    #[error("No probe backend named `{0}` is registered")]
+    UnknownBackend(String),
+
+    #[error("Unknown provider `{0}`")]
+    UnknownProvider(String),
+
+    #[error("No provider found:\n{0}")]
+    NoProviderFound(String),
+
+    #[error("BLAS provider `{provider}` is not available for `{target}`")]
+    UnsupportedBlasProvider {
+        provider: &'static str,
+        target: String,
+    },
+
+    #[error("`{command}` failed: {reason}")]
+    CommandFailed { command: String, reason: String },
+
+    #[error("I/O error on `{path}`: {err}")]
+    Io {
+        path: Utf8PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error(
+        "{what} `{}` isn't valid UTF-8, which buildkit needs to pass paths to build tools",
+        path.display()
+    )]
+    NonUtf8Path {
+        what: String,
+        path: std::path::PathBuf,
+    },
+
     #[error("Failed to get env var `{key}`: {err}")]
     EnvVarError {
         key: &'static str,
@@ -161,8 +1596,63 @@ enum ErrorKind {
         err: std::env::VarError,
     },
 
+    #[error("Invalid value for `{key}`: {reason}")]
+    InvalidEnvVar { key: String, reason: String },
+
+    #[error("Invalid cargo configuration {origin}: {reason}")]
+    InvalidCargoConfig { origin: String, reason: String },
+
+    #[error(
+        "Invalid link modifier `{modifier}` for `{lib}`; \
+         use `+` or `-` and `bundle`, `verbatim`, `whole-archive` or `as-needed`"
+    )]
+    InvalidLinkModifier { lib: String, modifier: String },
+
+    #[error("Invalid linker argument `{0}`; list each argument separately, without whitespace")]
+    InvalidLinkArg(String),
+
+    #[error("Invalid target expression `{expr}`: {reason}")]
+    InvalidCfg { expr: String, reason: String },
+
+    #[error(
+        "`{library}` was found in `{path}`, outside the target sysroot `{sysroot}`; \
+         that's a library of the build machine"
+    )]
+    OutsideSysroot {
+        library: String,
+        path: Utf8PathBuf,
+        sysroot: Utf8PathBuf,
+    },
+
+    #[error("`{library}` was found in `{path}`, outside `BUILDKIT_ALLOWED_ROOTS`")]
+    OutsideAllowedRoots { library: String, path: Utf8PathBuf },
+
+    #[error("Invalid buildkit configuration `{path}`: {reason}")]
+    InvalidWorkspaceConfig { path: Utf8PathBuf, reason: String },
+
+    #[error("Invalid lockfile `{path}`: {reason}")]
+    InvalidLockfile { path: Utf8PathBuf, reason: String },
+
+    #[error(
+        "`{header}` declares {library} {header_version}, but {found_in} has {found}; \
+         the headers and the library are from different installations"
+    )]
+    AbiMismatch {
+        library: String,
+        header: Utf8PathBuf,
+        header_version: String,
+        found: String,
+        found_in: String,
+    },
+
+    #[error("`{header}` doesn't define the version macro `{name}`")]
+    MissingVersionMacro { header: Utf8PathBuf, name: String },
+
     #[error(transparent)]
     Custom(Box<dyn std::error::Error>),
+
+    #[error("{err}\n\n{help}")]
+    WithHelp { err: Box<ErrorKind>, help: String },
 }
 
 // This will represent the data that folks can specify within their Cargo.toml
@@ -170,10 +1660,129 @@ enum ErrorKind {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct BuildKitMetadata {
+    /// A hash of the metadata as written, keying the work `build-both` builds share.
+    #[serde(skip)]
+    digest: String,
     pkg_config: Option<PkgConfigRequirement>,
     vcpkg: Option<VcpkgRequirement>,
     vendored_source: Option<VendoredSource>,
     default_mode: BuildKitMode,
+    /// Modes for target triples matching glob patterns, overriding the others.
+    #[serde(default)]
+    target_modes: BTreeMap<String, BuildKitMode>,
+    /// Overrides the mode for `*-windows-gnu` and `*-windows-gnullvm` targets.
+    windows_gnu_mode: Option<BuildKitMode>,
+    /// Whether a failed probe is tolerated rather than an error.
+    #[serde(default)]
+    optional: bool,
+    /// Runtime search paths for vendored shared libraries, e.g. `$ORIGIN/../lib`.
+    #[serde(default)]
+    rpath: Vec<String>,
+    /// Whether to add rpaths into `OUT_DIR` in non-release profiles.
+    #[serde(default)]
+    dev_rpath: bool,
+    /// Whether to drop default linker directories like `/usr/lib` from the search paths.
+    #[serde(default)]
+    filter_system_paths: bool,
+    #[serde(default)]
+    linkage: Linkage,
+    /// Whether to compile vendored sources for cross-language LTO.
+    #[serde(default)]
+    cross_lang_lto: bool,
+    /// What to do when the system library is older than required.
+    #[serde(default)]
+    too_old: TooOldPolicy,
+    /// Symbols the vendored libraries must define.
+    #[serde(default)]
+    required_symbols: Vec<String>,
+    /// The public API of vendored static libraries; all other symbols are localized.
+    #[serde(default)]
+    public_symbols: Vec<String>,
+    /// A prefix for the global symbols of vendored static libraries.
+    symbol_prefix: Option<String>,
+    /// Whether the library is C++ and needs the C++ standard library.
+    #[serde(default)]
+    cxx: bool,
+    /// Per-target overrides of the C++ standard library to link.
+    #[serde(default)]
+    cxx_stdlib: BTreeMap<String, String>,
+    /// The Fortran runtime the library needs.
+    fortran: Option<FortranRuntime>,
+    /// The BLAS/LAPACK provider the library needs.
+    blas: Option<Blas>,
+    /// Alternative implementations of the library, in order of preference.
+    #[serde(default)]
+    providers: Vec<Provider>,
+    /// A registered [`ProbeBackend`] to probe with instead of pkg-config and vcpkg.
+    backend: Option<String>,
+    /// Settings for the [`ProbeBackend`].
+    backend_config: Option<serde_json::Value>,
+    /// Where vcpkg and its installed libraries are.
+    #[serde(default)]
+    vcpkg_config: VcpkgConfig,
+    /// Whether sources may be fetched without a hash, given `BUILDKIT_ALLOW_UNVERIFIED=1`.
+    #[serde(default)]
+    allow_unverified: bool,
+    /// The hash of the vendored source's unpacked tree, in Nix's NAR format.
+    tree_hash: Option<Digests>,
+    /// Options of the vendored build for cargo features.
+    #[serde(default)]
+    features: BTreeMap<String, FeatureOptions>,
+    /// A pkg-config file to generate for the vendored build.
+    generate_pc: Option<PcFile>,
+    /// The library's packages in distributions and package sets, e.g. under `nix`.
+    #[serde(default)]
+    system_packages: BTreeMap<String, Vec<String>>,
+    /// System libraries the vendored build links against, probed before it.
+    #[serde(default)]
+    dependencies: Vec<Dependency>,
+    /// Executables the vendored build runs.
+    #[serde(default)]
+    tools: Vec<ToolRequirement>,
+    /// Interpreters the vendored build's scripts run.
+    #[serde(default)]
+    needs: Vec<Prerequisite>,
+    /// The assembler of the vendored library's x86 code, and the options without one.
+    assembler: Option<Assembler>,
+    /// Whether the vendored build is to give bit-identical libraries when repeated.
+    #[serde(default)]
+    reproducible: bool,
+    /// Whether to keep the paths of the vendored build out of its libraries.
+    #[serde(default)]
+    remap_paths: bool,
+    /// `rustc-link-lib` modifiers of the libraries, per target.
+    #[serde(default)]
+    link_modifiers: Vec<LinkModifiers>,
+    /// Frameworks and libraries to link weakly on Apple targets.
+    #[serde(default)]
+    weak_link: WeakLink,
+    /// Extra linker arguments by `cfg(...)` expression or target triple pattern.
+    #[serde(default)]
+    link_args: BTreeMap<String, Vec<String>>,
+    /// The newest glibc and macOS versions the shared libraries may require.
+    #[serde(default)]
+    runtime_compat: RuntimeCompat,
+    /// Whether to copy the DLLs linked next to the binaries and tests on Windows.
+    #[serde(default)]
+    copy_dlls: bool,
+    /// The Emscripten port providing the library on Emscripten targets.
+    emscripten_port: Option<String>,
+    /// Guidance appended to errors, by the kind of error.
+    #[serde(default)]
+    help: BTreeMap<String, Help>,
+}
+
+/// Resolution policy for a system library that is found but older than required.
+///
+/// Only pkg-config reports versions, so this doesn't affect vcpkg.
+#[derive(Debug, Default, Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum TooOldPolicy {
+    /// Fail the build.
+    #[default]
+    Error,
+    /// Fall through to the vendored build with a warning.
+    Vendor,
 }
 
 #[derive(Debug, Deserialize, Clone, Copy)]
@@ -184,6 +1793,40 @@ enum BuildKitMode {
     VendoredBuild,
 }
 
+/// A library the vendored build links against.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Dependency {
+    /// A system library, probed with pkg-config.
+    PkgConfig(PkgConfigRequirement),
+    /// The library of another `-sys` crate, from its `DEP_<links>_*` metadata.
+    SysCrate(SysCrate),
+}
+
+/// The name of a `.pc` file variable, passed on to dependents as `cargo:pc-<name>`, so it
+/// can't clash with the keys cargo and buildkit emit, or break the line it's printed on.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "String")]
+struct PcVariable(String);
+
+impl TryFrom<String> for PcVariable {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<PcVariable, String> {
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        if name.is_empty() || !valid {
+            return Err(format!(
+                "invalid pkg-config variable `{}`, which can only have letters, digits, \
+                 `_` and `.`",
+                name.escape_debug()
+            ));
+        }
+        Ok(PcVariable(name))
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 struct PkgConfigRequirement {
@@ -192,6 +1835,22 @@ struct PkgConfigRequirement {
     /// For example, libcurl will be `libcurl`.
     name: String,
     version_req: Option<PkgConfigVersionReq>,
+    /// Versions within the requirement to reject, e.g. releases with known ABI bugs.
+    #[serde(default)]
+    exclude: Vec<VersionRange>,
+    /// Variables of the `.pc` file to query, e.g. `plugindir`.
+    #[serde(default)]
+    variables: Vec<PcVariable>,
+    /// GObject introspection namespaces the library provides, e.g. `Gtk-4.0`.
+    #[serde(default)]
+    gir: Vec<String>,
+    /// Directories with `.pc` files, relative to the package, to search for this probe only.
+    #[serde(default)]
+    pkg_config_path: Vec<Utf8PathBuf>,
+    /// Where to find the library on Linux if pkg-config doesn't know it.
+    ldconfig_fallback: Option<LdconfigFallback>,
+    /// A header whose version has to match the library's.
+    abi_check: Option<Box<AbiCheck>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -207,6 +1866,55 @@ enum PkgConfigVersionReq {
     Max { max: String },
     /// At exactly the given version.
     Exact { exact: String },
+    /// In any of the given ranges, e.g. `["1.2.x", ">=2.1"]`.
+    Any { any: Vec<VersionRange> },
+}
+
+impl fmt::Display for PkgConfigVersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PkgConfigVersionReq::Range { min, max } => write!(f, ">= {min}, < {max}"),
+            PkgConfigVersionReq::Min { min } => write!(f, ">= {min}"),
+            PkgConfigVersionReq::Max { max } => write!(f, "<= {max}"),
+            PkgConfigVersionReq::Exact { exact } => write!(f, "= {exact}"),
+            PkgConfigVersionReq::Any { any } => {
+                let ranges: Vec<String> = any.iter().map(|range| range.to_string()).collect();
+                f.write_str(&ranges.join(" or "))
+            }
+        }
+    }
+}
+
+impl PkgConfigVersionReq {
+    /// Whether `version` satisfies the requirement, the way pkg-config compares them.
+    fn matches(&self, version: &Version) -> Result<bool, Error> {
+        Ok(match self {
+            PkgConfigVersionReq::Range { min, max } => {
+                *version >= Version::parse(min)? && *version < Version::parse(max)?
+            }
+            PkgConfigVersionReq::Min { min } => *version >= Version::parse(min)?,
+            PkgConfigVersionReq::Max { max } => *version <= Version::parse(max)?,
+            PkgConfigVersionReq::Exact { exact } => *version == Version::parse(exact)?,
+            PkgConfigVersionReq::Any { any } => any.iter().any(|range| range.contains(version)),
+        })
+    }
+
+    /// The lowest acceptable version, if bounded from below; for ranges, that of the lowest
+    /// range, if every one is bounded.
+    fn min(&self) -> Option<&str> {
+        match self {
+            PkgConfigVersionReq::Range { min, .. } | PkgConfigVersionReq::Min { min } => Some(min),
+            PkgConfigVersionReq::Exact { exact } => Some(exact),
+            PkgConfigVersionReq::Max { .. } => None,
+            PkgConfigVersionReq::Any { any } => any
+                .iter()
+                .map(VersionRange::min)
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .min()
+                .map(Version::as_str),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -219,6 +1927,14 @@ struct VcpkgRequirement {
     ///
     /// See [`vcpkg::Config::lib_names`] for more.
     libs: Vec<VcpkgLibName>,
+    /// Port features required by cargo features, e.g. `{ ssl = "openssl" }`.
+    #[serde(default)]
+    features: BTreeMap<String, String>,
+    /// The accepted versions of the installed port, which vcpkg-rs doesn't check.
+    version_req: Option<PkgConfigVersionReq>,
+    /// Versions within the requirement to reject.
+    #[serde(default)]
+    exclude: Vec<VersionRange>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -230,46 +1946,394 @@ struct VcpkgLibName {
     dll_name: String,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-#[serde(rename_all_fields = "kebab-case")]
-enum VendoredSource {
-    RemoteTarball {
-        url: String,
-        hash: String,
-    },
-    // TODO: Is just ref enough here? SHA1...
-    GitRepo {
-        url: String,
-        git_ref: String,
-        hash: String,
-    },
-    CratePath {
-        relative_path: Utf8PathBuf,
-    },
-    // TODO: Cannot be specified in the crate, only can be set at build time
-    SystemPath {
-        path: Utf8PathBuf,
-    },
-}
-
 /// Provides the information needed for build a library from a vendred source.
 #[derive(Debug)]
 pub struct VendoredBuildContext {
     source_path: Utf8PathBuf,
+    out_dir: Utf8PathBuf,
+    install_prefix: Utf8PathBuf,
+    cflags: Vec<String>,
+    env: Vec<(String, String)>,
+    link_kind: Option<LinkKind>,
+    build_both: bool,
+    built: bool,
+    dev_rpath: bool,
+    cross_host: Option<String>,
+    cmake_toolchain_file: Option<String>,
+    feature_options: BuildOptions,
+    dependency_include_paths: Vec<Utf8PathBuf>,
+    dependency_link_paths: Vec<Utf8PathBuf>,
+    dependency_link: LinkDirectives,
+    /// The rpaths added through [`dev_rpath`](Self::dev_rpath).
+    dev_link: Rc<RefCell<LinkDirectives>>,
+    /// Held by a `build-both` build until it's done with the shared work directory.
+    work_dir_lock: Option<build_dir::WorkDirLock>,
+    target: TargetInfo,
 }
 
 impl VendoredBuildContext {
-    fn new(source: &VendoredSource) -> VendoredBuildContext {
-        VendoredBuildContext {
-            source_path: Utf8PathBuf::new(),
+    fn new(
+        source: &VendoredSource,
+        metadata: &BuildKitMetadata,
+        target: TargetInfo,
+        link_kind: Option<LinkKind>,
+        fetch: &FetchContext<'_>,
+    ) -> Result<VendoredBuildContext, Error> {
+        tools::check_prerequisites(&metadata.needs)?;
+        let mut env = Vec::new();
+        if let Some(path) = tools::check(&metadata.tools, fetch)? {
+            env.push(("PATH".to_owned(), path));
+        }
+        let flavor = cflags::Flavor::detect(&target);
+        let mut cflags = cflags::profile_flags(flavor, &target)?;
+        cflags.extend(sanitizer::compiler_flags(flavor));
+        if metadata.cross_lang_lto {
+            if flavor == cflags::Flavor::Msvc {
+                warning!("cross-language LTO is not supported for MSVC targets");
+            } else {
+                lto::configure(&mut cflags, &mut env)?;
+            }
+        }
+        let mut cross_host = cross::configure(&mut env)?;
+        let mut pkg_env = PkgConfigEnv::default();
+        cross_rs::configure_pkg_config(&mut pkg_env);
+        let sysroot = Sysroot::detect()?;
+        let mut cmake_toolchain_file = None;
+        if let Some(sysroot) = &sysroot {
+            sysroot.configure_pkg_config(&mut pkg_env);
+            // The SDK's `CC` and `CFLAGS` already target the sysroot.
+            cross_host = sysroot.host_triple().or(cross_host);
+            cmake_toolchain_file = sysroot.cmake_toolchain_file();
+        }
+        pkg_env.export(&mut env);
+        let mut dependency_include_paths = Vec::new();
+        let mut dependency_link_paths = Vec::new();
+        let mut dependency_link = LinkDirectives::default();
+        for dependency in &metadata.dependencies {
+            let req = match dependency {
+                Dependency::PkgConfig(req) => req,
+                Dependency::SysCrate(sys_crate) => {
+                    let (include_paths, lib_paths) = sys_crate.paths()?;
+                    dependency_link.extend(sys_crate.link_directives(&lib_paths));
+                    dependency_include_paths.extend(include_paths);
+                    dependency_link_paths.extend(lib_paths);
+                    continue;
+                }
+            };
+            let mut config = pkg_config_for(req, None);
+            let lib = pc_file::with_search_dirs(&req.pkg_config_path, &pkg_env, || {
+                config.cargo_metadata(false).probe(&req.name)
+            })?
+            .map_err(|err| ErrorKind::PkgConfigProbe {
+                library: req.name.clone(),
+                err: Box::new(err),
+            })?;
+            let statik = link::pkg_config_static(&req.name);
+            let link = LinkDirectives::from_pkg_config(&lib, statik, &target);
+            if let Some(sysroot) = &sysroot {
+                sysroot.check(&req.name, &link)?;
+            }
+            allowed_roots::check_link(&req.name, &link)?;
+            dependency_link.extend(link);
+            let what = format!("A search path of `{}`", req.name);
+            for path in lib.include_paths {
+                let path = utf8_path(path, &what)?;
+                allowed_roots::check(&req.name, &path)?;
+                dependency_include_paths.push(path);
+            }
+            for path in lib.link_paths {
+                dependency_link_paths.push(utf8_path(path, &what)?);
+            }
+        }
+        add_search_flags(&mut env, "CPPFLAGS", "-I", &dependency_include_paths);
+        add_search_flags(&mut env, "LDFLAGS", "-L", &dependency_link_paths);
+        illumos::configure(&target, &mut cflags, &mut env, &dependency_link_paths);
+        let mut feature_options = features::resolve(&metadata.features);
+        if let Some(assembler) = &metadata.assembler {
+            let path = match env.iter().find(|(var, _)| var == "PATH") {
+                Some((_, path)) => path.into(),
+                None => std::env::var_os("PATH").unwrap_or_default(),
+            };
+            feature_options.extend(assembler.resolve(&target, &path)?);
+        }
+        let source_path = source.fetch(fetch)?;
+        let build_both = metadata.linkage.build_both();
+        // A hermetic build doesn't share its work with others outside `OUT_DIR`.
+        let (out_dir, work_dir_lock) = if build_both && !hermetic::enabled()? {
+            let dir = build_dir::shared_work_dir(
+                &metadata.linkage,
+                &metadata.digest,
+                &source_path,
+                &cflags,
+                &env,
+            )?;
+            let lock = build_dir::lock(&dir)?;
+            (dir, Some(lock))
+        } else {
+            (build_dir::work_dir()?, None)
+        };
+        hermetic::configure(&out_dir, &mut env)?;
+        let built = build_both && out_dir.join(build_dir::BUILT_STAMP).is_file();
+        event!(debug, %out_dir, built, "looking up the shared build");
+        let reproducible = reproducible::enabled(metadata.reproducible)?;
+        if reproducible || path_remap::enabled(metadata.remap_paths)? {
+            path_remap::configure(flavor, &source_path, &out_dir, &mut cflags);
+        }
+        if reproducible {
+            reproducible::configure(flavor, &target, &mut cflags, &mut env)?;
         }
+        Ok(VendoredBuildContext {
+            source_path,
+            install_prefix: out_dir.join("prefix"),
+            out_dir,
+            cflags,
+            env,
+            link_kind,
+            build_both,
+            built,
+            dev_rpath: metadata.dev_rpath,
+            cross_host,
+            cmake_toolchain_file,
+            feature_options,
+            dependency_include_paths,
+            dependency_link_paths,
+            dependency_link,
+            dev_link: Rc::default(),
+            work_dir_lock,
+            target,
+        })
+    }
+
+    /// Gets the configuration of the target, for choosing build options by OS, architecture
+    /// or target features without parsing the target triple.
+    pub fn target(&self) -> &TargetInfo {
+        &self.target
     }
 
     /// Gets the local path to the vendored source.
     pub fn source_path(&self) -> &Utf8PathBuf {
         &self.source_path
     }
+
+    /// Gets the prefix the vendored build installs into, `OUT_DIR/prefix`.
+    ///
+    /// Libraries go into its `lib`, headers into `include` and executables into `bin`.
+    /// The [`Autotools`] and [`CMake`] drivers install there, and buildkit emits the link
+    /// search path and the `root` and `include` metadata of dependents from it.
+    pub fn install_prefix(&self) -> &Utf8PathBuf {
+        &self.install_prefix
+    }
+
+    /// Gets the C/C++ compiler flags matching the cargo profile.
+    ///
+    /// These map `OPT_LEVEL`, `DEBUG`, debug assertions and the panic strategy
+    /// to the target compiler's flags, so a debug Rust build gets unoptimized C code with
+    /// debug info and a release build gets optimized C code with `NDEBUG`.
+    /// Sanitizers enabled with `-Zsanitizer` are passed on as `-fsanitize`.
+    /// The [`Autotools`] and [`CMake`] drivers apply them automatically.
+    pub fn cflags(&self) -> &[String] {
+        &self.cflags
+    }
+
+    /// Gets the environment for the build tools, such as `CC` and `AR`,
+    /// when buildkit needs to override them.
+    ///
+    /// For cross builds configured through `.cargo/config.toml`, the target linker
+    /// is used as `CC` when it's a compiler driver, its toolchain's `ar` as `AR`,
+    /// and `-L` paths from the rustflags are passed in `LDFLAGS`.
+    ///
+    /// The [`Autotools`] and [`CMake`] drivers apply them automatically.
+    pub fn env(&self) -> &[(String, String)] {
+        &self.env
+    }
+
+    /// Gets the include paths of the system libraries listed in
+    /// `package.metadata.buildkit.dependencies`, which were probed before the vendored build.
+    ///
+    /// The [`Autotools`] and [`CMake`] drivers pass them on automatically.
+    pub fn dependency_include_paths(&self) -> &[Utf8PathBuf] {
+        &self.dependency_include_paths
+    }
+
+    /// Gets the library search paths of the system libraries listed in
+    /// `package.metadata.buildkit.dependencies`.
+    ///
+    /// The [`Autotools`] and [`CMake`] drivers pass them on automatically.
+    pub fn dependency_link_paths(&self) -> &[Utf8PathBuf] {
+        &self.dependency_link_paths
+    }
+
+    /// Creates a [`cc::Build`] with [`cflags`](Self::cflags) and [`env`](Self::env) applied,
+    /// the include paths of the `dependencies`, and the `cc` definitions of the `features` table.
+    #[cfg(feature = "cc")]
+    pub fn cc_build(&self) -> cc::Build {
+        let mut build = cc::Build::new();
+        for flag in &self.cflags {
+            build.flag(flag);
+        }
+        for path in &self.dependency_include_paths {
+            build.include(path);
+        }
+        for (name, value) in &self.feature_options.cc {
+            build.define(name, value.as_str());
+        }
+        for (var, value) in &self.env {
+            match var.as_str() {
+                "CC" => {
+                    build.compiler(value);
+                }
+                "AR" => {
+                    build.archiver(value);
+                }
+                _ => {}
+            }
+        }
+        build
+    }
+
+    /// Gets the linkage selected by `package.metadata.buildkit.linkage`.
+    ///
+    /// The vendored build should produce (and emit `cargo:rustc-link-lib` for) this kind of library.
+    /// `None` means the metadata leaves it up to the build script.
+    pub fn link_kind(&self) -> Option<LinkKind> {
+        self.link_kind
+    }
+
+    /// Whether the vendored build is to produce both static and shared libraries, as
+    /// `build-both` in `package.metadata.buildkit.linkage` asks, of which
+    /// [`link_kind`](Self::link_kind) selects the one to link.
+    ///
+    /// The [`Autotools`] and [`CMake`] drivers ask the build system for both.
+    pub fn build_both(&self) -> bool {
+        self.build_both
+    }
+
+    /// Whether the libraries were already built and installed into the
+    /// [`install_prefix`](Self::install_prefix) by a build selecting the other linkage,
+    /// with [`build_both`](Self::build_both).
+    ///
+    /// The [`Autotools`] and [`CMake`] drivers then return right away; other build scripts
+    /// can skip their build too, but still emit the `cargo:rustc-link-lib` for the linkage.
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+
+    /// Makes the shared libraries in `lib_dir` loadable through the rpath.
+    ///
+    /// On Apple targets this rewrites each `.dylib`'s install name to `@rpath/<file name>`
+    /// with `install_name_tool`. It does nothing elsewhere.
+    /// Use it together with `package.metadata.buildkit.rpath`.
+    pub fn fix_install_names(&self, lib_dir: impl AsRef<Utf8Path>) -> Result<(), Error> {
+        rpath::fix_install_names(&self.target, lib_dir.as_ref())
+    }
+
+    /// Adds `lib_dir` to the rpath of this package's binaries and tests in non-release profiles,
+    /// if `package.metadata.buildkit.dev-rpath` is enabled.
+    ///
+    /// Call this with the directory the shared libraries were built into,
+    /// so `cargo test` and `cargo run` work without setting `LD_LIBRARY_PATH`.
+    pub fn dev_rpath(&self, lib_dir: impl AsRef<Utf8Path>) -> Result<(), Error> {
+        if !self.dev_rpath {
+            return Ok(());
+        }
+        rpath::add_dev_rpath(
+            &self.target,
+            lib_dir.as_ref(),
+            &mut self.dev_link.borrow_mut(),
+        )
+    }
+}
+
+/// The result of a successful [`BuildKit::build`].
+#[derive(Debug)]
+pub struct BuildOutcome {
+    found: bool,
+    version: Option<Version>,
+    provider: Option<String>,
+    vendored: bool,
+    variables: BTreeMap<String, String>,
+    link: LinkDirectives,
+}
+
+impl BuildOutcome {
+    /// Creates the outcome for a library that was found, e.g. by a [`ProbeBackend`].
+    pub fn found(version: Option<Version>) -> BuildOutcome {
+        BuildOutcome {
+            found: true,
+            version,
+            provider: None,
+            vendored: false,
+            variables: BTreeMap::new(),
+            link: LinkDirectives::default(),
+        }
+    }
+
+    fn missing() -> BuildOutcome {
+        BuildOutcome {
+            found: false,
+            version: None,
+            provider: None,
+            vendored: false,
+            variables: BTreeMap::new(),
+            link: LinkDirectives::default(),
+        }
+    }
+
+    /// Whether the library was found.
+    ///
+    /// This is always `true` unless the requirement is marked `optional`.
+    pub fn is_found(&self) -> bool {
+        self.found
+    }
+
+    /// Gets the version of the library that was resolved.
+    ///
+    /// This is useful for picking pregenerated bindings or emitting API-level cfgs.
+    /// pkg-config reports the version from the `.pc` file,
+    /// and vendored builds report the `version` declared for their source.
+    /// vcpkg reports the port's version only when its requirement has a `version-req` or
+    /// an `exclude`.
+    pub fn version(&self) -> Option<&Version> {
+        self.version.as_ref()
+    }
+
+    /// Gets the name of the provider that satisfied the dependency,
+    /// if `package.metadata.buildkit.providers` lists alternatives.
+    pub fn provider(&self) -> Option<&str> {
+        self.provider.as_deref()
+    }
+
+    /// Gets a pkg-config variable of the library, e.g. `plugindir`,
+    /// if the requirement lists it in `variables` and the `.pc` file defines it.
+    pub fn variable(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(String::as_str)
+    }
+
+    /// Gets the libraries to link, as `cargo:rustc-link-lib` values such as `static=z`.
+    ///
+    /// These are the libraries pkg-config or vcpkg reported, those of a prebuilt binary or
+    /// the vendored build's dependencies, and runtimes such as the C++ standard library,
+    /// emitted after the [`post_probe`](BuildKit::post_probe) hook. Libraries a vendored
+    /// build script or a custom backend links itself aren't included.
+    pub fn link_libs(&self) -> &[String] {
+        &self.link.libs
+    }
+
+    /// Gets the libraries to link for the [`post_probe`](BuildKit::post_probe) hook to change.
+    pub fn link_libs_mut(&mut self) -> &mut Vec<String> {
+        &mut self.link.libs
+    }
+
+    /// Gets the library search paths, as `cargo:rustc-link-search` values such as
+    /// `native=/usr/lib`.
+    pub fn link_search_paths(&self) -> &[String] {
+        &self.link.search_paths
+    }
+
+    /// Gets the library search paths for the [`post_probe`](BuildKit::post_probe) hook to change.
+    pub fn link_search_paths_mut(&mut self) -> &mut Vec<String> {
+        &mut self.link.search_paths
+    }
 }
 
 /// Probes system libraries via the [`vcpkg`] crate.
@@ -278,23 +2342,268 @@ impl VendoredBuildContext {
 /// it appears that this crate doesn't really call into the [`vcpkg` from Microsoft][ms-vcpkg].
 ///
 /// [ms-vcpkg]: https://github.com/microsoft/vcpkg
-fn try_vcpkg(req: &VcpkgRequirement) -> Result<(), Error> {
+fn try_vcpkg(
+    target: &TargetInfo,
+    req: &VcpkgRequirement,
+    vcpkg_config: &VcpkgConfig,
+    link_kind: Option<LinkKind>,
+) -> Result<BuildOutcome, Error> {
     let name = req.name.as_str();
+    event!(debug, port = name, "probing with vcpkg");
     emit_no_vendor(name);
+    sanitizer::warn_uninstrumented(name);
     let mut config = vcpkg::Config::new();
-    config.emit_includes(true);
+    config.emit_includes(true).cargo_metadata(false);
+    if std::env::var_os("VCPKGRS_TRIPLET").is_none() {
+        if let Some(triplet) = vcpkg_triplet(target, link_kind) {
+            config.target_triplet(triplet);
+        }
+    }
     for lib in &req.libs {
         config.lib_names(&lib.lib_name, &lib.dll_name);
     }
-    let _ = config.find_package(name).map_err(ErrorKind::VcpkgError)?;
-    Ok(())
+    vcpkg_config.apply(&mut config)?;
+    let library = config
+        .find_package(name)
+        .map_err(|err| ErrorKind::VcpkgProbe {
+            port: name.to_owned(),
+            err: Box::new(err),
+        })?;
+    let triplet = library.vcpkg_triplet.as_str();
+    let mut version = None;
+    if !req.features.is_empty() || req.version_req.is_some() || !req.exclude.is_empty() {
+        let root = vcpkg::find_vcpkg_root(&config).map_err(ErrorKind::VcpkgError)?;
+        let root = utf8_path(root, "The vcpkg root")?;
+        vcpkg_port::check_features(&root, name, triplet, &req.features, vcpkg_config)?;
+        if req.version_req.is_some() || !req.exclude.is_empty() {
+            let installed = vcpkg_port::installed_version(&root, name, triplet)?
+                .map(|installed| Version::parse(&installed))
+                .transpose()?;
+            if let Some(version_req) = &req.version_req {
+                match &installed {
+                    Some(installed) if version_req.matches(installed)? => {}
+                    installed => {
+                        return Err(ErrorKind::VcpkgVersionMismatch {
+                            port: name.to_owned(),
+                            triplet: triplet.to_owned(),
+                            found: installed.as_ref().map_or_else(
+                                || "an unknown version".to_owned(),
+                                |v| format!("version {v}"),
+                            ),
+                            required: version_req.to_string(),
+                        }
+                        .into());
+                    }
+                }
+            }
+            version_range::check_excluded(name, installed.as_ref(), &req.exclude)?;
+            version = installed;
+        }
+    }
+    check_vcpkg_crt(target, triplet)?;
+    vcpkg_port::emit_port_files(&library, name);
+    let mut outcome = BuildOutcome::found(version);
+    outcome.link = LinkDirectives::from_vcpkg(&library);
+    Ok(outcome)
+}
+
+fn is_windows_gnu(target: &str) -> bool {
+    target.ends_with("-windows-gnu") || target.ends_with("-windows-gnullvm")
+}
+
+/// Checks that an MSVC triplet's libraries use the same CRT as the Rust code.
+///
+/// `*-windows-static` triplets link the static CRT (`/MT`); `*-windows-static-md` and
+/// `*-windows` ones link the dynamic CRT (`/MD`).
+fn check_vcpkg_crt(target: &TargetInfo, triplet: &str) -> Result<(), Error> {
+    if !triplet.contains("-windows") {
+        return Ok(());
+    }
+    let triplet_static = triplet.contains("-static") && !triplet.contains("-static-md");
+    let crt_static = cflags::crt_static(target);
+    if triplet_static == crt_static {
+        return Ok(());
+    }
+    Err(ErrorKind::CrtMismatch {
+        triplet: triplet.to_owned(),
+        crt_static,
+    }
+    .into())
+}
+
+/// Picks the vcpkg triplet for the target and linkage,
+/// or `None` to let vcpkg-rs infer it.
+fn vcpkg_triplet(target: &TargetInfo, link_kind: Option<LinkKind>) -> Option<String> {
+    let triple = target.triple();
+    let arch = match triple.split('-').next()? {
+        "x86_64" => "x64",
+        "i686" | "i586" => "x86",
+        "aarch64" => "arm64",
+        _ => return None,
+    };
+    if is_windows_gnu(triple) {
+        // vcpkg-rs only infers triplets for MSVC targets.
+        // Some people seems to use vcpkg with mingw: https://www.reddit.com/r/cpp/comments/p1655e/comment/h8bly7v
+        // vcpkg-rs assumes `.a` libraries for non-MSVC triplets, so only the static ones work.
+        return Some(format!("{arch}-mingw-static"));
+    }
+    if !triple.ends_with("-windows-msvc") {
+        return None;
+    }
+    match link_kind? {
+        LinkKind::Dylib => Some(format!("{arch}-windows")),
+        LinkKind::Static => {
+            let suffix = if cflags::crt_static(target) {
+                "static"
+            } else {
+                "static-md"
+            };
+            Some(format!("{arch}-windows-{suffix}"))
+        }
+    }
+}
+
+/// Adds `-I` or `-L` flags for `paths` to the `var` flags of the build environment,
+/// after those already set there or by the user.
+fn add_search_flags(env: &mut Vec<(String, String)>, var: &str, flag: &str, paths: &[Utf8PathBuf]) {
+    if paths.is_empty() {
+        return;
+    }
+    let flags = paths
+        .iter()
+        .map(|path| format!("{flag}{path}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    append_env_flags(env, var, flags);
+}
+
+/// Adds `flags` to the `var` flags of the build environment,
+/// after those already set there or by the user.
+fn append_env_flags(env: &mut Vec<(String, String)>, var: &str, flags: String) {
+    if let Some((_, value)) = env.iter_mut().find(|(name, _)| name == var) {
+        value.push(' ');
+        value.push_str(&flags);
+        return;
+    }
+    println!("cargo:rerun-if-env-changed={var}");
+    let value = match std::env::var(var) {
+        Ok(user) => format!("{user} {flags}"),
+        Err(_) => flags,
+    };
+    env.push((var.to_owned(), value));
+}
+
+/// How pkg-config probes on the platform the build runs on and for.
+struct PkgConfigPlatform {
+    env: PkgConfigEnv,
+    /// The sysroot of the SDK the build cross-compiles with, if any.
+    sysroot: Option<Sysroot>,
+    /// Whether the build targets Emscripten, whose sysroot has the `.pc` files.
+    emscripten: bool,
+    /// The prefixes of the keg-only Homebrew formulas providing the library.
+    kegs: Vec<std::path::PathBuf>,
 }
 
 /// Probes system libraries via the [`pkg-config`] crate.
-fn try_pkg_config(req: &PkgConfigRequirement) -> Result<(), Error> {
+fn try_pkg_config(
+    target: &TargetInfo,
+    req: &PkgConfigRequirement,
+    link_kind: Option<LinkKind>,
+    pkg_env: &PkgConfigEnv,
+) -> Result<BuildOutcome, Error> {
     let name = req.name.as_str();
+    event!(debug, module = name, "probing with pkg-config");
     emit_no_vendor(name);
+    sanitizer::warn_uninstrumented(name);
+    let mut config = pkg_config_for(req, link_kind);
+    pc_file::with_search_dirs(&req.pkg_config_path, pkg_env, || {
+        let lib = config
+            .cargo_metadata(false)
+            .probe(&req.name)
+            .map_err(|err| ErrorKind::PkgConfigProbe {
+                library: req.name.clone(),
+                err: Box::new(err),
+            })?;
+        // pkg-config itself checks exact versions and ranges; any-of requirements and
+        // exclusions need the version parsed.
+        let compared = matches!(req.version_req, Some(PkgConfigVersionReq::Any { .. }))
+            || !req.exclude.is_empty();
+        let version = match Version::parse(&lib.version) {
+            Ok(version) => Some(version),
+            Err(err) if !compared => {
+                warning!("the version of `{name}` is unknown: {err}");
+                None
+            }
+            Err(err) => return Err(err),
+        };
+        if let Some(version_req @ PkgConfigVersionReq::Any { .. }) = &req.version_req {
+            if !version
+                .as_ref()
+                .map_or(Ok(false), |v| version_req.matches(v))?
+            {
+                return Err(ErrorKind::VersionMismatch {
+                    library: name.to_owned(),
+                    found: lib.version.clone(),
+                    required: version_req.to_string(),
+                }
+                .into());
+            }
+        }
+        version_range::check_excluded(name, version.as_ref(), &req.exclude)?;
+        for include in &lib.include_paths {
+            let include = utf8_path(include.clone(), &format!("An include path of `{name}`"))?;
+            allowed_roots::check(name, &include)?;
+            println!("cargo:include={include}");
+        }
+        // pkg-config leaves out the system's directories, so the `.pc` file tells whether
+        // the library is the build machine's.
+        if allowed_roots::enabled()? {
+            let dir =
+                pkg_config::get_variable(name, "pcfiledir").map_err(ErrorKind::PkgConfigError)?;
+            allowed_roots::check(name, Utf8Path::new(&dir))?;
+        }
+        let mut outcome = BuildOutcome::found(version);
+        let statik =
+            link_kind.map_or_else(|| link::pkg_config_static(name), |k| k == LinkKind::Static);
+        outcome.link = LinkDirectives::from_pkg_config(&lib, statik, target);
+        if let Some(check) = &req.abi_check {
+            let paths = |paths: &[std::path::PathBuf]| -> Vec<Utf8PathBuf> {
+                paths
+                    .iter()
+                    .filter_map(|path| Utf8PathBuf::from_path_buf(path.clone()).ok())
+                    .collect()
+            };
+            check.verify(
+                target,
+                name,
+                &paths(&lib.include_paths),
+                outcome.version.as_ref(),
+                &lib.libs,
+                &paths(&lib.link_paths),
+            )?;
+        }
+        for PcVariable(variable) in &req.variables {
+            let value =
+                pkg_config::get_variable(&req.name, variable).map_err(ErrorKind::PkgConfigError)?;
+            // pkg-config prints nothing for variables the `.pc` file doesn't define.
+            if !value.is_empty() {
+                println!("cargo:pc-{variable}={value}");
+                outcome.variables.insert(variable.clone(), value);
+            }
+        }
+        if !req.gir.is_empty() {
+            gir::probe(name, &req.gir, &mut outcome)?;
+        }
+        Ok(outcome)
+    })?
+}
+
+/// Configures a pkg-config probe for the requirement's version and the linkage.
+fn pkg_config_for(req: &PkgConfigRequirement, link_kind: Option<LinkKind>) -> pkg_config::Config {
     let mut config = pkg_config::Config::new();
+    if let Some(link_kind) = link_kind {
+        config.statik(link_kind == LinkKind::Static);
+    }
 
     if let Some(version_req) = &req.version_req {
         match version_req {
@@ -310,14 +2619,58 @@ fn try_pkg_config(req: &PkgConfigRequirement) -> Result<(), Error> {
             PkgConfigVersionReq::Exact { exact } => {
                 config.exactly_version(&exact);
             }
+            // pkg-config takes a single interval, so the ranges are checked after probing.
+            PkgConfigVersionReq::Any { .. } => {}
         }
     }
+    config
+}
+
+/// Whether pkg-config has a `.pc` file for the module, of any version.
+fn pkg_config_knows(req: &PkgConfigRequirement, pkg_env: &PkgConfigEnv) -> bool {
+    pc_file::with_search_dirs(&req.pkg_config_path, pkg_env, || {
+        pkg_config::Config::new()
+            .cargo_metadata(false)
+            .env_metadata(false)
+            .probe(&req.name)
+            .is_ok()
+    })
+    .unwrap_or(false)
+}
 
-    let lib = config.probe(&req.name).map_err(ErrorKind::PkgConfigError)?;
-    for include in &lib.include_paths {
-        println!("cargo:include={}", include.display());
+/// Warns about which of the library's runtime and development files are installed,
+/// if only some are.
+fn warn_partial_install(target: &TargetInfo, req: &PkgConfigRequirement) {
+    let (libs, headers) = match &req.ldconfig_fallback {
+        Some(fallback) => (&fallback.libs[..], &fallback.headers[..]),
+        None => (&[][..], &[][..]),
+    };
+    if let Ok(Some(diagnosis)) = dev_files::diagnose(target, &req.name, libs, headers) {
+        warning!("{diagnosis}");
+    }
+}
+
+/// Quietly re-probes a library that failed its version requirement,
+/// returning the found and required versions if it is installed but too old.
+fn outdated_version(
+    req: &PkgConfigRequirement,
+    pkg_env: &PkgConfigEnv,
+) -> Option<(Version, Version)> {
+    let required = Version::parse(req.version_req.as_ref()?.min()?).ok()?;
+    let lib = pc_file::with_search_dirs(&req.pkg_config_path, pkg_env, || {
+        pkg_config::Config::new()
+            .cargo_metadata(false)
+            .env_metadata(false)
+            .probe(&req.name)
+    })
+    .ok()?
+    .ok()?;
+    let found = Version::parse(&lib.version).ok()?;
+    if found < required {
+        Some((found, required))
+    } else {
+        None
     }
-    Ok(())
 }
 
 fn emit_no_vendor(lib_name: &str) {
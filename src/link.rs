@@ -0,0 +1,283 @@
+//! Link directives of the library and its runtimes.
+//!
+//! pkg-config and vcpkg are probed without emitting `cargo:rustc-link-*` themselves.
+//! Their directives are collected here along with those of prebuilt binaries and runtimes,
+//! so the [`post_probe`] hook can adjust them, and they're emitted once, without duplicates.
+//!
+//! [`post_probe`]: crate::BuildKit::post_probe
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{illumos, musl, run_command, termux, TargetInfo};
+
+/// The `rustc-link-search`, `rustc-link-lib` and `rustc-link-arg` values of a library.
+#[derive(Debug, Default)]
+pub(crate) struct LinkDirectives {
+    /// Search paths, e.g. `native=/usr/lib`.
+    pub(crate) search_paths: Vec<String>,
+    /// Libraries, e.g. `static=z` or `framework=Security`.
+    pub(crate) libs: Vec<String>,
+    /// Linker arguments.
+    pub(crate) args: Vec<String>,
+    /// Modifiers of libraries by name, e.g. `("foo", "+whole-archive,-bundle")`.
+    pub(crate) modifiers: Vec<(String, String)>,
+}
+
+impl LinkDirectives {
+    /// Collects the directives the pkg-config crate would emit for `lib`.
+    pub(crate) fn from_pkg_config(
+        lib: &pkg_config::Library,
+        statik: bool,
+        target: &TargetInfo,
+    ) -> LinkDirectives {
+        let mut link = LinkDirectives::default();
+        for path in &lib.link_paths {
+            link.search_paths.push(format!("native={}", path.display()));
+        }
+        for path in &lib.framework_paths {
+            link.search_paths
+                .push(format!("framework={}", path.display()));
+        }
+        for name in &lib.libs {
+            if name.starts_with(':') {
+                link.args.push(format!("-l{name}"));
+            } else if statik && static_available(target, name, &lib.link_paths) {
+                link.libs.push(format!("static={name}"));
+            } else {
+                link.libs.push(name.clone());
+            }
+        }
+        for file in &lib.link_files {
+            if let (Some(dir), Some(name)) = (file.parent(), file_lib_name(file)) {
+                link.search_paths.push(dir.display().to_string());
+                link.libs.push(name);
+            }
+        }
+        for framework in &lib.frameworks {
+            link.libs.push(format!("framework={framework}"));
+        }
+        for args in &lib.ld_args {
+            link.args.push(format!("-Wl,{}", args.join(",")));
+        }
+        link
+    }
+
+    /// Collects the directives from the `cargo:` lines vcpkg would print,
+    /// printing the others, such as `cargo:include`, right away.
+    pub(crate) fn from_vcpkg(lib: &vcpkg::Library) -> LinkDirectives {
+        let mut link = LinkDirectives::default();
+        for line in &lib.cargo_metadata {
+            let directive = line.strip_prefix("cargo:").and_then(|d| d.split_once('='));
+            match directive {
+                Some(("rustc-link-search", value)) => link.search_paths.push(value.to_owned()),
+                Some(("rustc-link-lib", value)) => link.libs.push(value.to_owned()),
+                Some(("rustc-link-arg", value)) => link.args.push(value.to_owned()),
+                _ => println!("{line}"),
+            }
+        }
+        link
+    }
+
+    /// Appends the directives of another library.
+    pub(crate) fn extend(&mut self, other: LinkDirectives) {
+        self.search_paths.extend(other.search_paths);
+        self.libs.extend(other.libs);
+        self.args.extend(other.args);
+        self.modifiers.extend(other.modifiers);
+    }
+
+    /// Drops the search paths the linker searches anyway, e.g. `/usr/lib`.
+    ///
+    /// Passing them with `-L` moves them ahead of other default paths,
+    /// where they can shadow the intended copy of an unrelated library.
+    pub(crate) fn filter_system_paths(&mut self, target: &TargetInfo) {
+        let system_dirs: Vec<String> = system_lib_dirs(target)
+            .iter()
+            .map(|dir| canonicalize(dir))
+            .collect();
+        self.search_paths.retain(|path| {
+            let (kind, path) = split_search_path(path);
+            !matches!(kind, "native" | "all") || !system_dirs.contains(&canonicalize(path))
+        });
+    }
+
+    /// Prints the directives for cargo, each once.
+    ///
+    /// Search paths are canonicalized and keep their first position. Libraries keep their
+    /// last one, since a static library has to come after those that use it, and the first
+    /// kind given for them, with their modifiers.
+    pub(crate) fn emit(&self) {
+        let mut search_paths: Vec<(&str, String)> = Vec::new();
+        for path in &self.search_paths {
+            let (kind, path) = split_search_path(path);
+            let path = canonicalize(path);
+            if !search_paths.contains(&(kind, path.clone())) {
+                search_paths.push((kind, path));
+            }
+        }
+        for (kind, path) in &search_paths {
+            println!("cargo:rustc-link-search={kind}={path}");
+        }
+
+        let mut libs: Vec<(Option<&str>, &str)> = Vec::new();
+        for lib in &self.libs {
+            let (kind, name) = match lib.split_once('=') {
+                Some((kind, name)) => (Some(kind), name),
+                None => (None, lib.as_str()),
+            };
+            let mut kind = kind;
+            if let Some(i) = libs.iter().position(|(_, other)| *other == name) {
+                let (other_kind, _) = libs.remove(i);
+                match (kind, other_kind) {
+                    (Some(kind), Some(other_kind)) if kind != other_kind => warning!(
+                        "`{name}` is linked as both `{other_kind}` and `{kind}`, \
+                         using `{other_kind}`"
+                    ),
+                    _ => {}
+                }
+                kind = other_kind.or(kind);
+            }
+            libs.push((kind, name));
+        }
+        for (kind, name) in libs {
+            let modifiers = self
+                .modifiers
+                .iter()
+                .filter(|(lib, _)| lib == name)
+                .map(|(_, modifiers)| modifiers.as_str())
+                .collect::<Vec<_>>()
+                .join(",");
+            match (kind, modifiers.as_str()) {
+                (Some(kind), "") => println!("cargo:rustc-link-lib={kind}={name}"),
+                (None, "") => println!("cargo:rustc-link-lib={name}"),
+                (kind, modifiers) => {
+                    // Modifiers need a kind, and rustc's default one is `dylib`.
+                    let kind = kind.unwrap_or("dylib");
+                    let (static_only, others): (Vec<&str>, Vec<&str>) = modifiers
+                        .split(',')
+                        .partition(|modifier| matches!(*modifier, "+whole-archive" | "+bundle"));
+                    if kind == "static" || static_only.is_empty() {
+                        println!("cargo:rustc-link-lib={kind}:{modifiers}={name}");
+                        continue;
+                    }
+                    warning!(
+                        "`{}` only applies to static libraries, \
+                         linking `{name}` as `{kind}` without it",
+                        static_only.join(",")
+                    );
+                    if others.is_empty() {
+                        println!("cargo:rustc-link-lib={kind}={name}");
+                    } else {
+                        println!("cargo:rustc-link-lib={kind}:{}={name}", others.join(","));
+                    }
+                }
+            }
+        }
+
+        let mut args: Vec<&String> = Vec::new();
+        for arg in &self.args {
+            if !args.contains(&arg) {
+                args.push(arg);
+            }
+        }
+        for arg in args {
+            println!("cargo:rustc-link-arg={arg}");
+        }
+    }
+}
+
+/// Splits a search path into its kind and path, `all` if it doesn't say.
+fn split_search_path(path: &str) -> (&str, &str) {
+    match path.split_once('=') {
+        Some((kind @ ("native" | "dependency" | "crate" | "framework" | "all"), path)) => {
+            (kind, path)
+        }
+        _ => ("all", path),
+    }
+}
+
+/// Gets the directories the linker searches by default, as pkg-config knows them
+/// from `PKG_CONFIG_SYSTEM_LIBRARY_PATH` or its own configuration.
+pub(crate) fn system_lib_dirs(target: &TargetInfo) -> Vec<String> {
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_SYSTEM_LIBRARY_PATH");
+    let dirs = std::env::var("PKG_CONFIG_SYSTEM_LIBRARY_PATH").or_else(|_| {
+        let pkg_config = std::env::var("PKG_CONFIG").unwrap_or_else(|_| "pkg-config".to_owned());
+        run_command(Command::new(pkg_config).args(["--variable=pc_system_libdirs", "pkg-config"]))
+    });
+    match dirs {
+        Ok(dirs) if !dirs.is_empty() => std::env::split_paths(&dirs)
+            .filter_map(|dir| dir.into_os_string().into_string().ok())
+            .collect(),
+        _ => {
+            let mut dirs = vec!["/usr/lib", "/usr/lib64", "/lib", "/lib64"];
+            if illumos::is_target(target) {
+                dirs.extend(illumos::SYSTEM_LIB_DIRS);
+            }
+            let mut dirs: Vec<String> = dirs.into_iter().map(String::from).collect();
+            if let Ok(Some(prefix)) = termux::prefix(target) {
+                dirs.extend(prefix.join("lib").into_os_string().into_string());
+            }
+            dirs
+        }
+    }
+}
+
+/// Resolves `.`, `..` and symlinks in an existing search path.
+///
+/// Windows paths are left alone, as canonicalizing them there gives verbatim `\\?\` paths
+/// that not all linkers accept.
+fn canonicalize(path: &str) -> String {
+    if cfg!(windows) {
+        return path.to_owned();
+    }
+    std::fs::canonicalize(path)
+        .ok()
+        .and_then(|path| path.into_os_string().into_string().ok())
+        .unwrap_or_else(|| path.to_owned())
+}
+
+/// Whether pkg-config links the `name` module statically without an explicit linkage,
+/// from `<NAME>_STATIC` and `PKG_CONFIG_ALL_STATIC` and their `DYNAMIC` counterparts.
+pub(crate) fn pkg_config_static(name: &str) -> bool {
+    let name = name.to_uppercase().replace('-', "_");
+    let set = |var: &str| std::env::var_os(var).is_some();
+    if set(&format!("{name}_DYNAMIC")) {
+        false
+    } else if set(&format!("{name}_STATIC")) {
+        true
+    } else {
+        !set("PKG_CONFIG_ALL_DYNAMIC") && set("PKG_CONFIG_ALL_STATIC")
+    }
+}
+
+/// Whether a static `name` library is in `dirs` outside the system prefix,
+/// the way the pkg-config crate decides to link it statically.
+fn static_available(target: &TargetInfo, name: &str, dirs: &[PathBuf]) -> bool {
+    // Static musl binaries can't use system shared libraries either.
+    let system_root = if cfg!(windows) || musl::links_statically(target) {
+        None
+    } else {
+        Some("/usr")
+    };
+    let mut file_names = vec![format!("lib{name}.a")];
+    if cfg!(windows) {
+        file_names.push(format!("{name}.lib"));
+    }
+    dirs.iter().any(|dir| {
+        file_names.iter().any(|file| dir.join(file).exists())
+            && !system_root.map_or(false, |root| dir.starts_with(root))
+    })
+}
+
+/// Gets the library name to link a library file by, e.g. `foo` for `libfoo.so.1`.
+fn file_lib_name(file: &Path) -> Option<String> {
+    let file_name = file.file_name()?.to_str()?;
+    let stem = file_name.split('.').next()?;
+    let name = if file_name.ends_with(".lib") {
+        stem
+    } else {
+        stem.strip_prefix("lib")?
+    };
+    Some(name.to_owned())
+}
@@ -0,0 +1,36 @@
+//! Extra linker arguments per target.
+//!
+//! Some platforms need a linker argument no probe reports, e.g.
+//! `-Wl,--allow-multiple-definition` for mingw builds of libraries that define symbols of its
+//! runtime again. This spares `-sys` crates a hand-written build script for them.
+
+use std::collections::BTreeMap;
+
+use crate::link::LinkDirectives;
+use crate::{target_cfg, Error, ErrorKind, TargetInfo};
+
+/// Adds the arguments of the keys the target matches to `link`, checking all of them
+/// so that a mistake shows up on any target.
+///
+/// ```toml
+/// [package.metadata.buildkit.link-args]
+/// 'cfg(all(windows, target_env = "gnu"))' = ["-Wl,--allow-multiple-definition"]
+/// "x86_64-unknown-linux-musl" = ["-Wl,-z,stack-size=1048576"]
+/// ```
+pub(crate) fn apply(
+    link_args: &BTreeMap<String, Vec<String>>,
+    target: &TargetInfo,
+    link: &mut LinkDirectives,
+) -> Result<(), Error> {
+    for (key, args) in link_args {
+        for arg in args {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                return Err(ErrorKind::InvalidLinkArg(arg.clone()).into());
+            }
+        }
+        if target_cfg::matches(key, target)? {
+            link.args.extend(args.iter().cloned());
+        }
+    }
+    Ok(())
+}
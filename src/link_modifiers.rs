@@ -0,0 +1,65 @@
+//! Linking libraries with `rustc-link-lib` modifiers.
+//!
+//! C libraries whose objects register themselves from static constructors, e.g. codec and
+//! plugin registries, lose those objects when linked normally, as nothing references them,
+//! and need `+whole-archive`. Others need `+verbatim` for file names rustc would otherwise
+//! decorate, e.g. MSVC import libraries not named `<name>.lib`, or `-bundle` to stay out of
+//! the rlib and be linked by the final binary.
+
+use serde::Deserialize;
+
+use crate::link::LinkDirectives;
+use crate::{target_modes, Error, ErrorKind, TargetInfo};
+
+/// Modifiers for linking a library.
+///
+/// ```toml
+/// [[package.metadata.buildkit.link-modifiers]]
+/// lib = "gstcoreelements"
+/// modifiers = ["+whole-archive", "-bundle"]
+/// targets = ["*-linux-*"]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct LinkModifiers {
+    /// The library, as named in `rustc-link-lib`.
+    lib: String,
+    modifiers: Vec<String>,
+    /// Target triple patterns the modifiers apply to, or all targets if empty.
+    #[serde(default)]
+    targets: Vec<String>,
+}
+
+/// Records the modifiers of the rules matching the target in `link`, to be emitted with its
+/// libraries.
+pub(crate) fn apply(
+    rules: &[LinkModifiers],
+    target: &TargetInfo,
+    link: &mut LinkDirectives,
+) -> Result<(), Error> {
+    for rule in rules {
+        if !rule.targets.is_empty()
+            && !rule
+                .targets
+                .iter()
+                .any(|pattern| target_modes::glob_matches(pattern, target.triple()))
+        {
+            continue;
+        }
+        for modifier in &rule.modifiers {
+            let name = modifier
+                .strip_prefix(|c| c == '+' || c == '-')
+                .unwrap_or_default();
+            if !matches!(name, "bundle" | "verbatim" | "whole-archive" | "as-needed") {
+                return Err(ErrorKind::InvalidLinkModifier {
+                    lib: rule.lib.clone(),
+                    modifier: modifier.clone(),
+                }
+                .into());
+            }
+        }
+        link.modifiers
+            .push((rule.lib.clone(), rule.modifiers.join(",")));
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Static vs. dynamic linkage selection.
+
+use serde::Deserialize;
+
+use crate::{env_var, Error};
+
+/// How a library is linked.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkKind {
+    /// Link a static archive (`.a`/`.lib`) into the final binary.
+    Static,
+    /// Link a shared library (`.so`/`.dylib`/`.dll`).
+    Dylib,
+}
+
+impl LinkKind {
+    /// The kind as spelled in `cargo:rustc-link-lib=KIND=NAME`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkKind::Static => "static",
+            LinkKind::Dylib => "dylib",
+        }
+    }
+}
+
+/// Linkage preferences from `package.metadata.buildkit.linkage`.
+///
+/// ```toml
+/// [package.metadata.buildkit.linkage]
+/// default = "dylib"
+/// release = "static"
+/// static-feature = "static"
+/// build-both = true
+/// ```
+///
+/// An enabled feature takes precedence over the profile, which takes precedence over `default`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct Linkage {
+    default: Option<LinkKind>,
+    /// Linkage for the `release` profile (and profiles inheriting from it).
+    release: Option<LinkKind>,
+    /// Linkage for every other profile.
+    dev: Option<LinkKind>,
+    /// Cargo feature that selects static linking.
+    static_feature: Option<String>,
+    /// Cargo feature that selects dynamic linking.
+    dylib_feature: Option<String>,
+    /// Whether vendored builds produce both static and shared libraries, kept across
+    /// builds that only differ in the linkage features.
+    #[serde(default)]
+    build_both: bool,
+}
+
+impl Linkage {
+    /// Picks the linkage for the current build,
+    /// or `None` to leave it to each backend's defaults.
+    pub(crate) fn resolve(&self) -> Result<Option<LinkKind>, Error> {
+        if self
+            .static_feature
+            .as_deref()
+            .map_or(false, feature_enabled)
+        {
+            return Ok(Some(LinkKind::Static));
+        }
+        if self.dylib_feature.as_deref().map_or(false, feature_enabled) {
+            return Ok(Some(LinkKind::Dylib));
+        }
+        let by_profile = if env_var("PROFILE")? == "release" {
+            self.release
+        } else {
+            self.dev
+        };
+        Ok(by_profile.or(self.default))
+    }
+
+    pub(crate) fn build_both(&self) -> bool {
+        self.build_both
+    }
+
+    /// Whether `var` is the `CARGO_FEATURE_*` variable of a feature selecting the linkage.
+    pub(crate) fn is_feature_var(&self, var: &str) -> bool {
+        [&self.static_feature, &self.dylib_feature]
+            .iter()
+            .filter_map(|feature| feature.as_deref())
+            .any(|feature| feature_var(feature) == var)
+    }
+}
+
+/// Gets the `CARGO_FEATURE_*` variable of `feature`.
+fn feature_var(feature: &str) -> String {
+    format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"))
+}
+
+pub(crate) fn feature_enabled(feature: &str) -> bool {
+    std::env::var_os(feature_var(feature)).is_some()
+}
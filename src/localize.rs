@@ -0,0 +1,159 @@
+//! Hiding the internals of bundled static libraries.
+//!
+//! Two copies of a common library like zlib in one binary clash at link time.
+//! Localizing every symbol but the library's public API avoids that: the archive's
+//! objects are first combined with a relocatable link, so references between them
+//! still resolve, and then `objcopy --keep-global-symbols` localizes the rest.
+//!
+//! Where the clashing copy is loaded at runtime instead, e.g. a system OpenSSL next to
+//! a vendored BoringSSL, the public API itself has to be renamed with a prefix.
+
+use std::process::Command;
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{cross, io_error, path_var, run_command, symbols, Error, TargetInfo};
+
+/// Localizes all symbols not matching `public` in the static archives in `dirs`.
+///
+/// `public` holds symbol names, which may use `*` and `?` wildcards.
+pub(crate) fn localize(
+    target: &TargetInfo,
+    dirs: &[Utf8PathBuf],
+    public: &[String],
+    env: &[(String, String)],
+) -> Result<(), Error> {
+    if public.is_empty() {
+        return Ok(());
+    }
+    if target.env() == "msvc" {
+        warning!("localizing symbols is not supported for MSVC targets");
+        return Ok(());
+    }
+    for archive in static_archives(dirs)? {
+        localize_archive(target, &archive, public, env)?;
+    }
+    Ok(())
+}
+
+fn localize_archive(
+    target: &TargetInfo,
+    archive: &Utf8Path,
+    public: &[String],
+    env: &[(String, String)],
+) -> Result<(), Error> {
+    let apple = target.is_apple();
+    let combined = archive.with_extension("o");
+    let symbol_list = archive.with_extension("symbols");
+
+    let whole_archive: &[&str] = if apple {
+        &["-Wl,-all_load"]
+    } else {
+        &["-Wl,--whole-archive"]
+    };
+    run_command(
+        Command::new(cross::target_tool(env, "CC", "cc"))
+            .args(["-r", "-nostdlib", "-o"])
+            .arg(&combined)
+            .args(whole_archive)
+            .arg(archive),
+    )?;
+
+    // Mach-O symbols carry a leading underscore.
+    let mut symbols = String::new();
+    for symbol in public {
+        if apple {
+            symbols.push('_');
+        }
+        symbols.push_str(symbol);
+        symbols.push('\n');
+    }
+    std::fs::write(&symbol_list, symbols).map_err(io_error(&symbol_list))?;
+    // GNU objcopy only handles Mach-O through LLVM's.
+    let objcopy = if apple { "llvm-objcopy" } else { "objcopy" };
+    run_command(
+        Command::new(cross::target_tool(env, "OBJCOPY", objcopy))
+            .arg("--wildcard")
+            .arg(format!("--keep-global-symbols={symbol_list}"))
+            .arg(&combined),
+    )?;
+
+    std::fs::remove_file(archive).map_err(io_error(archive))?;
+    run_command(
+        Command::new(cross::target_tool(env, "AR", "ar"))
+            .arg("crs")
+            .arg(archive)
+            .arg(&combined),
+    )?;
+    for file in [&combined, &symbol_list] {
+        std::fs::remove_file(file).map_err(io_error(file))?;
+    }
+    Ok(())
+}
+
+/// Prefixes the global symbols of the static archives in `dirs` with `prefix`,
+/// returning the path of a header that maps the original names to the prefixed ones.
+///
+/// References between the archives are renamed too, so they still link together.
+pub(crate) fn prefix(
+    target: &TargetInfo,
+    dirs: &[Utf8PathBuf],
+    prefix: &str,
+    env: &[(String, String)],
+    install_prefix: &Utf8Path,
+) -> Result<Option<Utf8PathBuf>, Error> {
+    if prefix.is_empty() {
+        return Ok(None);
+    }
+    if target.env() == "msvc" {
+        warning!("prefixing symbols is not supported for MSVC targets");
+        return Ok(None);
+    }
+    let archives = static_archives(dirs)?;
+    let mut defined = BTreeSet::new();
+    for archive in &archives {
+        symbols::defined_symbols(archive, &mut defined)?;
+    }
+
+    // Mach-O symbols carry a leading underscore, which stays in front.
+    let underscore = if target.is_apple() { "_" } else { "" };
+    let mut renames = String::new();
+    let mut header = String::from("/* Generated by buildkit. */\n#pragma once\n");
+    for symbol in &defined {
+        let name = symbol.strip_prefix(underscore).unwrap_or(symbol);
+        if name.starts_with(prefix) {
+            continue;
+        }
+        writeln!(renames, "{underscore}{name} {underscore}{prefix}{name}").unwrap();
+        writeln!(header, "#define {name} {prefix}{name}").unwrap();
+    }
+    let renames_path = path_var("OUT_DIR")?.join("buildkit_redefine_syms.txt");
+    std::fs::write(&renames_path, renames).map_err(io_error(&renames_path))?;
+    let objcopy = if underscore.is_empty() {
+        "objcopy"
+    } else {
+        "llvm-objcopy"
+    };
+    for archive in &archives {
+        run_command(
+            Command::new(cross::target_tool(env, "OBJCOPY", objcopy))
+                .arg(format!("--redefine-syms={renames_path}"))
+                .arg(archive),
+        )?;
+    }
+
+    let include_dir = install_prefix.join("include");
+    std::fs::create_dir_all(&include_dir).map_err(io_error(&include_dir))?;
+    let header_path = include_dir.join("buildkit_prefix_symbols.h");
+    std::fs::write(&header_path, header).map_err(io_error(&header_path))?;
+    Ok(Some(header_path))
+}
+
+fn static_archives(dirs: &[Utf8PathBuf]) -> Result<Vec<Utf8PathBuf>, Error> {
+    let mut libraries = symbols::libraries(dirs)?;
+    libraries.retain(|path| path.extension() == Some("a"));
+    Ok(libraries)
+}
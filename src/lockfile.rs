@@ -0,0 +1,165 @@
+//! Locking the sources of a workspace's builds in `buildkit.lock`.
+//!
+//! Hashes in a crate's metadata pin what its author vouched for, but not what a workspace
+//! builds instead: unverified URLs, unpacked sources in the vendor directory and local
+//! `source-path` trees. `buildkit.lock` records the digest of every source the builds used,
+//! as `cargo buildkit lock` collects them from a build with `BUILDKIT_RECORD_DIGESTS=1`, and
+//! with `BUILDKIT_LOCKED=1` a source that is missing from it or doesn't match fails the
+//! build, like `cargo --locked` does for `Cargo.lock`. Without either, sources aren't hashed
+//! for the lockfile.
+//!
+//! ```toml
+//! [[source]]
+//! url = "https://zlib.net/zlib-1.3.1.tar.gz"
+//! digest = "sha256-..."
+//! ```
+//!
+//! The digest is that of the unpacked tree, as `nix hash path` computes it, so an entry
+//! matches however the source was provided, e.g. from a mirror or the vendor directory.
+//! Local trees are locked by their path.
+
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::MetadataCommand;
+use serde::{Deserialize, Serialize};
+
+use crate::conflicts::build_outputs;
+use crate::{env_overrides, io_error, path_var, Error, ErrorKind};
+
+/// The sources the build scripts of a workspace fetched and their digests, from the build
+/// script outputs cargo keeps in the target directory, or as read from `buildkit.lock`.
+///
+/// For a source fetched by several builds, the latest build counts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lockfile {
+    #[serde(default, rename = "source")]
+    sources: Vec<LockedSource>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LockedSource {
+    url: String,
+    digest: String,
+}
+
+impl Lockfile {
+    /// Collects the sources from the target directory of the workspace at `manifest_path`.
+    pub fn from_manifest(manifest_path: impl AsRef<Utf8Path>) -> Result<Lockfile, Error> {
+        let metadata = MetadataCommand::new()
+            .manifest_path(manifest_path.as_ref())
+            .no_deps()
+            .exec()
+            .map_err(ErrorKind::CargoMetadataError)?;
+        Lockfile::from_target_dir(metadata.target_directory)
+    }
+
+    /// Collects the sources from the build script outputs under `target_dir`,
+    /// for every profile and target.
+    pub fn from_target_dir(target_dir: impl AsRef<Utf8Path>) -> Result<Lockfile, Error> {
+        let mut latest: BTreeMap<String, (SystemTime, String)> = BTreeMap::new();
+        for output in build_outputs(target_dir.as_ref())? {
+            let contents = std::fs::read_to_string(&output).map_err(io_error(&output))?;
+            let modified = std::fs::metadata(&output)
+                .and_then(|metadata| metadata.modified())
+                .map_err(io_error(&output))?;
+            let locked = contents
+                .lines()
+                .filter_map(|line| line.strip_prefix("cargo:source-digest="))
+                .filter_map(|value| value.split_once(' '));
+            for (digest, url) in locked {
+                if latest
+                    .get(url)
+                    .map_or(true, |(previous, _)| modified > *previous)
+                {
+                    latest.insert(url.to_owned(), (modified, digest.to_owned()));
+                }
+            }
+        }
+        let sources = latest
+            .into_iter()
+            .map(|(url, (_, digest))| LockedSource { url, digest })
+            .collect();
+        Ok(Lockfile { sources })
+    }
+
+    /// Renders the lockfile as `buildkit.lock` holds it.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("lockfiles always serialize")
+    }
+
+    /// Reads `BUILDKIT_LOCKFILE` or the closest `buildkit.lock` above `OUT_DIR`, if any.
+    fn load() -> Result<Option<(Utf8PathBuf, Lockfile)>, Error> {
+        let path = match env_overrides::path("BUILDKIT_LOCKFILE")? {
+            Some(path) => path,
+            None => {
+                let out_dir = path_var("OUT_DIR")?;
+                match out_dir
+                    .ancestors()
+                    .map(|dir| dir.join("buildkit.lock"))
+                    .find(|path| path.is_file())
+                {
+                    Some(path) => path,
+                    None => return Ok(None),
+                }
+            }
+        };
+        println!("cargo:rerun-if-changed={path}");
+        let contents = std::fs::read_to_string(&path).map_err(io_error(&path))?;
+        let lockfile = toml::from_str(&contents).map_err(|err| ErrorKind::InvalidLockfile {
+            path: path.clone(),
+            reason: err.message().to_owned(),
+        })?;
+        Ok(Some((path, lockfile)))
+    }
+}
+
+/// With `BUILDKIT_LOCKED=1`, fails unless `buildkit.lock` has the tree digest of the source
+/// fetched from `url` for it, and with that or `BUILDKIT_RECORD_DIGESTS=1` records the digest
+/// for `cargo buildkit lock`. Otherwise the tree isn't hashed at all.
+pub(crate) fn check(
+    url: &str,
+    digest: impl FnOnce() -> Result<String, Error>,
+) -> Result<(), Error> {
+    span!("lockfile", url);
+    let locked = env_overrides::flag("BUILDKIT_LOCKED")?;
+    let record = env_overrides::flag("BUILDKIT_RECORD_DIGESTS")?;
+    if !locked && !record {
+        event!(
+            debug,
+            "neither locked nor recording, not hashing the source"
+        );
+        return Ok(());
+    }
+    let digest = digest()?;
+    println!("cargo:source-digest={digest} {url}");
+    if !locked {
+        event!(debug, %digest, "recorded the source's digest");
+        return Ok(());
+    }
+    let (path, lockfile) = match Lockfile::load()? {
+        Some(loaded) => loaded,
+        None => return Err(ErrorKind::NoLockfile(path_var("OUT_DIR")?).into()),
+    };
+    match lockfile.sources.iter().find(|source| source.url == url) {
+        None => Err(ErrorKind::NotLocked {
+            url: url.to_owned(),
+            lockfile: path,
+        }
+        .into()),
+        Some(source) if source.digest != digest => Err(ErrorKind::LockMismatch {
+            url: url.to_owned(),
+            lockfile: path,
+            expected: source.digest.clone(),
+            actual: digest,
+        }
+        .into()),
+        Some(_) => {
+            event!(debug, %digest, lockfile = %path, "the source matches the lockfile");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Cross-language LTO for vendored builds.
+//!
+//! With `-Clinker-plugin-lto`, rustc emits LLVM bitcode and leaves optimization to the linker.
+//! Compiling the vendored C/C++ code to bitcode as well (clang's `-flto=thin`)
+//! lets the linker inline across the language boundary.
+//! This only works when clang's LLVM matches rustc's.
+
+use std::process::Command;
+
+use crate::rustflags::RustFlags;
+use crate::{env_var, run_command, Error, ErrorKind};
+
+/// Sets up ThinLTO for the vendored build,
+/// adding compiler flags to `flags` and tool overrides to `env`.
+///
+/// Returns `false` (after a warning) if rustc isn't using linker-plugin LTO,
+/// since bitcode objects can't be linked without it.
+pub(crate) fn configure(
+    flags: &mut Vec<String>,
+    env: &mut Vec<(String, String)>,
+) -> Result<bool, Error> {
+    if RustFlags::from_env().codegen("linker-plugin-lto").is_none() {
+        warning!(
+            "cross-language LTO requested but rustc isn't using \
+             `-Clinker-plugin-lto`, building without it"
+        );
+        return Ok(false);
+    }
+
+    let cc = match target_cc() {
+        Some(cc) => cc,
+        None => {
+            env.push(("CC".to_owned(), "clang".to_owned()));
+            env.push(("CXX".to_owned(), "clang++".to_owned()));
+            env.push(("AR".to_owned(), "llvm-ar".to_owned()));
+            "clang".to_owned()
+        }
+    };
+    check_llvm_versions(&cc)?;
+    flags.push("-flto=thin".to_owned());
+    Ok(true)
+}
+
+/// Gets the C compiler configured for the target, looked up the same way `cc` does.
+fn target_cc() -> Option<String> {
+    let target = std::env::var("TARGET").ok()?;
+    [
+        format!("CC_{target}"),
+        format!("CC_{}", target.replace('-', "_")),
+        "TARGET_CC".to_owned(),
+        "CC".to_owned(),
+    ]
+    .iter()
+    .find_map(|var| {
+        println!("cargo:rerun-if-env-changed={var}");
+        std::env::var(var).ok()
+    })
+}
+
+/// Checks that `cc` is a clang whose LLVM major version matches rustc's.
+fn check_llvm_versions(cc: &str) -> Result<(), Error> {
+    let rustc = env_var("RUSTC")?;
+    let rustc_version = run_command(Command::new(rustc).arg("-vV"))?;
+    let rustc_llvm = rustc_version
+        .lines()
+        .find_map(|line| line.strip_prefix("LLVM version: "))
+        .map(str::to_owned);
+
+    let cc_version = run_command(Command::new(cc).arg("--version"))?;
+    let first_line = cc_version.lines().next().unwrap_or_default();
+    if first_line.starts_with("Apple clang") {
+        // Apple's clang versions don't follow LLVM's.
+        warning!("cannot verify that Apple clang's LLVM matches rustc's");
+        return Ok(());
+    }
+    let clang = first_line
+        .split("clang version ")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .map(str::to_owned);
+
+    let major = |version: &Option<String>| {
+        version
+            .as_deref()
+            .and_then(|v| v.split('.').next())
+            .map(str::to_owned)
+    };
+    match (major(&rustc_llvm), major(&clang)) {
+        (Some(rustc_major), Some(clang_major)) if rustc_major == clang_major => Ok(()),
+        _ => Err(ErrorKind::IncompatibleLto {
+            rustc_llvm: rustc_llvm.unwrap_or_else(|| "unknown".to_owned()),
+            cc: cc.to_owned(),
+            clang: clang.unwrap_or_else(|| "not clang".to_owned()),
+        }
+        .into()),
+    }
+}
@@ -0,0 +1,26 @@
+//! Finding libraries installed with MacPorts.
+//!
+//! MacPorts installs into `/opt/local`, which only its own pkg-config searches. It's only
+//! searched when the library isn't found otherwise, so that machines with
+//! both MacPorts and Homebrew keep preferring the pkg-config on `PATH`.
+
+use std::path::Path;
+
+use crate::homebrew::is_native_macos;
+use crate::pc_file::PkgConfigEnv;
+use crate::Error;
+
+/// Where MacPorts installs ports.
+const PREFIX: &str = "/opt/local";
+
+/// Searches MacPorts' `lib/pkgconfig` with the probes of `pkg_env`, after the user's
+/// `PKG_CONFIG_PATH`, and returns whether it wasn't searched already.
+///
+/// Does nothing unless building natively on macOS with MacPorts installed.
+pub(crate) fn add_pkg_config_path(pkg_env: &mut PkgConfigEnv) -> Result<bool, Error> {
+    if !is_native_macos()? || !Path::new(PREFIX).join("bin").join("port").exists() {
+        return Ok(false);
+    }
+    let dir = Path::new(PREFIX).join("lib").join("pkgconfig");
+    Ok(pkg_env.append_dirs([dir]))
+}
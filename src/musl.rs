@@ -0,0 +1,50 @@
+//! Building for musl targets, and on Alpine Linux.
+//!
+//! Rust's musl targets link the C runtime statically by default, which leaves no way to load
+//! shared libraries, so libraries are linked statically there unless the metadata says
+//! otherwise, including those in `/usr`. Alpine, whose libc is musl, packages headers and
+//! `.pc` files in `-dev` packages installed with `apk`.
+
+use std::path::Path;
+
+use camino::Utf8Path;
+
+use crate::{cflags, env_var, symbols, Error, LinkKind, TargetInfo};
+
+/// Whether the target is musl-based and links the C runtime statically.
+pub(crate) fn links_statically(target: &TargetInfo) -> bool {
+    target.env() == "musl" && cflags::crt_static(target)
+}
+
+/// Gets the linkage to use when the metadata doesn't pick one.
+pub(crate) fn default_link_kind(target: &TargetInfo) -> Option<LinkKind> {
+    if links_statically(target) {
+        Some(LinkKind::Static)
+    } else {
+        None
+    }
+}
+
+/// Whether the build script builds for the Alpine it runs on.
+pub(crate) fn is_native_alpine(target: &TargetInfo) -> Result<bool, Error> {
+    Ok(target.env() == "musl"
+        && env_var("HOST")? == target.triple()
+        && Path::new("/etc/alpine-release").exists())
+}
+
+/// Warns about libraries in the prebuilt `lib_dir` that were built against glibc,
+/// when building for a musl target.
+pub(crate) fn warn_glibc_prebuilt(target: &TargetInfo, lib_dir: &Utf8Path) -> Result<(), Error> {
+    if target.env() != "musl" {
+        return Ok(());
+    }
+    for library in symbols::libraries(&[lib_dir.to_owned()])? {
+        if symbols::requires_glibc(&library)? {
+            warning!(
+                "prebuilt `{library}` was built against glibc \
+                 and likely won't link or run on musl",
+            );
+        }
+    }
+    Ok(())
+}
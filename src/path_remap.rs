@@ -0,0 +1,59 @@
+//! Keeping the paths of the build out of vendored libraries.
+//!
+//! Debug info and `__FILE__` in C code record absolute paths, which name the user's home
+//! directory or the CI runner's workspace and end up in shipped binaries. Remapping them
+//! rewrites the source and out directories to placeholders, and applies the remappings
+//! rustc was given with `--remap-path-prefix` so C and Rust code agree.
+
+use camino::Utf8Path;
+
+use crate::cflags::Flavor;
+use crate::env_overrides;
+use crate::rustflags::RustFlags;
+use crate::Error;
+
+/// What the source directory is renamed to.
+const SOURCE_PLACEHOLDER: &str = "/buildkit/src";
+/// What `OUT_DIR` is renamed to.
+const OUT_PLACEHOLDER: &str = "/buildkit/out";
+
+/// Whether to remap paths, with `remap-paths = true` in the metadata,
+/// `BUILDKIT_REMAP_PATHS=1`, or when rustc remaps paths too.
+pub(crate) fn enabled(metadata: bool) -> Result<bool, Error> {
+    Ok(metadata
+        || env_overrides::flag("BUILDKIT_REMAP_PATHS")?
+        || rustc_remappings().next().is_some())
+}
+
+/// Adds the flags remapping the paths. MSVC has no documented way to, so it gets none.
+pub(crate) fn configure(
+    flavor: Flavor,
+    source_path: &Utf8Path,
+    out_dir: &Utf8Path,
+    flags: &mut Vec<String>,
+) {
+    if flavor == Flavor::Msvc {
+        return;
+    }
+    // GCC and Clang apply the last matching map, so the more specific ones go last;
+    // the source may be in `OUT_DIR`, which is usually in the directories rustc remaps.
+    flags.extend(
+        rustc_remappings()
+            .map(|remapping| format!("-ffile-prefix-map={remapping}"))
+            .chain([
+                format!("-ffile-prefix-map={out_dir}={OUT_PLACEHOLDER}"),
+                format!("-ffile-prefix-map={source_path}={SOURCE_PLACEHOLDER}"),
+            ]),
+    );
+}
+
+/// Gets the `FROM=TO` remappings of rustc's `--remap-path-prefix`.
+fn rustc_remappings() -> impl Iterator<Item = String> {
+    let flags = RustFlags::from_env();
+    let remappings: Vec<String> = flags
+        .values("--remap-path-prefix")
+        .map(|value| value.strip_prefix('=').unwrap_or(value).to_owned())
+        .filter(|value| value.contains('='))
+        .collect();
+    remappings.into_iter()
+}
@@ -0,0 +1,210 @@
+//! Generating pkg-config files for vendored builds.
+//!
+//! Other native libraries built later in the graph usually find their dependencies with
+//! pkg-config. A `.pc` file for the vendored copy, passed on through `DEP_<links>_PKG_CONFIG_PATH`,
+//! lets them find it instead of a system copy.
+
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+
+use crate::{env_var, io_error, path_var, utf8_vars, Error};
+
+/// The contents of a generated `.pc` file.
+///
+/// ```toml
+/// [package.metadata.buildkit.generate-pc]
+/// libs = ["z"]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PcFile {
+    /// The module name, by default the `pkg-config` requirement's.
+    name: Option<String>,
+    description: Option<String>,
+    /// Libraries to link, without `-l`.
+    libs: Vec<String>,
+    /// Libraries static linking needs too, e.g. `pthread`.
+    #[serde(default)]
+    libs_private: Vec<String>,
+    /// pkg-config modules the library depends on.
+    #[serde(default)]
+    requires: Vec<String>,
+}
+
+/// The first line of the `.pc` files buildkit writes, telling them from those the vendored
+/// build installs.
+const GENERATED: &str = "# Generated by buildkit";
+
+impl PcFile {
+    /// Writes the `.pc` file into `<prefix>/lib/pkgconfig`, unless the vendored build
+    /// installed one already, and returns that directory.
+    ///
+    /// A file written by an earlier build is written again, as the libraries, version or
+    /// prefix may have changed since.
+    pub(crate) fn install(
+        &self,
+        default_name: Option<&str>,
+        version: Option<&str>,
+        prefix: &Utf8Path,
+    ) -> Result<Utf8PathBuf, Error> {
+        let name = match self.name.as_deref().or(default_name) {
+            Some(name) => name.to_owned(),
+            None => env_var("CARGO_PKG_NAME")?,
+        };
+        let dir = prefix.join("lib").join("pkgconfig");
+        let path = dir.join(format!("{name}.pc"));
+        let installed = std::fs::read_to_string(&path)
+            .map_or(false, |contents| !contents.starts_with(GENERATED));
+        if installed {
+            return Ok(dir);
+        }
+
+        let libs = |libs: &[String]| {
+            libs.iter()
+                .map(|lib| format!(" -l{lib}"))
+                .collect::<String>()
+        };
+        let mut contents = String::new();
+        writeln!(contents, "{GENERATED}").unwrap();
+        writeln!(contents, "prefix={prefix}").unwrap();
+        contents.push_str("libdir=${prefix}/lib\nincludedir=${prefix}/include\n\n");
+        writeln!(contents, "Name: {name}").unwrap();
+        writeln!(
+            contents,
+            "Description: {}",
+            self.description
+                .as_deref()
+                .unwrap_or("Vendored by buildkit")
+        )
+        .unwrap();
+        writeln!(contents, "Version: {}", version.unwrap_or("0")).unwrap();
+        if !self.requires.is_empty() {
+            writeln!(contents, "Requires: {}", self.requires.join(", ")).unwrap();
+        }
+        writeln!(contents, "Libs: -L${{libdir}}{}", libs(&self.libs)).unwrap();
+        if !self.libs_private.is_empty() {
+            writeln!(contents, "Libs.private:{}", libs(&self.libs_private)).unwrap();
+        }
+        contents.push_str("Cflags: -I${includedir}\n");
+
+        std::fs::create_dir_all(&dir).map_err(io_error(&dir))?;
+        std::fs::write(&path, contents).map_err(io_error(&path))?;
+        Ok(dir)
+    }
+}
+
+/// Changes to pkg-config's environment for the platform, like the `.pc` directories of a
+/// sysroot, which only buildkit's probes and the vendored build see rather than the whole
+/// build script, whose other probes, e.g. of other crates or with vcpkg, they'd mislead.
+#[derive(Debug, Default)]
+pub(crate) struct PkgConfigEnv {
+    vars: Vec<(&'static str, OsString)>,
+    /// Searched after `PKG_CONFIG_PATH`.
+    dirs: Vec<PathBuf>,
+}
+
+impl PkgConfigEnv {
+    /// Sets `var` for the probes, unless the user set it.
+    pub(crate) fn set_default(&mut self, var: &'static str, value: impl Into<OsString>) {
+        if std::env::var_os(var).is_none() && !self.vars.iter().any(|(set, _)| *set == var) {
+            self.vars.push((var, value.into()));
+        }
+    }
+
+    /// Whether the probes see `var`, as the user or the platform set it.
+    pub(crate) fn is_set(&self, var: &str) -> bool {
+        std::env::var_os(var).is_some() || self.vars.iter().any(|(set, _)| *set == var)
+    }
+
+    /// Searches the existing `dirs` after `PKG_CONFIG_PATH`, and returns whether any weren't
+    /// searched already.
+    pub(crate) fn append_dirs(&mut self, dirs: impl IntoIterator<Item = PathBuf>) -> bool {
+        let user: Vec<PathBuf> = std::env::var_os("PKG_CONFIG_PATH")
+            .map(|path| std::env::split_paths(&path).collect())
+            .unwrap_or_default();
+        let len = self.dirs.len();
+        for dir in dirs {
+            if dir.is_dir() && !user.contains(&dir) && !self.dirs.contains(&dir) {
+                self.dirs.push(dir);
+            }
+        }
+        self.dirs.len() > len
+    }
+
+    /// Passes the changes on to the vendored build, whose configure step may run pkg-config
+    /// too, along with the `.pc` directories of vendored dependencies.
+    pub(crate) fn export(&self, env: &mut Vec<(String, String)>) {
+        let path = self.search_path(&[]).map(|path| ("PKG_CONFIG_PATH", path));
+        for (var, value) in self.vars.iter().cloned().chain(path) {
+            if let Ok(value) = value.into_string() {
+                env.push((var.to_owned(), value));
+            }
+        }
+    }
+
+    /// Gets `PKG_CONFIG_PATH` with the `.pc` directories of vendored dependencies, from their
+    /// `DEP_<links>_PKG_CONFIG_PATH`, before it, so their libraries are found before the
+    /// system's, and the package's `package_dirs` and the platform's after it, if there are
+    /// any of them.
+    fn search_path(&self, package_dirs: &[PathBuf]) -> Option<OsString> {
+        let mut dependencies: Vec<PathBuf> = utf8_vars()
+            .filter(|(key, _)| key.starts_with("DEP_") && key.ends_with("_PKG_CONFIG_PATH"))
+            .map(|(_, dir)| dir.into())
+            .collect();
+        if dependencies.is_empty() && package_dirs.is_empty() && self.dirs.is_empty() {
+            return None;
+        }
+        dependencies.sort();
+        let user = std::env::var_os("PKG_CONFIG_PATH");
+        let path = dependencies
+            .into_iter()
+            .chain(user.iter().flat_map(std::env::split_paths))
+            .chain(package_dirs.iter().cloned())
+            .chain(self.dirs.iter().cloned());
+        std::env::join_paths(path).ok()
+    }
+}
+
+/// Runs `probe` with the package's `dirs` and the changes of `pkg_env` in pkg-config's
+/// environment, and restores it afterwards.
+///
+/// pkg-config takes its search path only from the environment, so this is the narrowest
+/// the change can be.
+pub(crate) fn with_search_dirs<T>(
+    dirs: &[Utf8PathBuf],
+    pkg_env: &PkgConfigEnv,
+    probe: impl FnOnce() -> T,
+) -> Result<T, Error> {
+    let mut package_dirs = Vec::new();
+    if !dirs.is_empty() {
+        let package = path_var("CARGO_MANIFEST_DIR")?;
+        for dir in dirs {
+            let dir = package.join(dir);
+            println!("cargo:rerun-if-changed={dir}");
+            package_dirs.push(dir.into_std_path_buf());
+        }
+    }
+    let mut vars = pkg_env.vars.clone();
+    if let Some(path) = pkg_env.search_path(&package_dirs) {
+        vars.push(("PKG_CONFIG_PATH", path));
+    }
+    let previous: Vec<(&str, Option<OsString>)> = vars
+        .iter()
+        .map(|(var, _)| (*var, std::env::var_os(var)))
+        .collect();
+    for (var, value) in &vars {
+        std::env::set_var(var, value);
+    }
+    let result = probe();
+    for (var, value) in previous {
+        match value {
+            Some(value) => std::env::set_var(var, value),
+            None => std::env::remove_var(var),
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,69 @@
+//! Alternative implementations of one logical dependency.
+//!
+//! A dependency on, say, the zlib API can be satisfied by zlib itself or by zlib-ng
+//! in compatibility mode. Providers are tried in order, and the chosen one is reported
+//! through `cfg(buildkit_provider = "...")` and `cargo:provider`.
+
+use serde::Deserialize;
+
+use crate::{env_overrides, Error, ErrorKind, PkgConfigRequirement, VcpkgRequirement};
+
+/// One implementation from `package.metadata.buildkit.providers`.
+///
+/// ```toml
+/// [[package.metadata.buildkit.providers]]
+/// name = "zlib-ng"
+/// pkg-config = { name = "zlib-ng" }
+///
+/// [[package.metadata.buildkit.providers]]
+/// name = "zlib"
+/// pkg-config = { name = "zlib" }
+/// vcpkg = { name = "zlib" }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct Provider {
+    pub(crate) name: String,
+    pub(crate) pkg_config: Option<PkgConfigRequirement>,
+    pub(crate) vcpkg: Option<VcpkgRequirement>,
+}
+
+/// Orders `providers` by preference.
+///
+/// `BUILDKIT_<PACKAGE>_PROVIDERS`, a comma-separated list of provider names,
+/// overrides the order of the metadata and can leave providers out.
+pub(crate) fn ordered(providers: &[Provider]) -> Result<Vec<&Provider>, Error> {
+    let order = match env_overrides::var(&env_overrides::package_key("PROVIDERS")?)? {
+        Some(order) => order,
+        None => return Ok(providers.iter().collect()),
+    };
+    order
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            providers
+                .iter()
+                .find(|provider| provider.name == name)
+                .ok_or_else(|| ErrorKind::UnknownProvider(name.to_owned()).into())
+        })
+        .collect()
+}
+
+/// Declares the `buildkit_provider` cfg and its possible values.
+pub(crate) fn emit_check_cfg(providers: &[Provider]) {
+    let values: Vec<String> = providers
+        .iter()
+        .map(|provider| format!("{:?}", provider.name))
+        .collect();
+    println!(
+        "cargo:rustc-check-cfg=cfg(buildkit_provider, values({}))",
+        values.join(", ")
+    );
+}
+
+/// Reports the chosen provider to the crate and its dependents.
+pub(crate) fn report(name: &str) {
+    println!("cargo:rustc-cfg=buildkit_provider={name:?}");
+    println!("cargo:provider={name}");
+}
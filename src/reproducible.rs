@@ -0,0 +1,106 @@
+//! Reproducible vendored builds.
+//!
+//! C builds record where and when they ran: absolute paths in debug info and `__FILE__`,
+//! timestamps, owners and permissions in the headers of static archives, `__DATE__`, and
+//! random linker build IDs. Opting in removes them, the paths through [`path_remap`], so
+//! rebuilding the same source gives bit-identical libraries, as the reproducible-builds
+//! efforts of Debian, Arch and Nix check.
+//!
+//! [`path_remap`]: crate::path_remap
+
+use std::fs;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::cflags::Flavor;
+use crate::{append_env_flags, env_overrides, io_error, Error, TargetInfo};
+
+/// 1980-01-01, the earliest time zip archives can store, as Nix uses too.
+const DEFAULT_SOURCE_DATE_EPOCH: &str = "315532800";
+
+/// Whether the vendored build is to be reproducible, with `reproducible = true` in the
+/// metadata or `BUILDKIT_REPRODUCIBLE=1`.
+pub(crate) fn enabled(metadata: bool) -> Result<bool, Error> {
+    Ok(metadata || env_overrides::flag("BUILDKIT_REPRODUCIBLE")?)
+}
+
+/// Sets `SOURCE_DATE_EPOCH`, keeping the user's, and adds the flags leaving out build IDs
+/// and timestamps.
+pub(crate) fn configure(
+    flavor: Flavor,
+    target: &TargetInfo,
+    flags: &mut Vec<String>,
+    env: &mut Vec<(String, String)>,
+) -> Result<(), Error> {
+    let epoch = env_overrides::var("SOURCE_DATE_EPOCH")?;
+    env.push((
+        "SOURCE_DATE_EPOCH".to_owned(),
+        epoch.unwrap_or_else(|| DEFAULT_SOURCE_DATE_EPOCH.to_owned()),
+    ));
+    // Apple's ar and libtool leave the dates out of archives with it.
+    env.push(("ZERO_AR_DATE".to_owned(), "1".to_owned()));
+    match flavor {
+        Flavor::Gnu => {
+            if target.families().iter().any(|family| family == "unix") && !target.is_apple() {
+                append_env_flags(env, "LDFLAGS", "-Wl,--build-id=none".to_owned());
+            }
+        }
+        Flavor::Msvc => flags.push("/Brepro".to_owned()),
+    }
+    Ok(())
+}
+
+/// Zeroes the dates, owners and groups in the member headers of the static libraries in
+/// `dirs`, and gives the members the same permissions, as `ar D` does.
+pub(crate) fn normalize_archives(dirs: &[Utf8PathBuf]) -> Result<(), Error> {
+    for dir in dirs {
+        let entries = match dir.read_dir_utf8() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if matches!(path.extension(), Some("a" | "lib")) && path.is_file() {
+                normalize_archive(path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rewrites the member headers of one archive, in the common format of Unix `.a` and
+/// MSVC `.lib` files. Thin archives and anything else are left alone.
+fn normalize_archive(path: &Utf8Path) -> Result<(), Error> {
+    let mut data = fs::read(path).map_err(io_error(path))?;
+    if !data.starts_with(b"!<arch>\n") {
+        return Ok(());
+    }
+    let original = data.clone();
+    let mut pos = 8;
+    while pos + 60 <= data.len() {
+        let header = &mut data[pos..pos + 60];
+        if &header[58..60] != b"`\n" {
+            break;
+        }
+        let size: usize = match std::str::from_utf8(&header[48..58])
+            .ok()
+            .and_then(|size| size.trim().parse().ok())
+        {
+            Some(size) => size,
+            None => break,
+        };
+        header[16..28].copy_from_slice(b"0           ");
+        header[28..34].copy_from_slice(b"0     ");
+        header[34..40].copy_from_slice(b"0     ");
+        // The symbol table and long names of GNU and BSD archives have no meaningful mode.
+        if !header.starts_with(b"/") && !header.starts_with(b"__.SYMDEF") {
+            header[40..48].copy_from_slice(b"644     ");
+        }
+        // Members are aligned to two bytes.
+        pos += 60 + size + size % 2;
+    }
+    if data != original {
+        fs::write(path, &data).map_err(io_error(path))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,236 @@
+//! Reporting the native requirements of a workspace to packaging tools.
+//!
+//! Distribution and Nix packagers need to know up front which system libraries
+//! the `-sys` crates in a dependency graph probe for and which sources they download,
+//! since their builds run without network access.
+
+use std::collections::BTreeSet;
+
+use camino::Utf8Path;
+use cargo_metadata::MetadataCommand;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{debian, BuildKitMetadata, BuildKitMode, Error, ErrorKind};
+
+/// The `package.metadata.buildkit` of every package in a workspace's dependency graph.
+///
+/// `cargo buildkit` prints these in the formats packaging tools take.
+pub struct Requirements {
+    packages: Vec<Package>,
+}
+
+struct Package {
+    name: String,
+    version: String,
+    metadata: BuildKitMetadata,
+}
+
+impl Requirements {
+    /// Collects the requirements of the workspace at `manifest_path` and its dependencies.
+    pub fn from_manifest(manifest_path: impl AsRef<Utf8Path>) -> Result<Requirements, Error> {
+        let metadata = MetadataCommand::new()
+            .manifest_path(manifest_path.as_ref())
+            .exec()
+            .map_err(ErrorKind::CargoMetadataError)?;
+        let mut packages = Vec::new();
+        for package in metadata.packages {
+            let value = match package.metadata.get("buildkit") {
+                Some(value) => value.clone(),
+                None => continue,
+            };
+            packages.push(Package {
+                name: package.name,
+                version: package.version.to_string(),
+                metadata: serde_json::from_value(value).map_err(ErrorKind::Json)?,
+            });
+        }
+        packages.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        Ok(Requirements { packages })
+    }
+
+    /// Renders the requirements as JSON for Nix tooling.
+    ///
+    /// Each package lists the pkg-config modules it probes for, the attributes its
+    /// author named under `system-packages.nix` for `buildInputs`, and its sources as
+    /// arguments to `fetchurl`, `fetchzip` or `fetchgit`. Archives pinned only by
+    /// `tree-hash` map to `fetchzip`, whose hash is the same NAR hash.
+    pub fn to_nix_json(&self) -> String {
+        let packages: Vec<_> = self
+            .packages
+            .iter()
+            .map(|package| {
+                let metadata = &package.metadata;
+                json!({
+                    "name": package.name,
+                    "version": package.version,
+                    "mode": mode_name(metadata.default_mode),
+                    "pkg-config": pkg_config_modules(metadata),
+                    "nix": metadata.system_packages.get("nix").cloned().unwrap_or_default(),
+                    "sources": nix_sources(metadata),
+                })
+            })
+            .collect();
+        serde_json::to_string_pretty(&json!({ "packages": packages }))
+            .expect("JSON values always serialize")
+    }
+
+    /// Renders a `Build-Depends` field for a Debian `control` file.
+    ///
+    /// Crates that probe pkg-config by default contribute the packages named under
+    /// `system-packages.debian`, or else those of their modules in a built-in table.
+    /// Modules the table doesn't know are listed in comments after the field.
+    pub fn to_debian_build_depends(&self) -> String {
+        let mut packages = BTreeSet::new();
+        let mut unknown = Vec::new();
+        for package in self.system_packages() {
+            let metadata = &package.metadata;
+            packages.insert("pkg-config".to_owned());
+            if let Some(hints) = metadata.system_packages.get("debian") {
+                packages.extend(hints.iter().cloned());
+                continue;
+            }
+            // Providers are alternatives, which Debian writes as `a | b`.
+            let mut alternatives = Vec::new();
+            for module in pkg_config_modules(metadata) {
+                match debian::dev_package(module.name) {
+                    Some(dev_package) if !alternatives.contains(&dev_package) => {
+                        alternatives.push(dev_package);
+                    }
+                    Some(_) => {}
+                    None => unknown.push((module.name, &package.name)),
+                }
+            }
+            if !alternatives.is_empty() {
+                packages.insert(alternatives.join(" | "));
+            }
+        }
+        let mut field = format!("Build-Depends: {}\n", join(&packages));
+        for (module, name) in unknown {
+            field.push_str(&format!(
+                "# No Debian package known for pkg-config module `{module}` of {name}; \
+                 set `system-packages.debian`\n"
+            ));
+        }
+        field
+    }
+
+    /// Renders `BuildRequires` lines for an RPM spec file.
+    ///
+    /// Crates that probe pkg-config by default contribute the packages named under
+    /// `system-packages.rpm`, or else their modules as `pkgconfig(...)` dependencies,
+    /// which Fedora, RHEL and openSUSE all provide. Providers become a rich dependency
+    /// accepting any of them.
+    pub fn to_rpm_build_requires(&self) -> String {
+        let mut requires = BTreeSet::new();
+        for package in self.system_packages() {
+            let metadata = &package.metadata;
+            requires.insert("pkgconfig".to_owned());
+            if let Some(hints) = metadata.system_packages.get("rpm") {
+                requires.extend(hints.iter().cloned());
+                continue;
+            }
+            let alternatives: Vec<String> = pkg_config_modules(metadata)
+                .iter()
+                .map(|module| match module.min_version {
+                    Some(min) => format!("pkgconfig({}) >= {min}", module.name),
+                    None => format!("pkgconfig({})", module.name),
+                })
+                .collect();
+            match alternatives.len() {
+                0 => {}
+                1 => {
+                    requires.extend(alternatives);
+                }
+                _ => {
+                    requires.insert(format!("({})", alternatives.join(" or ")));
+                }
+            }
+        }
+        requires
+            .iter()
+            .map(|require| format!("BuildRequires: {require}\n"))
+            .collect()
+    }
+
+    /// The packages that link a system library unless configured otherwise.
+    fn system_packages(&self) -> impl Iterator<Item = &Package> {
+        self.packages
+            .iter()
+            .filter(|package| matches!(package.metadata.default_mode, BuildKitMode::PkgConfig))
+    }
+}
+
+fn join(packages: &BTreeSet<String>) -> String {
+    packages
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A pkg-config module and the lowest version accepted.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct PkgConfigModule<'a> {
+    name: &'a str,
+    min_version: Option<&'a str>,
+}
+
+/// Lists the pkg-config modules a package probes for: those of its providers,
+/// in order of preference, or else its own.
+fn pkg_config_modules(metadata: &BuildKitMetadata) -> Vec<PkgConfigModule<'_>> {
+    let providers = metadata
+        .providers
+        .iter()
+        .filter_map(|p| p.pkg_config.as_ref());
+    let own = metadata.pkg_config.iter();
+    let modules: Box<dyn Iterator<Item = _>> = if metadata.providers.is_empty() {
+        Box::new(own)
+    } else {
+        Box::new(providers)
+    };
+    modules
+        .map(|req| PkgConfigModule {
+            name: &req.name,
+            min_version: req.version_req.as_ref().and_then(|req| req.min()),
+        })
+        .collect()
+}
+
+fn nix_sources(metadata: &BuildKitMetadata) -> Vec<serde_json::Value> {
+    let source = match &metadata.vendored_source {
+        Some(source) => source,
+        None => return Vec::new(),
+    };
+    let tree_hash = metadata.tree_hash.as_ref().map(|hash| hash.sri());
+    source
+        .fetchables()
+        .into_iter()
+        .map(|fetchable| {
+            let (fetcher, hash) = match (fetchable.kind, fetchable.hash) {
+                ("git", _) => ("fetchgit", tree_hash.clone()),
+                (_, Some(hash)) => ("fetchurl", Some(hash.sri())),
+                (_, None) => ("fetchzip", tree_hash.clone()),
+            };
+            let mut value = json!({
+                "fetcher": fetcher,
+                "url": fetchable.url,
+                "hash": hash,
+                "target": fetchable.target,
+            });
+            if let Some(rev) = fetchable.rev {
+                value["rev"] = rev.into();
+            }
+            value
+        })
+        .collect()
+}
+
+fn mode_name(mode: BuildKitMode) -> &'static str {
+    match mode {
+        BuildKitMode::PkgConfig => "pkg-config",
+        BuildKitMode::Vcpkg => "vcpkg",
+        BuildKitMode::VendoredBuild => "vendored-build",
+    }
+}
@@ -0,0 +1,76 @@
+//! Runtime search paths for vendored shared libraries.
+
+use std::process::Command;
+
+use camino::Utf8Path;
+
+use crate::link::LinkDirectives;
+use crate::{env_var, io_error, run_command, Error, TargetInfo};
+
+/// Adds `-Wl,-rpath` link args for `paths` to `link`.
+///
+/// `$ORIGIN` (the directory of the binary) is translated to `@loader_path` on Apple targets.
+/// Windows has no rpath, so nothing is added there.
+///
+/// Note that `cargo:rustc-link-arg` only applies to the binaries, tests and examples
+/// of the package whose build script emits it,
+/// so the paths are also exported as `DEP_<links>_RPATH` for dependents.
+pub(crate) fn add_rpaths(target: &TargetInfo, paths: &[String], link: &mut LinkDirectives) {
+    if paths.is_empty() || target.os() == "windows" {
+        return;
+    }
+    let mut exported = Vec::with_capacity(paths.len());
+    for path in paths {
+        let path = if target.is_apple() {
+            path.replace("$ORIGIN", "@loader_path")
+        } else {
+            path.clone()
+        };
+        link.args.push(format!("-Wl,-rpath,{path}"));
+        exported.push(path);
+    }
+    println!("cargo:rpath={}", exported.join(";"));
+}
+
+/// Adds an rpath pointing at `lib_dir` to `link` in non-release profiles,
+/// so `cargo test` and `cargo run` find shared libraries in `OUT_DIR`
+/// without `LD_LIBRARY_PATH`.
+///
+/// Release builds stay free of build-machine paths.
+pub(crate) fn add_dev_rpath(
+    target: &TargetInfo,
+    lib_dir: &Utf8Path,
+    link: &mut LinkDirectives,
+) -> Result<(), Error> {
+    if env_var("PROFILE")? == "release" || target.os() == "windows" {
+        return Ok(());
+    }
+    link.args.push(format!("-Wl,-rpath,{lib_dir}"));
+    Ok(())
+}
+
+/// Rewrites the install names of the `.dylib`s in `lib_dir` to `@rpath/<file name>`.
+///
+/// Build systems usually record the absolute path of the build tree as the install name,
+/// which the dynamic loader then tries instead of consulting the rpath.
+/// This does nothing on non-Apple targets.
+pub(crate) fn fix_install_names(target: &TargetInfo, lib_dir: &Utf8Path) -> Result<(), Error> {
+    if !target.is_apple() {
+        return Ok(());
+    }
+    for entry in lib_dir.read_dir_utf8().map_err(io_error(lib_dir))? {
+        let entry = entry.map_err(io_error(lib_dir))?;
+        let path = entry.path();
+        let is_dylib = path.extension() == Some("dylib");
+        let is_symlink = entry.file_type().map_err(io_error(path))?.is_symlink();
+        if is_dylib && !is_symlink {
+            run_command(
+                Command::new("install_name_tool")
+                    .arg("-id")
+                    .arg(format!("@rpath/{}", entry.file_name()))
+                    .arg(path),
+            )?;
+        }
+    }
+    Ok(())
+}
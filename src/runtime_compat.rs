@@ -0,0 +1,226 @@
+//! Checking which systems the shared libraries found can run on.
+//!
+//! A binary linked against a system library needs, at runtime, at least the glibc or macOS
+//! version that library was built for. On a new build machine that can be newer than the
+//! systems a crate supports, which only shows when the binary fails to load there. The
+//! newest glibc symbol version a library needs, or the minimum macOS version in its load
+//! commands, says that up front.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use object::macho::{LC_BUILD_VERSION, LC_VERSION_MIN_MACOSX, PLATFORM_MACOS};
+use object::read::macho::{
+    FatArch, LoadCommandVariant, MachHeader, MachOFatFile32, MachOFatFile64, MachOFile,
+    MachOFile32, MachOFile64,
+};
+use object::{Architecture, Endianness, FileKind, Object, ObjectSection};
+use serde::Deserialize;
+
+use crate::link::{self, LinkDirectives};
+use crate::{env_var, io_error, Error, ErrorKind, TargetInfo, Version};
+
+/// The newest runtime versions the shared libraries may require.
+///
+/// ```toml
+/// [package.metadata.buildkit.runtime-compat]
+/// glibc = "2.17"
+/// macos = "10.13"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct RuntimeCompat {
+    glibc: Option<String>,
+    macos: Option<String>,
+}
+
+impl RuntimeCompat {
+    /// Reports the glibc or macOS version the shared libraries in `link` require, as
+    /// `cargo:min-glibc` or `cargo:min-macos` for dependents, with a warning if it is newer
+    /// than the metadata allows. Does nothing unless a version is declared for the target.
+    pub(crate) fn check(&self, target: &TargetInfo, link: &LinkDirectives) -> Result<(), Error> {
+        let (runtime, max, key) = if target.os() == "linux" && target.env() == "gnu" {
+            match &self.glibc {
+                Some(max) => ("glibc", max, "min-glibc"),
+                None => return Ok(()),
+            }
+        } else if target.os() == "macos" {
+            match &self.macos {
+                Some(max) => ("macOS", max, "min-macos"),
+                None => return Ok(()),
+            }
+        } else {
+            return Ok(());
+        };
+        let max = Version::parse(max)?;
+        let mut newest: Option<Version> = None;
+        for library in shared_libraries(target, link)? {
+            let required = match runtime {
+                "glibc" => min_glibc(&library)?,
+                _ => min_macos(&library, target)?,
+            };
+            let required = match required {
+                Some(required) => required,
+                None => continue,
+            };
+            if required > max {
+                warning!(
+                    "{library} requires {runtime} {required}, newer than the \
+                     {max} this crate supports, so binaries linking it won't run on older systems"
+                );
+            }
+            if newest.as_ref().map_or(true, |newest| required > *newest) {
+                newest = Some(required);
+            }
+        }
+        if let Some(newest) = newest {
+            println!("cargo:{key}={newest}");
+        }
+        Ok(())
+    }
+}
+
+/// Finds the shared libraries `link` links, in its search paths and, for native builds,
+/// the system's library directories.
+fn shared_libraries(target: &TargetInfo, link: &LinkDirectives) -> Result<Vec<Utf8PathBuf>, Error> {
+    let mut dirs: Vec<Utf8PathBuf> = link
+        .search_paths
+        .iter()
+        .map(|path| match path.split_once('=') {
+            Some((_, dir)) => dir.into(),
+            None => path.into(),
+        })
+        .collect();
+    if env_var("HOST")? == target.triple() {
+        dirs.extend(
+            link::system_lib_dirs(target)
+                .into_iter()
+                .map(Utf8PathBuf::from),
+        );
+    }
+    let mut libraries = Vec::new();
+    for lib in &link.libs {
+        let (kind, name) = match lib.split_once('=') {
+            Some((kind, name)) => (kind, name),
+            None => ("dylib", lib.as_str()),
+        };
+        if !kind.starts_with("dylib") {
+            continue;
+        }
+        let found = dirs.iter().find_map(|dir| {
+            [format!("lib{name}.so"), format!("lib{name}.dylib")]
+                .iter()
+                .map(|file| dir.join(file))
+                .find(|path| path.is_file())
+        });
+        libraries.extend(found);
+    }
+    Ok(libraries)
+}
+
+fn invalid(path: &Utf8Path) -> impl Fn(object::Error) -> ErrorKind + '_ {
+    move |err| ErrorKind::InvalidLibrary {
+        path: path.to_owned(),
+        reason: err.to_string(),
+    }
+}
+
+/// Gets the newest `GLIBC_*` symbol version the ELF shared library at `path` needs.
+fn min_glibc(path: &Utf8Path) -> Result<Option<Version>, Error> {
+    let data = std::fs::read(path).map_err(io_error(path))?;
+    let file = object::File::parse(&*data).map_err(invalid(path))?;
+    let strings = match file.section_by_name(".dynstr") {
+        Some(section) => section.data().map_err(invalid(path))?,
+        None => return Ok(None),
+    };
+    let newest = strings
+        .split(|byte| *byte == 0)
+        .filter_map(|string| std::str::from_utf8(string.strip_prefix(b"GLIBC_")?).ok())
+        // `GLIBC_PRIVATE` and the like aren't versions.
+        .filter_map(|version| Version::parse(version).ok())
+        .max();
+    Ok(newest)
+}
+
+/// Gets the minimum macOS version of the Mach-O shared library at `path`, from the slice for
+/// the target's architecture if it's a universal binary.
+fn min_macos(path: &Utf8Path, target: &TargetInfo) -> Result<Option<Version>, Error> {
+    let data = std::fs::read(path).map_err(io_error(path))?;
+    let kind = FileKind::parse(&*data).map_err(invalid(path))?;
+    let slice = match kind {
+        FileKind::MachOFat32 => {
+            let fat = MachOFatFile32::parse(&*data).map_err(invalid(path))?;
+            fat_slice(fat.arches(), &data, target).map_err(invalid(path))?
+        }
+        FileKind::MachOFat64 => {
+            let fat = MachOFatFile64::parse(&*data).map_err(invalid(path))?;
+            fat_slice(fat.arches(), &data, target).map_err(invalid(path))?
+        }
+        _ => Some(&*data),
+    };
+    let slice = match slice {
+        Some(slice) => slice,
+        None => return Ok(None),
+    };
+    let version = match FileKind::parse(slice).map_err(invalid(path))? {
+        FileKind::MachO32 => MachOFile32::<Endianness>::parse(slice)
+            .and_then(|file| macos_version(&file))
+            .map_err(invalid(path))?,
+        FileKind::MachO64 => MachOFile64::<Endianness>::parse(slice)
+            .and_then(|file| macos_version(&file))
+            .map_err(invalid(path))?,
+        _ => None,
+    };
+    Ok(version.and_then(|version| Version::parse(&version).ok()))
+}
+
+fn fat_slice<'data, Fat: FatArch>(
+    arches: &[Fat],
+    data: &'data [u8],
+    target: &TargetInfo,
+) -> object::Result<Option<&'data [u8]>> {
+    let architecture = match target.arch() {
+        "x86_64" => Architecture::X86_64,
+        "aarch64" => Architecture::Aarch64,
+        "x86" => Architecture::I386,
+        "arm" => Architecture::Arm,
+        "powerpc" => Architecture::PowerPc,
+        "powerpc64" => Architecture::PowerPc64,
+        _ => return Ok(None),
+    };
+    match arches
+        .iter()
+        .find(|arch| arch.architecture() == architecture)
+    {
+        Some(arch) => arch.data(data).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Gets the minimum macOS version in `LC_BUILD_VERSION` or `LC_VERSION_MIN_MACOSX`.
+fn macos_version<'data, Mach: MachHeader>(
+    file: &MachOFile<'data, Mach, &'data [u8]>,
+) -> object::Result<Option<String>> {
+    let endian = file.endian();
+    let mut commands = file.macho_load_commands()?;
+    while let Some(command) = commands.next()? {
+        let minos = match command.variant()? {
+            LoadCommandVariant::BuildVersion(build)
+                if command.cmd() == LC_BUILD_VERSION
+                    && build.platform.get(endian) == PLATFORM_MACOS =>
+            {
+                build.minos.get(endian)
+            }
+            LoadCommandVariant::VersionMin(min) if command.cmd() == LC_VERSION_MIN_MACOSX => {
+                min.version.get(endian)
+            }
+            _ => continue,
+        };
+        // Encoded as xxxx.yy.zz in nibbles.
+        return Ok(Some(format!(
+            "{}.{}.{}",
+            minos >> 16,
+            (minos >> 8) & 0xff,
+            minos & 0xff
+        )));
+    }
+    Ok(None)
+}
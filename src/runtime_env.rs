@@ -0,0 +1,169 @@
+//! The search paths programs need to find the shared libraries they're linked against.
+//!
+//! A binary linked against shared libraries outside the system's directories, e.g. from
+//! vcpkg, a prefix or a vendored build, runs only with those directories on `PATH`,
+//! `LD_LIBRARY_PATH` or `DYLD_FALLBACK_LIBRARY_PATH`, unless an rpath points there. Test
+//! harnesses, launchers and `cargo buildkit runtime-env` get them from the build outputs
+//! instead of each working them out.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_metadata::MetadataCommand;
+
+use crate::conflicts::build_outputs;
+use crate::link::{self, LinkDirectives};
+use crate::{io_error, utf8_vars, Error, ErrorKind, TargetInfo};
+
+/// The variable the target's dynamic loader searches.
+fn search_path_var(target: &TargetInfo) -> &'static str {
+    if target.os() == "windows" {
+        "PATH"
+    } else if target.is_apple() {
+        "DYLD_FALLBACK_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    }
+}
+
+/// Passes on the directories of the shared libraries in `link`, along with those of the
+/// crates this one depends on, as `DEP_<links>_RUNTIME_PATH`, separated by `;`, and the
+/// variable to add them to as `DEP_<links>_RUNTIME_PATH_VAR`.
+pub(crate) fn report(target: &TargetInfo, link: &LinkDirectives) -> Result<(), Error> {
+    let mut dirs: BTreeSet<String> = utf8_vars()
+        .filter(|(key, _)| key.starts_with("DEP_") && key.ends_with("_RUNTIME_PATH"))
+        .flat_map(|(_, value)| {
+            value
+                .split(';')
+                .filter(|dir| !dir.is_empty())
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    dirs.extend(
+        shared_library_dirs(target, link)
+            .into_iter()
+            .map(String::from),
+    );
+    if dirs.is_empty() {
+        return Ok(());
+    }
+    let dirs: Vec<String> = dirs.into_iter().collect();
+    println!("cargo:runtime-path={}", dirs.join(";"));
+    println!("cargo:runtime-path-var={}", search_path_var(target));
+    Ok(())
+}
+
+/// Finds the directories of the shared libraries `link` links dynamically, other than the
+/// system's, which the loader searches anyway.
+fn shared_library_dirs(target: &TargetInfo, link: &LinkDirectives) -> Vec<Utf8PathBuf> {
+    let lib_dirs: Vec<Utf8PathBuf> = link
+        .search_paths
+        .iter()
+        .map(|path| match path.split_once('=') {
+            Some((_, dir)) => dir.into(),
+            None => path.into(),
+        })
+        .collect();
+    let names: Vec<&str> = link
+        .libs
+        .iter()
+        .filter_map(|lib| match lib.split_once('=') {
+            Some((kind, name)) if kind.starts_with("dylib") => Some(name),
+            Some(_) => None,
+            None => Some(lib.as_str()),
+        })
+        .collect();
+    if names.is_empty() {
+        return Vec::new();
+    }
+    let mut dirs = Vec::new();
+    if target.os() == "windows" {
+        // DLLs are installed into `bin`, next to the `lib` with the import libraries.
+        for dir in &lib_dirs {
+            let bin = dir.parent().map(|parent| parent.join("bin"));
+            for dir in std::iter::once(dir.clone()).chain(bin) {
+                if has_file(&dir, |name| name.to_ascii_lowercase().ends_with(".dll")) {
+                    dirs.push(dir);
+                }
+            }
+        }
+        return dirs;
+    }
+    let system = link::system_lib_dirs(target);
+    for dir in lib_dirs {
+        if system.iter().any(|system| Utf8Path::new(system) == dir) {
+            continue;
+        }
+        let shared = has_file(&dir, |file| {
+            names.iter().any(|name| {
+                file.strip_prefix("lib")
+                    .and_then(|file| file.strip_prefix(name))
+                    .map_or(false, |rest| {
+                        rest.starts_with(".so") || rest.ends_with(".dylib")
+                    })
+            })
+        });
+        if shared {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+fn has_file(dir: &Utf8Path, matches: impl Fn(&str) -> bool) -> bool {
+    match dir.read_dir_utf8() {
+        Ok(entries) => entries.flatten().any(|entry| matches(entry.file_name())),
+        Err(_) => false,
+    }
+}
+
+/// The runtime search paths the crates of a build need, by variable,
+/// from the build script outputs cargo keeps in the target directory.
+///
+/// Like [`Resolutions`](crate::Resolutions), this includes outputs of earlier builds.
+pub struct RuntimeEnv {
+    paths: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl RuntimeEnv {
+    /// Collects the search paths from the target directory of the workspace at
+    /// `manifest_path`.
+    pub fn from_manifest(manifest_path: impl AsRef<Utf8Path>) -> Result<RuntimeEnv, Error> {
+        let metadata = MetadataCommand::new()
+            .manifest_path(manifest_path.as_ref())
+            .no_deps()
+            .exec()
+            .map_err(ErrorKind::CargoMetadataError)?;
+        RuntimeEnv::from_target_dir(metadata.target_directory)
+    }
+
+    /// Collects the search paths from the build script outputs under `target_dir`,
+    /// for every profile and target.
+    pub fn from_target_dir(target_dir: impl AsRef<Utf8Path>) -> Result<RuntimeEnv, Error> {
+        let mut paths: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for output in build_outputs(target_dir.as_ref())? {
+            let contents = std::fs::read_to_string(&output).map_err(io_error(&output))?;
+            let value = |key: &str| {
+                contents
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            };
+            if let (Some(dirs), Some(var)) =
+                (value("cargo:runtime-path"), value("cargo:runtime-path-var"))
+            {
+                paths
+                    .entry(var.to_owned())
+                    .or_default()
+                    .extend(dirs.split(';').map(str::to_owned));
+            }
+        }
+        Ok(RuntimeEnv { paths })
+    }
+
+    /// Renders the directories to add to each variable as a JSON object,
+    /// e.g. `{"LD_LIBRARY_PATH": ["/opt/foo/lib"]}`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.paths).expect("JSON values always serialize")
+    }
+}
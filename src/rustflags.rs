@@ -0,0 +1,49 @@
+//! Inspecting the flags cargo passes to rustc.
+
+/// The rustc flags of the current build.
+#[derive(Debug)]
+pub(crate) struct RustFlags(Vec<String>);
+
+impl RustFlags {
+    /// Reads `CARGO_ENCODED_RUSTFLAGS`, falling back to `RUSTFLAGS`.
+    pub(crate) fn from_env() -> RustFlags {
+        if let Ok(encoded) = std::env::var("CARGO_ENCODED_RUSTFLAGS") {
+            let flags = encoded
+                .split('\x1f')
+                .filter(|flag| !flag.is_empty())
+                .map(str::to_owned)
+                .collect();
+            return RustFlags(flags);
+        }
+        let flags = std::env::var("RUSTFLAGS").unwrap_or_default();
+        RustFlags(flags.split_whitespace().map(str::to_owned).collect())
+    }
+
+    /// Gets the value of a `-C <name>[=<value>]` option,
+    /// or an empty string if it's given without a value.
+    pub(crate) fn codegen(&self, name: &str) -> Option<&str> {
+        self.option("-C", name)
+    }
+
+    /// Gets all values of a flag like `-L`, in both the `-Lvalue` and `-L value` forms.
+    pub(crate) fn values<'a>(&'a self, flag: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0.iter().enumerate().filter_map(move |(i, arg)| {
+            if arg == flag {
+                self.0.get(i + 1).map(String::as_str)
+            } else {
+                arg.strip_prefix(flag)
+            }
+        })
+    }
+
+    fn option<'a>(&'a self, flag: &'a str, name: &str) -> Option<&'a str> {
+        self.values(flag).find_map(|option| {
+            let rest = option.strip_prefix(name)?;
+            if rest.is_empty() {
+                Some(rest)
+            } else {
+                rest.strip_prefix('=')
+            }
+        })
+    }
+}
@@ -0,0 +1,52 @@
+//! Propagating rustc's `-Zsanitizer` to C/C++ code.
+
+use crate::cflags::Flavor;
+use crate::rustflags::RustFlags;
+
+/// Gets the sanitizers the Rust code is built with, using clang's names.
+pub(crate) fn enabled() -> Vec<String> {
+    RustFlags::from_env()
+        .values("-Z")
+        .filter_map(|option| option.strip_prefix("sanitizer="))
+        .flat_map(|sanitizers| sanitizers.split(','))
+        .map(|sanitizer| match sanitizer {
+            "safestack" => "safe-stack".to_owned(),
+            sanitizer => sanitizer.to_owned(),
+        })
+        .collect()
+}
+
+/// Gets the compiler flags that instrument vendored code like the Rust code.
+pub(crate) fn compiler_flags(flavor: Flavor) -> Vec<String> {
+    let sanitizers = enabled();
+    if sanitizers.is_empty() {
+        return Vec::new();
+    }
+    match flavor {
+        Flavor::Gnu => vec![
+            format!("-fsanitize={}", sanitizers.join(",")),
+            "-fno-omit-frame-pointer".to_owned(),
+        ],
+        // MSVC only has AddressSanitizer.
+        Flavor::Msvc if sanitizers.iter().any(|s| s == "address") => {
+            vec!["/fsanitize=address".to_owned()]
+        }
+        Flavor::Msvc => Vec::new(),
+    }
+}
+
+/// Warns that a system library won't be instrumented.
+///
+/// Mixing instrumented and uninstrumented code is mostly harmless for AddressSanitizer
+/// but makes MemorySanitizer report false positives.
+pub(crate) fn warn_uninstrumented(lib_name: &str) {
+    let sanitizers = enabled();
+    if sanitizers.is_empty() {
+        return;
+    }
+    warning!(
+        "building with -Zsanitizer={} but {lib_name} comes from the system \
+         and isn't instrumented; use the vendored build to sanitize it too",
+        sanitizers.join(",")
+    );
+}
@@ -0,0 +1,353 @@
+//! Vendored sources and making them available locally.
+
+use std::collections::BTreeMap;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+
+use crate::build_dir;
+use crate::fetch::{self, FetchContext};
+use crate::lockfile;
+use crate::sys_crate::SysCrate;
+use crate::verify::{self, Digests};
+use crate::{
+    env_var, path_var, timings, BuildOutcome, Error, ErrorKind, LinkKind, TargetInfo, Version,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[serde(rename_all_fields = "kebab-case")]
+pub(crate) enum VendoredSource {
+    RemoteTarball(RemoteArchive),
+    /// `hash` is the commit `git_ref` must resolve to.
+    GitRepo {
+        url: String,
+        git_ref: String,
+        hash: Option<String>,
+    },
+    CratePath {
+        relative_path: Utf8PathBuf,
+    },
+    /// A local source tree, set by `source-path` in the workspace's `buildkit.toml`.
+    SystemPath {
+        path: Utf8PathBuf,
+    },
+    /// A prebuilt library that is linked as-is instead of compiled.
+    PrebuiltBinary(PrebuiltBinary),
+    /// The library another crate builds, found through its `links` metadata.
+    SysCrate(SysCrate),
+}
+
+impl VendoredSource {
+    /// Makes the source available on the local filesystem,
+    /// downloading and unpacking it if needed.
+    pub(crate) fn fetch(&self, cx: &FetchContext<'_>) -> Result<Utf8PathBuf, Error> {
+        timings::time("fetch", || match self {
+            VendoredSource::RemoteTarball(archive) => {
+                let (url, hash) = archive.resolve(None)?;
+                fetch::fetch_archive(&url, hash, cx)
+            }
+            VendoredSource::GitRepo { url, git_ref, hash } => {
+                fetch::fetch_git(url, git_ref, hash.as_deref(), cx)
+            }
+            VendoredSource::CratePath { relative_path } => {
+                let manifest_dir = path_var("CARGO_MANIFEST_DIR")?;
+                let path = manifest_dir.join(relative_path);
+                if let Some(tree_hash) = cx.tree_hash {
+                    tree_hash.verify_local_tree(&path)?;
+                }
+                if is_packaged(&manifest_dir) || build_dir::relocates(&path)? {
+                    return fetch::stage(&path);
+                }
+                Ok(path)
+            }
+            VendoredSource::SystemPath { path } => {
+                if let Some(tree_hash) = cx.tree_hash {
+                    tree_hash.verify_local_tree(path)?;
+                }
+                lockfile::check(path.as_str(), || verify::tree_digest(path))?;
+                if build_dir::relocates(path)? {
+                    return fetch::stage(path);
+                }
+                Ok(path.clone())
+            }
+            VendoredSource::PrebuiltBinary(prebuilt) => {
+                let (url, hash) = prebuilt.archive()?.resolve(prebuilt.version.as_deref())?;
+                fetch::fetch_archive(&url, hash, cx)
+            }
+            VendoredSource::SysCrate(sys_crate) => sys_crate.root(),
+        })
+    }
+
+    /// Lists what the source downloads, for every target, for packaging tools
+    /// that fetch sources ahead of the build.
+    pub(crate) fn fetchables(&self) -> Vec<Fetchable<'_>> {
+        match self {
+            VendoredSource::RemoteTarball(archive) => archive.fetchables(None),
+            VendoredSource::GitRepo { url, git_ref, hash } => vec![Fetchable {
+                kind: "git",
+                url: url.clone(),
+                rev: Some(hash.as_deref().unwrap_or(git_ref).to_owned()),
+                hash: None,
+                target: None,
+            }],
+            VendoredSource::CratePath { .. }
+            | VendoredSource::SystemPath { .. }
+            | VendoredSource::SysCrate(_) => Vec::new(),
+            VendoredSource::PrebuiltBinary(prebuilt) => prebuilt
+                .targets
+                .iter()
+                .flat_map(|(target, archive)| {
+                    let mut fetchables = archive.fetchables(prebuilt.version.as_deref());
+                    for fetchable in &mut fetchables {
+                        fetchable.target.get_or_insert_with(|| target.clone());
+                    }
+                    fetchables
+                })
+                .collect(),
+        }
+    }
+
+    /// The declared version of the source, if any.
+    pub(crate) fn version(&self) -> Option<&str> {
+        match self {
+            VendoredSource::RemoteTarball(archive) => archive.version.as_deref(),
+            VendoredSource::PrebuiltBinary(prebuilt) => prebuilt.version.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the crate at `manifest_dir` is a copy made by `cargo vendor` or unpacked
+/// into the registry, which cargo checksums, rather than a checkout being worked on.
+fn is_packaged(manifest_dir: &Utf8Path) -> bool {
+    [".cargo-checksum.json", ".cargo-ok"]
+        .iter()
+        .any(|marker| manifest_dir.join(marker).exists())
+}
+
+/// A remote archive.
+///
+/// The URL may be a template using `{version}`, `{target}`, `{arch}` and `{os}`,
+/// with a `hashes` table keyed by target triple instead of a single `hash`:
+///
+/// ```toml
+/// [package.metadata.buildkit.vendored-source.remote-tarball]
+/// url = "https://example.com/libfoo-{version}-{target}.tar.gz"
+/// version = "1.2.3"
+///
+/// [package.metadata.buildkit.vendored-source.remote-tarball.hashes]
+/// x86_64-unknown-linux-gnu = "..."
+/// aarch64-apple-darwin = "..."
+/// ```
+///
+/// Hashes are hex SHA-256 digests or SRI strings like `sha512-<base64>` or `blake3-<base64>`;
+/// a list of them accepts any, e.g. while rotating algorithms.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct RemoteArchive {
+    url: String,
+    hash: Option<Digests>,
+    /// Hashes keyed by target triple, taking precedence over `hash`.
+    #[serde(default)]
+    hashes: BTreeMap<String, Digests>,
+    version: Option<String>,
+}
+
+impl RemoteArchive {
+    /// Expands the URL template and picks the hash for the current target.
+    ///
+    /// `default_version` fills `{version}` if the archive doesn't declare its own.
+    fn resolve(&self, default_version: Option<&str>) -> Result<(String, Option<&Digests>), Error> {
+        let target = TargetInfo::from_env()?;
+        let version = self.version.as_deref().or(default_version);
+        let url = expand_url(&self.url, target.triple(), version, &|cfg| match cfg {
+            "arch" => Ok(target.arch().to_owned()),
+            _ => Ok(target.os().to_owned()),
+        })?;
+        let hash = self.hashes.get(target.triple()).or(self.hash.as_ref());
+        Ok((url, hash))
+    }
+
+    /// Expands the URL template and picks the hash for the host, for tools the build runs.
+    pub(crate) fn resolve_host(&self) -> Result<(String, Option<&Digests>), Error> {
+        let host = env_var("HOST")?;
+        let url = expand_url(&self.url, &host, self.version.as_deref(), &|cfg| {
+            Ok(triple_cfg(&host, cfg).to_owned())
+        })?;
+        let hash = self.hashes.get(&host).or(self.hash.as_ref());
+        Ok((url, hash))
+    }
+
+    /// Lists the archive for each target with a hash, plus the one for other targets.
+    ///
+    /// Outside a build script, `{arch}` and `{os}` are derived from the target triple.
+    /// Templates that can't be expanded are reported as they are.
+    fn fetchables(&self, default_version: Option<&str>) -> Vec<Fetchable<'_>> {
+        let version = self.version.as_deref().or(default_version);
+        let expand = |target: Option<&str>| {
+            let target = target.unwrap_or_default();
+            expand_url(&self.url, target, version, &|cfg| {
+                Ok(triple_cfg(target, cfg).to_owned())
+            })
+            .unwrap_or_else(|_| self.url.clone())
+        };
+        let mut fetchables: Vec<Fetchable<'_>> = self
+            .hashes
+            .iter()
+            .map(|(target, hash)| Fetchable {
+                kind: "archive",
+                url: expand(Some(target)),
+                rev: None,
+                hash: Some(hash),
+                target: Some(target.clone()),
+            })
+            .collect();
+        if self.hashes.is_empty() || self.hash.is_some() {
+            fetchables.push(Fetchable {
+                kind: "archive",
+                url: expand(None),
+                rev: None,
+                hash: self.hash.as_ref(),
+                target: None,
+            });
+        }
+        fetchables
+    }
+}
+
+/// A download of a source, as reported to packaging tools.
+pub(crate) struct Fetchable<'a> {
+    /// `archive` or `git`.
+    pub(crate) kind: &'static str,
+    pub(crate) url: String,
+    /// The commit or ref of a git repository.
+    pub(crate) rev: Option<String>,
+    /// The hash of an archive.
+    pub(crate) hash: Option<&'a Digests>,
+    /// The target the download is for, or `None` for all targets.
+    pub(crate) target: Option<String>,
+}
+
+/// Gets a best guess of `target_arch` or `target_os` for a target triple.
+fn triple_cfg<'a>(target: &'a str, cfg: &str) -> &'a str {
+    let mut parts = target.split('-');
+    let arch = parts.next().unwrap_or_default();
+    if cfg == "arch" {
+        return match arch {
+            "i386" | "i586" | "i686" => "x86",
+            _ if arch.starts_with("arm") || arch.starts_with("thumb") => "arm",
+            _ if arch.starts_with("riscv64") => "riscv64",
+            _ if arch.starts_with("riscv32") => "riscv32",
+            _ => arch,
+        };
+    }
+    let rest: Vec<&str> = parts.collect();
+    match rest.as_slice() {
+        ["apple", "darwin", ..] => "macos",
+        ["apple", os, ..] | ["pc", os, ..] | ["unknown", os, ..] => os,
+        [_, "android", ..] | [_, "androideabi", ..] => "android",
+        [os, ..] => os,
+        [] => "",
+    }
+}
+
+/// Expands the variables of a URL template, getting `target_arch` and `target_os`
+/// from `cfg`.
+fn expand_url(
+    template: &str,
+    target: &str,
+    version: Option<&str>,
+    cfg: &dyn Fn(&str) -> Result<String, Error>,
+) -> Result<String, Error> {
+    let invalid = |reason: String| ErrorKind::InvalidUrlTemplate {
+        template: template.to_owned(),
+        reason,
+    };
+    let mut url = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        url.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| invalid("unclosed `{`".to_owned()))?
+            + start;
+        let value = match &rest[start + 1..end] {
+            "version" => version
+                .ok_or_else(|| invalid("`{version}` used but no version declared".to_owned()))?
+                .to_owned(),
+            "target" if !target.is_empty() => target.to_owned(),
+            "arch" if !target.is_empty() => cfg("arch")?,
+            "os" if !target.is_empty() => cfg("os")?,
+            var => return Err(invalid(format!("unknown variable `{{{var}}}`")).into()),
+        };
+        url.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    url.push_str(rest);
+    Ok(url)
+}
+
+/// Prebuilt library archives, one per Rust target.
+///
+/// ```toml
+/// [package.metadata.buildkit.vendored-source.prebuilt-binary]
+/// libs = ["onnxruntime"]
+///
+/// [package.metadata.buildkit.vendored-source.prebuilt-binary.targets.x86_64-unknown-linux-gnu]
+/// url = "https://example.com/onnxruntime-linux-x64.tgz"
+/// hash = "..."
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PrebuiltBinary {
+    /// Archive to download for each Rust target triple.
+    targets: BTreeMap<String, RemoteArchive>,
+    /// Version used for `{version}` in the archive URLs.
+    version: Option<String>,
+    /// Libraries to link, without the `lib` prefix or file extension.
+    libs: Vec<String>,
+    /// Directory of the libraries, relative to the archive root.
+    #[serde(default = "PrebuiltBinary::default_lib_dir")]
+    lib_dir: Utf8PathBuf,
+    /// Directory of the headers, relative to the archive root.
+    #[serde(default = "PrebuiltBinary::default_include_dir")]
+    include_dir: Utf8PathBuf,
+}
+
+impl PrebuiltBinary {
+    fn default_lib_dir() -> Utf8PathBuf {
+        "lib".into()
+    }
+
+    fn default_include_dir() -> Utf8PathBuf {
+        "include".into()
+    }
+
+    fn archive(&self) -> Result<&RemoteArchive, Error> {
+        let target = env_var("TARGET")?;
+        self.targets
+            .get(&target)
+            .ok_or_else(|| ErrorKind::NoPrebuiltForTarget(target).into())
+    }
+
+    /// Gets the directory of the libraries in the unpacked archive at `root`.
+    pub(crate) fn lib_dir(&self, root: &Utf8Path) -> Utf8PathBuf {
+        root.join(&self.lib_dir)
+    }
+
+    /// Emits link directives for the unpacked archive at `root`.
+    pub(crate) fn link(&self, root: &Utf8Path, link_kind: LinkKind) -> Result<BuildOutcome, Error> {
+        let kind = link_kind.as_str();
+        let version = self.version.as_deref().map(Version::parse).transpose()?;
+        let mut outcome = BuildOutcome::found(version);
+        let link = &mut outcome.link;
+        link.search_paths
+            .push(format!("native={}", self.lib_dir(root)));
+        link.libs
+            .extend(self.libs.iter().map(|lib| format!("{kind}={lib}")));
+        println!("cargo:include={}", root.join(&self.include_dir));
+        println!("cargo:root={root}");
+        Ok(outcome)
+    }
+}
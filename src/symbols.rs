@@ -0,0 +1,163 @@
+//! Checking that built libraries define the symbols a crate needs.
+
+use std::collections::BTreeSet;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use object::read::archive::ArchiveFile;
+use object::{Object, ObjectSection, ObjectSymbol};
+
+use crate::{io_error, Error, ErrorKind};
+
+/// Checks that the libraries in `dirs` define all `required` symbols.
+///
+/// This catches misconfigured vendored builds, e.g. a feature accidentally disabled,
+/// before the Rust link step fails with a wall of undefined references.
+pub(crate) fn verify(dirs: &[Utf8PathBuf], required: &[String]) -> Result<(), Error> {
+    if required.is_empty() {
+        return Ok(());
+    }
+    let libraries = libraries(dirs)?;
+    let mut defined = BTreeSet::new();
+    for library in &libraries {
+        defined_symbols(library, &mut defined)?;
+    }
+
+    let missing: Vec<String> = required
+        .iter()
+        .filter(|symbol| {
+            // Mach-O and 32-bit Windows symbols carry a leading underscore.
+            !defined.contains(symbol.as_str()) && !defined.contains(&format!("_{symbol}"))
+        })
+        .cloned()
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(ErrorKind::MissingSymbols {
+        missing,
+        libraries: libraries.iter().map(|path| path.to_string()).collect(),
+    }
+    .into())
+}
+
+/// Lists the static and shared libraries directly in `dirs`, skipping missing ones.
+pub(crate) fn libraries(dirs: &[Utf8PathBuf]) -> Result<Vec<Utf8PathBuf>, Error> {
+    let mut libraries = Vec::new();
+    for dir in dirs {
+        let entries = match dir.read_dir_utf8() {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(io_error(dir)(err).into()),
+        };
+        for entry in entries {
+            let path = entry.map_err(io_error(dir))?.into_path();
+            if is_library(&path) {
+                libraries.push(path);
+            }
+        }
+    }
+    Ok(libraries)
+}
+
+fn is_library(path: &Utf8Path) -> bool {
+    let name = path.file_name().unwrap_or_default();
+    [".a", ".lib", ".so", ".dylib", ".dll"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+        || name.contains(".so.")
+}
+
+/// Adds the global symbols `path` defines to `defined`.
+pub(crate) fn defined_symbols(
+    path: &Utf8Path,
+    defined: &mut BTreeSet<String>,
+) -> Result<(), Error> {
+    let data = std::fs::read(path).map_err(io_error(path))?;
+    let invalid = |err: object::Error| ErrorKind::InvalidLibrary {
+        path: path.to_owned(),
+        reason: err.to_string(),
+    };
+
+    if let Ok(archive) = ArchiveFile::parse(&*data) {
+        // The archive's symbol table also covers import libraries,
+        // whose members aren't regular object files.
+        if let Some(symbols) = archive.symbols().map_err(invalid)? {
+            for symbol in symbols {
+                let name = symbol.map_err(invalid)?.name();
+                defined.insert(String::from_utf8_lossy(name).into_owned());
+            }
+            return Ok(());
+        }
+        for member in archive.members() {
+            let member = member.map_err(invalid)?;
+            let member_data = member.data(&*data).map_err(invalid)?;
+            if let Ok(file) = object::File::parse(member_data) {
+                insert_definitions(&file, defined);
+            }
+        }
+        return Ok(());
+    }
+
+    let file = object::File::parse(&*data).map_err(invalid)?;
+    insert_definitions(&file, defined);
+    for export in file.exports().map_err(invalid)? {
+        defined.insert(String::from_utf8_lossy(export.name()).into_owned());
+    }
+    Ok(())
+}
+
+/// Whether the library at `path` was built against glibc: a shared library needing `GLIBC_*`
+/// symbol versions, or objects referencing glibc-only symbols, like `__isoc99_sscanf` or the
+/// `__*_chk` functions of `_FORTIFY_SOURCE`.
+pub(crate) fn requires_glibc(path: &Utf8Path) -> Result<bool, Error> {
+    let data = std::fs::read(path).map_err(io_error(path))?;
+    let invalid = |err: object::Error| ErrorKind::InvalidLibrary {
+        path: path.to_owned(),
+        reason: err.to_string(),
+    };
+
+    if let Ok(archive) = ArchiveFile::parse(&*data) {
+        for member in archive.members() {
+            let member = member.map_err(invalid)?;
+            let member_data = member.data(&*data).map_err(invalid)?;
+            if let Ok(file) = object::File::parse(member_data) {
+                if references_glibc(&file) {
+                    return Ok(true);
+                }
+            }
+        }
+        return Ok(false);
+    }
+
+    let file = object::File::parse(&*data).map_err(invalid)?;
+    let versioned = file
+        .section_by_name(".dynstr")
+        .and_then(|section| section.data().ok())
+        .map_or(false, |strings| {
+            strings
+                .split(|byte| *byte == 0)
+                .any(|string| string.starts_with(b"GLIBC_"))
+        });
+    Ok(versioned || references_glibc(&file))
+}
+
+fn references_glibc(file: &object::File<'_>) -> bool {
+    let symbols = file.symbols().chain(file.dynamic_symbols());
+    symbols
+        .filter(|symbol| symbol.is_undefined())
+        .filter_map(|symbol| symbol.name().ok())
+        .any(|name| {
+            name.starts_with("__isoc99_")
+                || name.starts_with("__isoc23_")
+                || (name.starts_with("__") && name.ends_with("_chk"))
+        })
+}
+
+fn insert_definitions(file: &object::File<'_>, defined: &mut BTreeSet<String>) {
+    let symbols = file.symbols().chain(file.dynamic_symbols());
+    for symbol in symbols.filter(|s| s.is_definition() && s.is_global()) {
+        if let Ok(name) = symbol.name() {
+            defined.insert(name.to_owned());
+        }
+    }
+}
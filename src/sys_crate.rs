@@ -0,0 +1,118 @@
+//! Taking libraries from other `-sys` crates.
+//!
+//! A `-sys` crate with `links = "z"` passes what its build script prints as `cargo:<key>=…`
+//! on to the crates depending on it as `DEP_Z_<KEY>`. By convention, `include` and `root`
+//! point to the headers and the install prefix of the library it built or found, which is
+//! how e.g. libgit2-sys builds against libz-sys's zlib rather than a second copy.
+//! A crate can also take its whole library from another, e.g. one workspace member building
+//! a library that several `-sys` crates bind.
+
+use camino::Utf8PathBuf;
+use serde::Deserialize;
+
+use crate::link::LinkDirectives;
+use crate::{utf8_path, BuildOutcome, Error, ErrorKind, Version};
+
+/// A library provided by the `-sys` crate with the given `links`, which has to be
+/// a regular dependency of the package.
+///
+/// ```toml
+/// dependencies = [{ links = "z" }]
+///
+/// [package.metadata.buildkit.vendored-source.sys-crate]
+/// links = "git2"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct SysCrate {
+    links: String,
+    /// Libraries to link, for crates that build them without linking them.
+    #[serde(default)]
+    libs: Vec<String>,
+}
+
+impl SysCrate {
+    /// Gets the include and library directories from the crate's `DEP_<links>_INCLUDE`
+    /// and `DEP_<links>_ROOT`.
+    pub(crate) fn paths(&self) -> Result<(Vec<Utf8PathBuf>, Vec<Utf8PathBuf>), Error> {
+        let include_var = self.var("INCLUDE");
+        let root_var = self.var("ROOT");
+        let include = std::env::var_os(&include_var);
+        let root = std::env::var_os(&root_var);
+        if include.is_none() && root.is_none() {
+            return Err(ErrorKind::MissingSysCrate {
+                links: self.links.clone(),
+                vars: format!("{include_var} or {root_var}"),
+            }
+            .into());
+        }
+
+        let mut include_paths = match include {
+            Some(paths) => std::env::split_paths(&paths)
+                .map(|path| utf8_path(path, &include_var))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+        let mut lib_paths = Vec::new();
+        if let Some(root) = root
+            .map(|root| utf8_path(root.into(), &root_var))
+            .transpose()?
+        {
+            if include_paths.is_empty() {
+                include_paths.push(root.join("include"));
+            }
+            lib_paths.extend(
+                ["lib", "lib64"]
+                    .iter()
+                    .map(|dir| root.join(dir))
+                    .filter(|dir| dir.is_dir()),
+            );
+        }
+        Ok((include_paths, lib_paths))
+    }
+
+    /// Links the crate's library as this package's, passing its paths on to dependents
+    /// as this package's `include` and `root`.
+    pub(crate) fn link(&self) -> Result<BuildOutcome, Error> {
+        let (include_paths, lib_paths) = self.paths()?;
+        let version = std::env::var(self.var("VERSION"))
+            .ok()
+            .and_then(|version| Version::parse(&version).ok());
+        let mut outcome = BuildOutcome::found(version);
+        outcome.link = self.link_directives(&lib_paths);
+        for include in &include_paths {
+            println!("cargo:include={include}");
+        }
+        if let Ok(root) = self.root() {
+            println!("cargo:root={root}");
+        }
+        Ok(outcome)
+    }
+
+    /// Gets the install prefix from the crate's `DEP_<links>_ROOT`.
+    pub(crate) fn root(&self) -> Result<Utf8PathBuf, Error> {
+        let root_var = self.var("ROOT");
+        match std::env::var(&root_var) {
+            Ok(root) => Ok(root.into()),
+            Err(_) => Err(ErrorKind::MissingSysCrate {
+                links: self.links.clone(),
+                vars: root_var,
+            }
+            .into()),
+        }
+    }
+
+    /// Gets the search paths for `lib_paths` and the `libs` to link explicitly.
+    pub(crate) fn link_directives(&self, lib_paths: &[Utf8PathBuf]) -> LinkDirectives {
+        let mut link = LinkDirectives::default();
+        link.search_paths
+            .extend(lib_paths.iter().map(|dir| format!("native={dir}")));
+        link.libs.extend(self.libs.iter().cloned());
+        link
+    }
+
+    /// Gets the name of the crate's `DEP_<links>_<key>` variable.
+    fn var(&self, key: &str) -> String {
+        format!("DEP_{}_{key}", self.links.to_uppercase().replace('-', "_"))
+    }
+}
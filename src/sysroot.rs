@@ -0,0 +1,127 @@
+//! Building against the target sysroot of an embedded Linux SDK.
+//!
+//! The SDK of Yocto/OpenEmbedded, sourced from its `environment-setup-*` script, describes the
+//! target in the environment: `OECORE_TARGET_SYSROOT` holds its libraries and headers,
+//! `CC` wraps the cross compiler with `--sysroot`, and `PKG_CONFIG_SYSROOT_DIR` and
+//! `PKG_CONFIG_PATH` point pkg-config there. Buildroot runs package builds with the
+//! toolchain in `CC` and the sysroot in `STAGING_DIR`, under its host directory.
+//! Anything found outside the sysroot is a library of the build machine, which links if its
+//! architecture happens to match and then fails on the device, so it's refused instead.
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::cross;
+use crate::link::LinkDirectives;
+use crate::pc_file::PkgConfigEnv;
+use crate::{env_var, Error, ErrorKind};
+
+/// The target sysroot of the SDK the build runs in.
+#[derive(Debug)]
+pub(crate) struct Sysroot {
+    path: Utf8PathBuf,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Yocto,
+    Buildroot,
+}
+
+impl Sysroot {
+    /// Finds the sysroot from the environment of a Yocto SDK or a Buildroot package build,
+    /// if the build runs in one and cross-compiles. Native builds leave these variables
+    /// alone, as they may just be left over in the user's shell.
+    pub(crate) fn detect() -> Result<Option<Sysroot>, Error> {
+        if env_var("TARGET")? == env_var("HOST")? {
+            return Ok(None);
+        }
+        let var = |key| {
+            println!("cargo:rerun-if-env-changed={key}");
+            std::env::var(key).ok().filter(|path| !path.is_empty())
+        };
+        if let Some(path) = var("OECORE_TARGET_SYSROOT") {
+            return Ok(Some(Sysroot {
+                path: path.into(),
+                kind: Kind::Yocto,
+            }));
+        }
+        // Other build systems use `STAGING_DIR` differently; Buildroot's is a sysroot.
+        let path = match var("STAGING_DIR") {
+            Some(path) => Utf8PathBuf::from(path),
+            None => return Ok(None),
+        };
+        if path.join("usr/lib").is_dir() {
+            return Ok(Some(Sysroot {
+                path,
+                kind: Kind::Buildroot,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Probes the sysroot's `.pc` files with its paths, and only those: pkg-config's own
+    /// directories are the build machine's.
+    pub(crate) fn configure_pkg_config(&self, pkg_env: &mut PkgConfigEnv) {
+        pkg_env.set_default("PKG_CONFIG_SYSROOT_DIR", self.path.as_str());
+        let dirs = ["usr/lib/pkgconfig", "usr/share/pkgconfig"]
+            .map(|dir| self.path.join(dir).into_string());
+        pkg_env.set_default("PKG_CONFIG_LIBDIR", dirs.join(":"));
+        // The pkg-config crate refuses cross builds without it.
+        pkg_env.set_default("PKG_CONFIG_ALLOW_CROSS", "1");
+    }
+
+    /// Fails if `link` searches for libraries outside the sysroot.
+    pub(crate) fn check(&self, library: &str, link: &LinkDirectives) -> Result<(), Error> {
+        for path in &link.search_paths {
+            let dir = match path.split_once('=') {
+                Some((_, dir)) => dir,
+                None => path.as_str(),
+            };
+            if !Utf8Path::new(dir).starts_with(&self.path) {
+                return Err(ErrorKind::OutsideSysroot {
+                    library: library.to_owned(),
+                    path: dir.into(),
+                    sysroot: self.path.clone(),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the GNU triple the SDK's toolchain builds for, e.g. `aarch64-poky-linux`,
+    /// from Yocto's `TARGET_PREFIX` or the name of Buildroot's `CC`.
+    pub(crate) fn host_triple(&self) -> Option<String> {
+        let prefix = match self.kind {
+            Kind::Yocto => {
+                println!("cargo:rerun-if-env-changed=TARGET_PREFIX");
+                std::env::var("TARGET_PREFIX").ok()?
+            }
+            Kind::Buildroot => {
+                println!("cargo:rerun-if-env-changed=CC");
+                let cc = std::env::var("CC").ok()?;
+                let name = Utf8Path::new(&cc).file_name()?;
+                cross::tool_prefix(name)?
+            }
+        };
+        let triple = prefix.trim_end_matches('-');
+        Some(triple.to_owned()).filter(|triple| !triple.is_empty())
+    }
+
+    /// Gets the CMake toolchain file the SDK provides.
+    pub(crate) fn cmake_toolchain_file(&self) -> Option<String> {
+        match self.kind {
+            Kind::Yocto => {
+                println!("cargo:rerun-if-env-changed=OE_CMAKE_TOOLCHAIN_FILE");
+                std::env::var("OE_CMAKE_TOOLCHAIN_FILE").ok()
+            }
+            Kind::Buildroot => {
+                // `STAGING_DIR` is `<host dir>/<triple>/sysroot`.
+                let host_dir = self.path.ancestors().nth(2)?;
+                let file = host_dir.join("share/buildroot/toolchainfile.cmake");
+                Some(file.into_string()).filter(|file| Utf8Path::new(file).is_file())
+            }
+        }
+    }
+}
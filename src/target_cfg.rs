@@ -0,0 +1,173 @@
+//! Matching the target against `cfg(...)` expressions or triple patterns.
+//!
+//! Metadata keyed by target accepts what cargo's `[target.<key>]` tables do:
+//! `cfg(all(windows, target_env = "gnu"))` evaluated like rustc would, from the
+//! [`TargetInfo`], or a target triple, here with `*` and `?` wildcards.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::{target_modes, Error, ErrorKind, TargetInfo};
+
+/// Whether the target matches `key`, a `cfg(...)` expression or a triple pattern.
+pub(crate) fn matches(key: &str, target: &TargetInfo) -> Result<bool, Error> {
+    let expr = match key.trim().strip_prefix("cfg(") {
+        Some(rest) => match rest.strip_suffix(')') {
+            Some(expr) => expr,
+            None => return Err(invalid(key, "missing `)`")),
+        },
+        None => return Ok(target_modes::glob_matches(key, target.triple())),
+    };
+    let mut parser = Parser {
+        target,
+        expr,
+        chars: expr.char_indices().peekable(),
+    };
+    let result = parser.predicate().and_then(|matched| match parser.token() {
+        Token::End => Ok(matched),
+        token => Err(format!("unexpected {token}")),
+    });
+    result.map_err(|reason| invalid(key, &reason))
+}
+
+fn invalid(key: &str, reason: &str) -> Error {
+    ErrorKind::InvalidCfg {
+        expr: key.to_owned(),
+        reason: reason.to_owned(),
+    }
+    .into()
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Ident(&'a str),
+    Str(&'a str),
+    Open,
+    Close,
+    Comma,
+    Equals,
+    End,
+    Unexpected(char),
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{name}`"),
+            Token::Str(value) => write!(f, "`\"{value}\"`"),
+            Token::Open => f.write_str("`(`"),
+            Token::Close => f.write_str("`)`"),
+            Token::Comma => f.write_str("`,`"),
+            Token::Equals => f.write_str("`=`"),
+            Token::End => f.write_str("the end"),
+            Token::Unexpected(c) => write!(f, "`{c}`"),
+        }
+    }
+}
+
+struct Parser<'a> {
+    target: &'a TargetInfo,
+    expr: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses and evaluates `all(..)`, `any(..)`, `not(..)`, `name` or `name = "value"`.
+    fn predicate(&mut self) -> Result<bool, String> {
+        let name = match self.token() {
+            Token::Ident(name) => name,
+            token => return Err(format!("expected a cfg name, found {token}")),
+        };
+        if matches!(name, "all" | "any" | "not") {
+            self.expect(Token::Open)?;
+            let mut values = Vec::new();
+            loop {
+                if self.peek_close() {
+                    self.token();
+                    break;
+                }
+                values.push(self.predicate()?);
+                match self.token() {
+                    Token::Comma => {}
+                    Token::Close => break,
+                    token => return Err(format!("expected `,` or `)`, found {token}")),
+                }
+            }
+            return match name {
+                "all" => Ok(values.iter().all(|&value| value)),
+                "any" => Ok(values.iter().any(|&value| value)),
+                _ if values.len() == 1 => Ok(!values[0]),
+                _ => Err("`not` takes one predicate".to_owned()),
+            };
+        }
+        if !self.peek_equals() {
+            return Ok(self.target.cfg(name).is_some());
+        }
+        self.token();
+        match self.token() {
+            Token::Str(value) => Ok(self.target.cfg(name).map_or(false, |values| {
+                values.iter().any(|candidate| candidate == value)
+            })),
+            token => Err(format!("expected a string, found {token}")),
+        }
+    }
+
+    fn expect(&mut self, expected: Token<'_>) -> Result<(), String> {
+        let token = self.token();
+        if token == expected {
+            Ok(())
+        } else {
+            Err(format!("expected {expected}, found {token}"))
+        }
+    }
+
+    fn peek_close(&mut self) -> bool {
+        self.skip_whitespace();
+        matches!(self.chars.peek(), Some((_, ')')))
+    }
+
+    fn peek_equals(&mut self) -> bool {
+        self.skip_whitespace();
+        matches!(self.chars.peek(), Some((_, '=')))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some((_, c)) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn token(&mut self) -> Token<'a> {
+        self.skip_whitespace();
+        let (start, c) = match self.chars.next() {
+            Some(next) => next,
+            None => return Token::End,
+        };
+        match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '=' => Token::Equals,
+            '"' => {
+                let end = match self.chars.find(|&(_, c)| c == '"') {
+                    Some((end, _)) => end,
+                    None => return Token::Unexpected('"'),
+                };
+                Token::Str(&self.expr[start + 1..end])
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = self.chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    self.chars.next();
+                }
+                Token::Ident(&self.expr[start..end])
+            }
+            c => Token::Unexpected(c),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! The target's configuration, as cargo passes it to build scripts.
+
+use std::collections::BTreeMap;
+
+use crate::{env_overrides, env_var, Error};
+
+/// The configuration of the target being built for, from the `CARGO_CFG_*` variables.
+///
+/// These are what `#[cfg(...)]` sees, so build scripts don't need to take the target triple
+/// apart, which gets custom targets and new triples wrong.
+#[derive(Debug, Clone)]
+pub struct TargetInfo {
+    triple: String,
+    /// The values of each cfg, e.g. `target_feature` to `["fxsr", "sse", "sse2"]`,
+    /// or an empty list for set names like `unix`.
+    cfgs: BTreeMap<String, Vec<String>>,
+    pointer_width: u32,
+}
+
+impl TargetInfo {
+    /// Reads the configuration of the current build script's target.
+    pub fn from_env() -> Result<TargetInfo, Error> {
+        let mut cfgs = BTreeMap::new();
+        for (key, value) in std::env::vars_os() {
+            let key = match key.into_string() {
+                Ok(key) => key,
+                Err(_) => continue,
+            };
+            if let (Some(name), Ok(value)) = (key.strip_prefix("CARGO_CFG_"), value.into_string()) {
+                let values = value
+                    .split(',')
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_owned)
+                    .collect();
+                cfgs.insert(name.to_lowercase(), values);
+            }
+        }
+        let pointer_width = env_var("CARGO_CFG_TARGET_POINTER_WIDTH")?;
+        let pointer_width = pointer_width.parse().map_err(|_| {
+            env_overrides::invalid(
+                "CARGO_CFG_TARGET_POINTER_WIDTH",
+                format!("`{pointer_width}` is not a number of bits"),
+            )
+        })?;
+        Ok(TargetInfo {
+            triple: env_var("TARGET")?,
+            cfgs,
+            pointer_width,
+        })
+    }
+
+    /// Gets the target triple, e.g. `x86_64-unknown-linux-gnu`.
+    pub fn triple(&self) -> &str {
+        &self.triple
+    }
+
+    /// Gets `target_arch`, e.g. `x86_64` or `aarch64`.
+    pub fn arch(&self) -> &str {
+        self.first("target_arch")
+    }
+
+    /// Gets `target_os`, e.g. `linux`, `macos`, `windows` or `none`.
+    pub fn os(&self) -> &str {
+        self.first("target_os")
+    }
+
+    /// Gets `target_env`, e.g. `gnu`, `musl` or `msvc`, or an empty string.
+    pub fn env(&self) -> &str {
+        self.first("target_env")
+    }
+
+    /// Gets `target_vendor`, e.g. `apple`, `pc` or `unknown`.
+    pub fn vendor(&self) -> &str {
+        self.first("target_vendor")
+    }
+
+    /// Gets `target_abi`, e.g. `eabihf` or `sim`, or an empty string.
+    pub fn abi(&self) -> &str {
+        self.first("target_abi")
+    }
+
+    /// Gets the `target_family` values, e.g. `unix`, or `unix` and `wasm`.
+    pub fn families(&self) -> &[String] {
+        self.values("target_family")
+    }
+
+    /// Gets `target_endian`, `little` or `big`.
+    pub fn endian(&self) -> &str {
+        self.first("target_endian")
+    }
+
+    /// Gets `target_pointer_width` in bits.
+    pub fn pointer_width(&self) -> u32 {
+        self.pointer_width
+    }
+
+    /// Gets the enabled `target_feature`s, e.g. `crt-static` or `sse2`.
+    pub fn features(&self) -> &[String] {
+        self.values("target_feature")
+    }
+
+    /// Whether the target feature `name` is enabled.
+    pub fn has_feature(&self, name: &str) -> bool {
+        self.features().iter().any(|feature| feature == name)
+    }
+
+    /// Whether the target is an Apple platform.
+    pub fn is_apple(&self) -> bool {
+        self.vendor() == "apple"
+    }
+
+    /// Gets the values of the cfg `name`, or `None` if it isn't set.
+    pub(crate) fn cfg(&self, name: &str) -> Option<&[String]> {
+        self.cfgs.get(name).map(Vec::as_slice)
+    }
+
+    fn values(&self, name: &str) -> &[String] {
+        self.cfg(name).unwrap_or_default()
+    }
+
+    fn first(&self, name: &str) -> &str {
+        self.values(name).first().map_or("", String::as_str)
+    }
+}
@@ -0,0 +1,55 @@
+//! Choosing the mode by target triple.
+//!
+//! ```toml
+//! [package.metadata.buildkit.target-modes]
+//! "*-unknown-linux-*" = "pkg-config"
+//! "*-pc-windows-*" = "vcpkg"
+//! "wasm32-*" = "vendored-build"
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::BuildKitMode;
+
+/// Gets the mode of the pattern matching `target`, the longest if several do, as that's
+/// the most specific. Patterns use `*` for any run of characters and `?` for one.
+pub(crate) fn mode_for(
+    rules: &BTreeMap<String, BuildKitMode>,
+    target: &str,
+) -> Option<BuildKitMode> {
+    rules
+        .iter()
+        .filter(|(pattern, _)| glob_matches(pattern, target))
+        .max_by_key(|(pattern, _)| pattern.len())
+        .map(|(_, mode)| *mode)
+}
+
+/// Whether `text` matches `pattern`, with `*` for any run of characters and `?` for one.
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was and the text position it currently matches up to.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,42 @@
+//! Building on Android in Termux.
+//!
+//! Termux installs packages under its app's `$PREFIX`, `/data/data/com.termux/files/usr`,
+//! rather than `/usr`, including its pkg-config and `.pc` files.
+
+use std::path::PathBuf;
+
+use crate::pc_file::PkgConfigEnv;
+use crate::{env_var, Error, TargetInfo};
+
+/// Gets Termux's `$PREFIX` when building natively in Termux.
+pub(crate) fn prefix(target: &TargetInfo) -> Result<Option<PathBuf>, Error> {
+    if target.os() != "android" || env_var("HOST")? != target.triple() {
+        return Ok(None);
+    }
+    println!("cargo:rerun-if-env-changed=PREFIX");
+    Ok(std::env::var("PREFIX")
+        .ok()
+        .filter(|prefix| prefix.starts_with("/data/data/com.termux/"))
+        .map(PathBuf::from))
+}
+
+/// Probes with Termux's pkg-config, unless `PKG_CONFIG` picks one, and its `.pc` files
+/// through `pkg_env`.
+pub(crate) fn configure_pkg_config(
+    target: &TargetInfo,
+    pkg_env: &mut PkgConfigEnv,
+) -> Result<(), Error> {
+    let prefix = match prefix(target)? {
+        Some(prefix) => prefix,
+        None => return Ok(()),
+    };
+    let pkg_config = prefix.join("bin").join("pkg-config");
+    if pkg_config.exists() {
+        pkg_env.set_default("PKG_CONFIG", pkg_config);
+    }
+    pkg_env.append_dirs([
+        prefix.join("lib").join("pkgconfig"),
+        prefix.join("share").join("pkgconfig"),
+    ]);
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Measuring where a build script spends its time.
+//!
+//! A cold build can spend minutes in a C dependency, and cargo's own timings show only the
+//! build script as a whole. Parsing metadata, fetching and verifying sources, probing and the
+//! vendored build are timed separately, each without the time of the others nested in it,
+//! e.g. a dependency probed during the vendored build.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime};
+
+use camino::Utf8Path;
+use cargo_metadata::MetadataCommand;
+
+use crate::conflicts::build_outputs;
+use crate::{env_overrides, env_var, io_error, Error, ErrorKind};
+
+thread_local! {
+    static TOTALS: RefCell<BTreeMap<&'static str, Duration>> = RefCell::new(BTreeMap::new());
+    /// The time spent in phases nested in each running one.
+    static NESTED: RefCell<Vec<Duration>> = RefCell::new(Vec::new());
+}
+
+/// Runs `f` as part of `phase`, e.g. `"fetch"`.
+pub(crate) fn time<T>(phase: &'static str, f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!(target: "buildkit", "phase", phase).entered();
+    NESTED.with(|nested| nested.borrow_mut().push(Duration::ZERO));
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    NESTED.with(|nested| {
+        let mut nested = nested.borrow_mut();
+        let inner = nested.pop().unwrap_or_default();
+        if let Some(outer) = nested.last_mut() {
+            *outer += elapsed;
+        }
+        TOTALS.with(|totals| {
+            *totals.borrow_mut().entry(phase).or_default() += elapsed.saturating_sub(inner);
+        });
+    });
+    result
+}
+
+/// Passes on the time of each phase as `cargo:timings`, and with `BUILDKIT_TIMINGS=1`
+/// shows them as a warning.
+pub(crate) fn report() -> Result<(), Error> {
+    let totals = TOTALS.with(|totals| totals.borrow().clone());
+    if totals.is_empty() {
+        return Ok(());
+    }
+    let phases: Vec<String> = totals
+        .iter()
+        .map(|(phase, duration)| format!("{phase}={:.3}", duration.as_secs_f64()))
+        .collect();
+    println!(
+        "cargo:timings={};{}",
+        env_var("CARGO_PKG_NAME")?,
+        phases.join(";")
+    );
+    if env_overrides::flag("BUILDKIT_TIMINGS")? {
+        let phases: Vec<String> = totals
+            .iter()
+            .map(|(phase, duration)| format!("{phase} {:.1}s", duration.as_secs_f64()))
+            .collect();
+        warning!("buildkit timings: {}", phases.join(", "));
+    }
+    Ok(())
+}
+
+/// The time the build scripts of the crates of a build spent in each phase, in seconds,
+/// from the build script outputs cargo keeps in the target directory.
+///
+/// For a package built several times, e.g. for several profiles, the latest build counts.
+pub struct Timings {
+    packages: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+impl Timings {
+    /// Collects the timings from the target directory of the workspace at `manifest_path`.
+    pub fn from_manifest(manifest_path: impl AsRef<Utf8Path>) -> Result<Timings, Error> {
+        let metadata = MetadataCommand::new()
+            .manifest_path(manifest_path.as_ref())
+            .no_deps()
+            .exec()
+            .map_err(ErrorKind::CargoMetadataError)?;
+        Timings::from_target_dir(metadata.target_directory)
+    }
+
+    /// Collects the timings from the build script outputs under `target_dir`,
+    /// for every profile and target.
+    pub fn from_target_dir(target_dir: impl AsRef<Utf8Path>) -> Result<Timings, Error> {
+        let mut latest: BTreeMap<String, (SystemTime, BTreeMap<String, f64>)> = BTreeMap::new();
+        for output in build_outputs(target_dir.as_ref())? {
+            let contents = std::fs::read_to_string(&output).map_err(io_error(&output))?;
+            let value = match contents
+                .lines()
+                .find_map(|line| line.strip_prefix("cargo:timings="))
+            {
+                Some(value) => value,
+                None => continue,
+            };
+            let modified = std::fs::metadata(&output)
+                .and_then(|metadata| metadata.modified())
+                .map_err(io_error(&output))?;
+            let mut parts = value.split(';');
+            let package = parts.next().unwrap_or_default().to_owned();
+            let phases = parts
+                .filter_map(|part| {
+                    let (phase, seconds) = part.split_once('=')?;
+                    Some((phase.to_owned(), seconds.parse().ok()?))
+                })
+                .collect();
+            if latest
+                .get(&package)
+                .map_or(true, |(previous, _)| modified > *previous)
+            {
+                latest.insert(package, (modified, phases));
+            }
+        }
+        let packages = latest
+            .into_iter()
+            .map(|(package, (_, phases))| (package, phases))
+            .collect();
+        Ok(Timings { packages })
+    }
+
+    /// Renders the seconds of each phase by package as a JSON object,
+    /// e.g. `{"libz-sys": {"build": 4.2, "fetch": 0.8}}`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.packages).expect("JSON values always serialize")
+    }
+}
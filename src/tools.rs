@@ -0,0 +1,257 @@
+//! Checking that the executables a vendored build runs are installed.
+//!
+//! A missing `cmake` or `nasm` otherwise shows up as a "command not found" somewhere in
+//! hundreds of lines of configure or make output, or a too old one as a syntax error in
+//! `CMakeLists.txt`. Tools that are often missing, e.g. on Windows CI, can instead be
+//! downloaded as pinned builds and put first on the vendored build's `PATH`. Interpreters
+//! are checked the same way, with a hint how to install them on the host.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::fetch::{self, FetchContext};
+use crate::source::RemoteArchive;
+use crate::{env_overrides, run_command, Error, ErrorKind, Version};
+
+/// An executable the vendored build needs on `PATH`.
+///
+/// ```toml
+/// [[package.metadata.buildkit.tools]]
+/// name = "cmake"
+/// min-version = "3.20"
+///
+/// [[package.metadata.buildkit.tools]]
+/// name = "nasm"
+///
+/// [package.metadata.buildkit.tools.bootstrap]
+/// url = "https://www.nasm.us/pub/nasm/releasebuilds/{version}/win64/nasm-{version}-win64.zip"
+/// version = "2.16.03"
+/// hashes = { x86_64-pc-windows-msvc = "..." }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ToolRequirement {
+    name: String,
+    /// The oldest version that works, compared with the one `<name> --version` prints.
+    min_version: Option<String>,
+    /// A pinned build of the tool to use when it's missing or too old and
+    /// `BUILDKIT_BOOTSTRAP_TOOLS=1`, with `hashes` keyed by the host triple.
+    bootstrap: Option<RemoteArchive>,
+}
+
+impl ToolRequirement {
+    /// Checks that the tool is on `PATH` and recent enough.
+    fn check(&self) -> Result<(), Error> {
+        let path = find(&self.name).ok_or_else(|| ErrorKind::MissingTool(self.name.clone()))?;
+        check_version(&self.name, &path, self.min_version.as_deref())
+    }
+
+    /// Downloads the pinned build, checks its version and returns the directory with the
+    /// executable.
+    fn bootstrap(&self, archive: &RemoteArchive, cx: &FetchContext<'_>) -> Result<PathBuf, Error> {
+        let (url, hash) = archive.resolve_host()?;
+        // The tree hash is the vendored source's.
+        let cx = FetchContext {
+            tree_hash: None,
+            ..*cx
+        };
+        let root = fetch::fetch_archive(&url, hash, &cx)?;
+        let path = [root.join("bin"), root]
+            .iter()
+            .map(|dir| dir.join(&self.name).into_std_path_buf())
+            .find_map(|path| candidates(path).find(|path| path.is_file()))
+            .ok_or_else(|| ErrorKind::MissingTool(format!("{} in {url}", self.name)))?;
+        check_version(&self.name, &path, self.min_version.as_deref())?;
+        Ok(path.parent().map(Path::to_owned).unwrap_or_default())
+    }
+}
+
+/// An interpreter the vendored build's scripts run, e.g. perl for OpenSSL's `Configure`
+/// or python3 for Meson projects.
+///
+/// ```toml
+/// needs = ["perl", { name = "python3", min-version = "3.8" }]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Prerequisite {
+    Name(String),
+    #[serde(rename_all = "kebab-case")]
+    Versioned {
+        name: String,
+        min_version: Option<String>,
+    },
+}
+
+impl Prerequisite {
+    /// Checks that the interpreter is on `PATH` and recent enough,
+    /// warning how to install it if it's not.
+    fn check(&self) -> Result<(), Error> {
+        let (name, min_version) = match self {
+            Prerequisite::Name(name) => (name, None),
+            Prerequisite::Versioned { name, min_version } => (name, min_version.as_deref()),
+        };
+        // Windows installs Python 3 as `python`.
+        let (names, implied_version): (&[&str], _) = match name.as_str() {
+            "python3" => (&["python3", "python"], Some("3")),
+            name => (&[name][..], None),
+        };
+        let result = match names.iter().find_map(|name| find(name)) {
+            Some(path) => check_version(name, &path, min_version.or(implied_version)),
+            None => Err(ErrorKind::MissingTool(name.clone()).into()),
+        };
+        if result.is_err() {
+            if let Some(hint) = install_hint(name) {
+                warning!("install {name} with {hint}");
+            }
+        }
+        result
+    }
+}
+
+/// Checks that each interpreter is on `PATH` and recent enough.
+pub(crate) fn check_prerequisites(needs: &[Prerequisite]) -> Result<(), Error> {
+    needs.iter().try_for_each(Prerequisite::check)
+}
+
+/// Gets how to install an interpreter on the host.
+fn install_hint(name: &str) -> Option<String> {
+    let hint = if cfg!(windows) {
+        match name {
+            // MSYS2's perl produces Unix paths that MSVC builds can't use.
+            "perl" => "Strawberry Perl, e.g. `winget install StrawberryPerl.StrawberryPerl`",
+            "python3" | "python" => "`winget install Python.Python.3.12`",
+            _ => return None,
+        }
+        .to_owned()
+    } else if cfg!(target_os = "macos") {
+        let formula = if name == "python3" { "python" } else { name };
+        format!("`brew install {formula}`")
+    } else if Path::new("/etc/alpine-release").exists() {
+        format!("`apk add {name}`")
+    } else {
+        // Fedora's plain perl package leaves out modules build scripts commonly use.
+        let rpm = if name == "perl" { "perl-core" } else { name };
+        format!("`apt install {name}` or `dnf install {rpm}`")
+    };
+    Some(hint)
+}
+
+/// Checks that each tool is on `PATH` and recent enough, downloading the pinned builds of
+/// those that aren't if `BUILDKIT_BOOTSTRAP_TOOLS=1`, and returns `PATH` with their
+/// directories first if any were.
+pub(crate) fn check(
+    tools: &[ToolRequirement],
+    cx: &FetchContext<'_>,
+) -> Result<Option<String>, Error> {
+    let mut bootstrapped = Vec::new();
+    for tool in tools {
+        match (tool.check(), &tool.bootstrap) {
+            (Ok(()), _) => {}
+            (Err(err), Some(archive)) if env_overrides::flag("BUILDKIT_BOOTSTRAP_TOOLS")? => {
+                bootstrapped.push(tool.bootstrap(archive, cx)?);
+                warning!(
+                    "{err}; using the pinned download of `{}` instead",
+                    tool.name
+                );
+            }
+            (Err(err), Some(_)) => {
+                warning!(
+                    "set BUILDKIT_BOOTSTRAP_TOOLS=1 to use the pinned download of `{}`",
+                    tool.name
+                );
+                return Err(err);
+            }
+            (Err(err), None) => return Err(err),
+        }
+    }
+    if bootstrapped.is_empty() {
+        return Ok(None);
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let path = std::env::join_paths(bootstrapped.into_iter().chain(std::env::split_paths(&path)))
+        .map_err(|err| env_overrides::invalid("PATH", err.to_string()))?;
+    match path.into_string() {
+        Ok(path) => Ok(Some(path)),
+        Err(_) => Err(env_overrides::invalid(
+            "PATH",
+            "it isn't valid UTF-8".to_owned(),
+        )),
+    }
+}
+
+/// Checks that the tool at `path` is at least `min_version`, if given.
+fn check_version(name: &str, path: &Path, min_version: Option<&str>) -> Result<(), Error> {
+    let min_version = match min_version {
+        Some(min_version) => Version::parse(min_version)?,
+        None => return Ok(()),
+    };
+    let output = run_command(Command::new(path).arg("--version"))?;
+    match parse_version(&output) {
+        Some(found) if found < min_version => Err(ErrorKind::ToolTooOld {
+            name: name.to_owned(),
+            found: found.to_string(),
+            required: min_version.to_string(),
+        }
+        .into()),
+        Some(_) => Ok(()),
+        None => {
+            warning!(
+                "couldn't tell the version of `{name}` to check it's at least \
+                 {min_version}"
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Finds the executable `name` on `PATH`, or at `name` itself if it's a path.
+pub(crate) fn find(name: &str) -> Option<PathBuf> {
+    find_on(name, &std::env::var_os("PATH")?)
+}
+
+/// Finds the executable `name` in the directories of `path`, or at `name` itself
+/// if it's a path.
+pub(crate) fn find_on(name: &str, path: &OsStr) -> Option<PathBuf> {
+    if Path::new(name).components().count() > 1 {
+        return candidates(PathBuf::from(name)).find(|path| path.is_file());
+    }
+    std::env::split_paths(path)
+        .flat_map(|dir| candidates(dir.join(name)))
+        .find(|path| path.is_file())
+}
+
+/// Gets `path` and, on Windows, the paths with executable extensions added.
+fn candidates(path: PathBuf) -> impl Iterator<Item = PathBuf> {
+    let extensions: &[&str] = if cfg!(windows) {
+        &["exe", "cmd", "bat"]
+    } else {
+        &[]
+    };
+    let with_extensions: Vec<PathBuf> = extensions
+        .iter()
+        .map(|extension| {
+            let mut path = path.clone().into_os_string();
+            path.push(".");
+            path.push(extension);
+            PathBuf::from(path)
+        })
+        .collect();
+    std::iter::once(path).chain(with_extensions)
+}
+
+/// Gets the first dotted version in a tool's `--version` output, e.g. `3.28.3` from
+/// `cmake version 3.28.3` or `5.36.0` from perl's `(v5.36.0)`.
+fn parse_version(output: &str) -> Option<Version> {
+    output.split_whitespace().find_map(|word| {
+        let word = word.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+        let word = word.strip_prefix('v').unwrap_or(word);
+        if !word.starts_with(|c: char| c.is_ascii_digit()) || !word.contains('.') {
+            return None;
+        }
+        Version::parse(word).ok()
+    })
+}
@@ -0,0 +1,282 @@
+//! Locating the vcpkg instance and inspecting what it installed for a port.
+//!
+//! vcpkg records installed ports and their features in `installed/vcpkg/status`,
+//! with changes not yet folded into it in `installed/vcpkg/updates`, as paragraphs
+//! of `Key: value` lines, the way `dpkg` does.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+
+use crate::{io_error, linkage, path_var, utf8_path, Error, ErrorKind};
+
+/// Where vcpkg and its installed libraries are, from `package.metadata.buildkit.vcpkg-config`.
+///
+/// ```toml
+/// [package.metadata.buildkit.vcpkg-config]
+/// root = "../../third_party/vcpkg"
+/// installed-dir = "../../vcpkg_installed"
+/// overlay-ports = ["../../ports"]
+/// overlay-triplets = ["../../triplets"]
+/// ```
+///
+/// Relative paths are relative to the package. `VCPKG_ROOT`, `VCPKG_INSTALLED_DIR`,
+/// `VCPKG_OVERLAY_PORTS` and `VCPKG_OVERLAY_TRIPLETS` override them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct VcpkgConfig {
+    /// The vcpkg instance.
+    root: Option<Utf8PathBuf>,
+    /// The installed tree, if it isn't the instance's `installed`, e.g. in manifest mode.
+    installed_dir: Option<Utf8PathBuf>,
+    /// Overlay port directories, for the install commands buildkit suggests.
+    #[serde(default)]
+    overlay_ports: Vec<Utf8PathBuf>,
+    /// Overlay triplet directories, for the install commands buildkit suggests.
+    #[serde(default)]
+    overlay_triplets: Vec<Utf8PathBuf>,
+}
+
+impl VcpkgConfig {
+    /// Points the vcpkg-rs configuration at the configured instance and installed tree.
+    pub(crate) fn apply(&self, config: &mut vcpkg::Config) -> Result<(), Error> {
+        let root = path_setting("VCPKG_ROOT", self.root.as_deref())?;
+        let installed_dir = path_setting("VCPKG_INSTALLED_DIR", self.installed_dir.as_deref())?;
+        if let Some(installed_dir) = installed_dir {
+            // vcpkg-rs only looks in `<root>/installed`, so give it a root whose
+            // `installed` is the configured tree.
+            config.vcpkg_root(shim_root(&installed_dir)?.into_std_path_buf());
+        } else if let Some(root) = root {
+            config.vcpkg_root(root.into_std_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Gets the `--overlay-ports` and `--overlay-triplets` arguments for `vcpkg install`.
+    fn overlay_args(&self) -> Result<String, Error> {
+        let mut args = String::new();
+        for (var, flag, dirs) in [
+            (
+                "VCPKG_OVERLAY_PORTS",
+                "--overlay-ports",
+                &self.overlay_ports,
+            ),
+            (
+                "VCPKG_OVERLAY_TRIPLETS",
+                "--overlay-triplets",
+                &self.overlay_triplets,
+            ),
+        ] {
+            println!("cargo:rerun-if-env-changed={var}");
+            let dirs = match std::env::var_os(var) {
+                Some(value) => std::env::split_paths(&value)
+                    .map(|dir| utf8_path(dir, var))
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => dirs
+                    .iter()
+                    .map(|dir| relative_to_package(dir))
+                    .collect::<Result<Vec<_>, _>>()?,
+            };
+            for dir in dirs {
+                write!(args, " {flag}={dir}").unwrap();
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Gets a path from the environment variable `var`, or else the metadata.
+fn path_setting(
+    var: &'static str,
+    metadata: Option<&Utf8Path>,
+) -> Result<Option<Utf8PathBuf>, Error> {
+    println!("cargo:rerun-if-env-changed={var}");
+    match std::env::var(var) {
+        Ok(path) => Ok(Some(path.into())),
+        Err(_) => metadata.map(relative_to_package).transpose(),
+    }
+}
+
+fn relative_to_package(path: &Utf8Path) -> Result<Utf8PathBuf, Error> {
+    Ok(path_var("CARGO_MANIFEST_DIR")?.join(path))
+}
+
+/// Creates a vcpkg root in `OUT_DIR` whose `installed` links to `installed_dir`.
+fn shim_root(installed_dir: &Utf8Path) -> Result<Utf8PathBuf, Error> {
+    let root = path_var("OUT_DIR")?.join("buildkit").join("vcpkg-root");
+    std::fs::create_dir_all(&root).map_err(io_error(&root))?;
+    let marker = root.join(".vcpkg-root");
+    std::fs::write(&marker, "").map_err(io_error(&marker))?;
+    let installed = root.join("installed");
+    if installed.symlink_metadata().is_ok() {
+        remove_link(&installed)?;
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(installed_dir, &installed).map_err(io_error(&installed))?;
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_dir(installed_dir, &installed).map_err(io_error(&installed))?;
+    Ok(root)
+}
+
+fn remove_link(link: &Utf8Path) -> Result<(), Error> {
+    // Directory symlinks are removed like directories on Windows.
+    let removed = if cfg!(windows) {
+        std::fs::remove_dir(link)
+    } else {
+        std::fs::remove_file(link)
+    };
+    removed.map_err(io_error(link))?;
+    Ok(())
+}
+
+/// Checks that `port` was installed for `triplet` with the vcpkg port features
+/// that `features` maps the enabled cargo features to.
+pub(crate) fn check_features(
+    root: &Utf8Path,
+    port: &str,
+    triplet: &str,
+    features: &BTreeMap<String, String>,
+    vcpkg_config: &VcpkgConfig,
+) -> Result<(), Error> {
+    let required: BTreeSet<&str> = features
+        .iter()
+        .filter(|(cargo_feature, _)| linkage::feature_enabled(cargo_feature))
+        .map(|(_, port_feature)| port_feature.as_str())
+        .collect();
+    if required.is_empty() {
+        return Ok(());
+    }
+    let installed = installed_features(root, port, triplet)?;
+    let missing: Vec<String> = required
+        .into_iter()
+        .filter(|feature| !installed.contains(*feature))
+        .map(str::to_owned)
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(ErrorKind::MissingVcpkgFeatures {
+        port: port.to_owned(),
+        triplet: triplet.to_owned(),
+        missing,
+        overlay_args: vcpkg_config.overlay_args()?,
+    }
+    .into())
+}
+
+/// Gets the features `port` is installed with for `triplet`.
+fn installed_features(
+    root: &Utf8Path,
+    port: &str,
+    triplet: &str,
+) -> Result<BTreeSet<String>, Error> {
+    // Later paragraphs for the same feature supersede earlier ones.
+    let mut statuses = BTreeMap::new();
+    for fields in port_paragraphs(root, port, triplet)? {
+        if let Some(feature) = fields.get("Feature") {
+            statuses.insert(feature.clone(), is_installed(&fields));
+        }
+    }
+    Ok(statuses
+        .into_iter()
+        .filter(|(_, installed)| *installed)
+        .map(|(feature, _)| feature)
+        .collect())
+}
+
+/// Gets the version `port` is installed at for `triplet`, if it's recorded.
+///
+/// Date versions like `2024-01-31` are returned with dots, so they compare as versions.
+pub(crate) fn installed_version(
+    root: &Utf8Path,
+    port: &str,
+    triplet: &str,
+) -> Result<Option<String>, Error> {
+    let version = port_paragraphs(root, port, triplet)?
+        .into_iter()
+        .filter(|fields| !fields.contains_key("Feature") && is_installed(fields))
+        .filter_map(|mut fields| fields.remove("Version"))
+        .next_back();
+    Ok(version.map(|version| {
+        let is_date = version.len() == 10
+            && version.char_indices().all(|(i, c)| {
+                if i == 4 || i == 7 {
+                    c == '-'
+                } else {
+                    c.is_ascii_digit()
+                }
+            });
+        if is_date {
+            version.replace('-', ".")
+        } else {
+            version
+        }
+    }))
+}
+
+/// Reads the status database paragraphs about `port` for `triplet`,
+/// in the order vcpkg applies them.
+fn port_paragraphs(
+    root: &Utf8Path,
+    port: &str,
+    triplet: &str,
+) -> Result<Vec<BTreeMap<String, String>>, Error> {
+    let mut paragraphs = Vec::new();
+    for file in status_files(root)? {
+        let contents = std::fs::read_to_string(&file).map_err(io_error(&file))?;
+        for paragraph in contents.split("\n\n") {
+            let fields: BTreeMap<String, String> = paragraph
+                .lines()
+                .filter_map(|line| line.split_once(": "))
+                .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+                .collect();
+            let field = |key: &str| fields.get(key).map(String::as_str);
+            if field("Package") == Some(port) && field("Architecture") == Some(triplet) {
+                paragraphs.push(fields);
+            }
+        }
+    }
+    Ok(paragraphs)
+}
+
+fn is_installed(fields: &BTreeMap<String, String>) -> bool {
+    fields
+        .get("Status")
+        .map_or(false, |status| status.ends_with(" installed"))
+}
+
+/// Emits the paths of the port's `copyright` and `usage` files, where vcpkg installed them,
+/// for dependents as `DEP_<links>_COPYRIGHT` and `DEP_<links>_USAGE`.
+pub(crate) fn emit_port_files(library: &vcpkg::Library, port: &str) {
+    // The include directory is `<installed>/<triplet>/include`, next to `share`.
+    let triplet_dir = match library.include_paths.first().and_then(|dir| dir.parent()) {
+        Some(dir) => dir,
+        None => return,
+    };
+    let share = triplet_dir.join("share").join(port);
+    for file in ["copyright", "usage"] {
+        let path = share.join(file);
+        if path.is_file() {
+            println!("cargo:{file}={}", path.display());
+        }
+    }
+}
+
+/// Lists the status database files in the order vcpkg applies them.
+fn status_files(root: &Utf8Path) -> Result<Vec<Utf8PathBuf>, Error> {
+    let status_dir = root.join("installed").join("vcpkg");
+    let mut files = vec![status_dir.join("status")];
+    let updates = status_dir.join("updates");
+    if updates.is_dir() {
+        let mut update_files = Vec::new();
+        for entry in updates.read_dir_utf8().map_err(io_error(&updates))? {
+            update_files.push(entry.map_err(io_error(&updates))?.into_path());
+        }
+        update_files.sort();
+        files.extend(update_files);
+    }
+    files.retain(|file| file.is_file());
+    Ok(files)
+}
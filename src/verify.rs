@@ -0,0 +1,387 @@
+//! Verification of fetched sources.
+//!
+//! Hashes are accepted as plain hex (SHA-256, SHA-384 or SHA-512 by length)
+//! or in Subresource Integrity form, `sha256-<base64>`, which also covers BLAKE3
+//! as `blake3-<base64>`. The part after the algorithm may be hex as well.
+//!
+//! Besides archives, hashes can pin an unpacked tree, using the NAR serialization
+//! of Nix so that `nix hash path` computes the same hash.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use camino::Utf8Path;
+use serde::Deserialize;
+use sha2::{Digest as _, Sha256, Sha384, Sha512};
+
+use crate::{fetch, io_error, timings, Error, ErrorKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+    /// Much faster than SHA-2 for the large archives some sources are.
+    Blake3,
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha384 => "sha384",
+            Algorithm::Sha512 => "sha512",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    fn output_len(self) -> usize {
+        match self {
+            Algorithm::Sha256 => 32,
+            Algorithm::Sha384 => 48,
+            Algorithm::Sha512 => 64,
+            Algorithm::Blake3 => 32,
+        }
+    }
+
+    /// Hashes whatever `feed` writes.
+    fn hash(self, feed: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>) -> io::Result<Vec<u8>> {
+        match self {
+            Algorithm::Sha256 => digest::<Sha256>(feed),
+            Algorithm::Sha384 => digest::<Sha384>(feed),
+            Algorithm::Sha512 => digest::<Sha512>(feed),
+            Algorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                feed(&mut hasher)?;
+                Ok(hasher.finalize().as_bytes().to_vec())
+            }
+        }
+    }
+}
+
+fn digest<D: sha2::Digest + Write>(
+    feed: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<Vec<u8>> {
+    let mut hasher = D::new();
+    feed(&mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// One expected digest of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Digest {
+    algorithm: Algorithm,
+    bytes: Vec<u8>,
+}
+
+impl FromStr for Digest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Digest, String> {
+        if let Some((name, value)) = s.split_once('-') {
+            let algorithm = match name {
+                "sha256" => Algorithm::Sha256,
+                "sha384" => Algorithm::Sha384,
+                "sha512" => Algorithm::Sha512,
+                "blake3" => Algorithm::Blake3,
+                _ => return Err(format!("unsupported hash algorithm `{name}` in `{s}`")),
+            };
+            // Hex as printed by `sha256sum` or `b3sum` is accepted after the prefix too.
+            let bytes = if value.len() == 2 * algorithm.output_len() {
+                hex::decode(value).map_err(|err| format!("invalid hex in `{s}`: {err}"))?
+            } else {
+                BASE64
+                    .decode(value)
+                    .map_err(|err| format!("invalid base64 in `{s}`: {err}"))?
+            };
+            if bytes.len() != algorithm.output_len() {
+                return Err(format!("`{s}` is too short or long for {name}"));
+            }
+            return Ok(Digest { algorithm, bytes });
+        }
+        let bytes = hex::decode(s).map_err(|err| format!("invalid hex hash `{s}`: {err}"))?;
+        let algorithm = match bytes.len() {
+            32 => Algorithm::Sha256,
+            48 => Algorithm::Sha384,
+            64 => Algorithm::Sha512,
+            len => {
+                return Err(format!(
+                    "`{s}` has {len} bytes, which is no known hash length"
+                ))
+            }
+        };
+        Ok(Digest { algorithm, bytes })
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.algorithm.name(),
+            BASE64.encode(&self.bytes)
+        )
+    }
+}
+
+/// The acceptable digests of a file, any of which may match,
+/// so that a source can move to another algorithm without breaking older hashes.
+///
+/// In metadata, this is either a string of whitespace-separated digests, as in SRI,
+/// or a list of them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "DigestsRepr")]
+pub(crate) struct Digests(Vec<Digest>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DigestsRepr {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl TryFrom<DigestsRepr> for Digests {
+    type Error = String;
+
+    fn try_from(repr: DigestsRepr) -> Result<Digests, String> {
+        let strings = match repr {
+            DigestsRepr::One(string) => vec![string],
+            DigestsRepr::Many(strings) => strings,
+        };
+        let digests = strings
+            .iter()
+            .flat_map(|string| string.split_whitespace())
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if digests.is_empty() {
+            return Err("no hash given".to_owned());
+        }
+        Ok(Digests(digests))
+    }
+}
+
+impl fmt::Display for Digests {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, digest) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{digest}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Digests {
+    /// Gets a short, filesystem-safe key identifying the expected content.
+    pub(crate) fn cache_key(&self) -> String {
+        let bytes = &self.0[0].bytes;
+        hex::encode(&bytes[..bytes.len().min(8)])
+    }
+
+    /// Gets one digest in SRI form for tools that take a single hash, like Nix's
+    /// fixed-output derivations, preferring SHA-2 as Nix doesn't support BLAKE3.
+    pub(crate) fn sri(&self) -> String {
+        let digest = self
+            .0
+            .iter()
+            .find(|digest| digest.algorithm != Algorithm::Blake3)
+            .unwrap_or(&self.0[0]);
+        digest.to_string()
+    }
+
+    /// Checks that a file matches one of the digests.
+    pub(crate) fn verify_file(&self, path: &Utf8Path) -> Result<(), Error> {
+        self.verify(path.as_str(), path, &mut |out| {
+            let mut file = File::open(path)?;
+            io::copy(&mut file, out).map(drop)
+        })
+    }
+
+    /// Checks that the NAR serialization of the tree at `root` matches one of the digests,
+    /// so the same hash verifies a source however it was provided.
+    ///
+    /// A top-level `.git` directory is left out, as it is by Nix's `fetchgit`.
+    pub(crate) fn verify_tree(&self, root: &Utf8Path) -> Result<(), Error> {
+        self.verify(&format!("tree of {root}"), root, &mut |out| {
+            write_tree(root, out)
+        })
+    }
+
+    /// Like [`Digests::verify_tree`], for a local tree that isn't unpacked by the build, e.g.
+    /// in the vendor directory. It's hashed once, and a marker trusts it afterwards until a
+    /// file in it changes size or modification time.
+    pub(crate) fn verify_local_tree(&self, root: &Utf8Path) -> Result<(), Error> {
+        let key = &hex::encode(Sha256::digest(root.as_str()))[..16];
+        let marker = fetch::cache_dir()?
+            .join("verified")
+            .join(key)
+            .with_extension("ok");
+        let mut hasher = Sha256::new();
+        fetch::fingerprint(root, root, &mut hasher)?;
+        let stamp = format!("{self}\n{}", hex::encode(hasher.finalize()));
+        if fs::read_to_string(&marker).ok().as_deref() == Some(stamp.as_str()) {
+            event!(debug, %root, "the tree is verified already");
+            return Ok(());
+        }
+        self.verify_tree(root)?;
+        let dir = marker.parent().expect("markers are in a directory");
+        fs::create_dir_all(dir).map_err(io_error(dir))?;
+        fs::write(&marker, stamp).map_err(io_error(&marker))?;
+        Ok(())
+    }
+
+    /// Checks that what `feed` writes from `path` matches one of the digests.
+    fn verify(
+        &self,
+        what: &str,
+        path: &Utf8Path,
+        feed: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>,
+    ) -> Result<(), Error> {
+        span!("verify", what);
+        let mut actual = Vec::new();
+        for digest in &self.0 {
+            let bytes =
+                timings::time("verify", || digest.algorithm.hash(feed)).map_err(io_error(path))?;
+            if bytes == digest.bytes {
+                return Ok(());
+            }
+            let computed = Digest {
+                algorithm: digest.algorithm,
+                bytes,
+            };
+            if !actual.contains(&computed) {
+                actual.push(computed);
+            }
+        }
+        Err(ErrorKind::HashMismatch {
+            what: what.to_owned(),
+            expected: self.to_string(),
+            actual: Digests(actual).to_string(),
+        }
+        .into())
+    }
+}
+
+/// Gets the SHA-256 of the tree at `root` in SRI form, as `nix hash path` prints it.
+pub(crate) fn tree_digest(root: &Utf8Path) -> Result<String, Error> {
+    let bytes = timings::time("verify", || {
+        Algorithm::Sha256.hash(&mut |out| write_tree(root, out))
+    })
+    .map_err(io_error(root))?;
+    let digest = Digest {
+        algorithm: Algorithm::Sha256,
+        bytes,
+    };
+    Ok(digest.to_string())
+}
+
+/// Writes the NAR serialization of the tree at `root`, which tree hashes cover.
+fn write_tree(root: &Utf8Path, out: &mut dyn Write) -> io::Result<()> {
+    write_str(out, b"nix-archive-1")?;
+    write_nar(root.as_std_path(), out, true)
+}
+
+/// Writes a file system object in Nix's NAR format, which only captures names,
+/// contents, symlink targets and executable bits, so hashes match `nix hash path`.
+fn write_nar(path: &Path, out: &mut dyn Write, skip_git: bool) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    write_str(out, b"(")?;
+    write_str(out, b"type")?;
+    if metadata.file_type().is_symlink() {
+        write_str(out, b"symlink")?;
+        write_str(out, b"target")?;
+        write_str(out, &os_bytes(fs::read_link(path)?.into_os_string()))?;
+    } else if metadata.is_dir() {
+        write_str(out, b"directory")?;
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|name| os_bytes(name.clone()));
+        for name in entries {
+            if skip_git && name == ".git" {
+                continue;
+            }
+            write_str(out, b"entry")?;
+            write_str(out, b"(")?;
+            write_str(out, b"name")?;
+            write_str(out, &os_bytes(name.clone()))?;
+            write_str(out, b"node")?;
+            write_nar(&path.join(name), out, false)?;
+            write_str(out, b")")?;
+        }
+    } else {
+        write_str(out, b"regular")?;
+        if is_executable(&metadata) {
+            write_str(out, b"executable")?;
+            write_str(out, b"")?;
+        }
+        write_str(out, b"contents")?;
+        let len = metadata.len();
+        out.write_all(&len.to_le_bytes())?;
+        let copied = io::copy(&mut File::open(path)?, out)?;
+        if copied != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file changed while hashing",
+            ));
+        }
+        write_padding(out, len)?;
+    }
+    write_str(out, b")")
+}
+
+/// Writes a length-prefixed, zero-padded NAR string.
+fn write_str(out: &mut dyn Write, s: &[u8]) -> io::Result<()> {
+    out.write_all(&(s.len() as u64).to_le_bytes())?;
+    out.write_all(s)?;
+    write_padding(out, s.len() as u64)
+}
+
+fn write_padding(out: &mut dyn Write, len: u64) -> io::Result<()> {
+    let padding = (8 - len % 8) % 8;
+    out.write_all(&[0; 8][..padding as usize])
+}
+
+#[cfg(unix)]
+fn os_bytes(s: OsString) -> Vec<u8> {
+    use std::os::unix::ffi::OsStringExt;
+    s.into_vec()
+}
+
+#[cfg(not(unix))]
+fn os_bytes(s: OsString) -> Vec<u8> {
+    s.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &fs::Metadata) -> bool {
+    false
+}
+
+/// Compares a computed hex digest against the expected one.
+pub(crate) fn check(what: &str, expected: &str, actual: String) -> Result<(), Error> {
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        Ok(())
+    } else {
+        Err(ErrorKind::HashMismatch {
+            what: what.to_owned(),
+            expected: expected.to_owned(),
+            actual,
+        }
+        .into())
+    }
+}
@@ -0,0 +1,184 @@
+//! Library versions reported by probing backends.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Error, ErrorKind};
+
+/// A parsed library version, e.g. the `Version:` field of a `.pc` file.
+///
+/// Native libraries don't follow semver consistently, so parsing is lenient:
+///
+/// * the release part is a dot-separated list of numbers of any length,
+///   and missing components compare as zero (`1.2` equals `1.2.0`);
+/// * anything after a `-` or `~`, or letters glued to a number like `2.0rc1`,
+///   is a pre-release, which sorts before the plain release;
+/// * build metadata after `+` is ignored.
+#[derive(Debug, Clone)]
+pub struct Version {
+    raw: String,
+    release: Vec<u64>,
+    pre: Option<String>,
+}
+
+impl Version {
+    /// Parses a version string.
+    pub fn parse(version: &str) -> Result<Version, Error> {
+        let invalid = || ErrorKind::InvalidVersion(version.to_owned());
+        let raw = version.trim();
+        let without_build = raw.split('+').next().unwrap_or_default();
+        let (release_str, mut pre) = match without_build.find(['-', '~']) {
+            Some(idx) => (
+                &without_build[..idx],
+                Some(without_build[idx + 1..].to_owned()),
+            ),
+            None => (without_build, None),
+        };
+
+        let mut release = Vec::new();
+        let mut components = release_str.split('.').peekable();
+        while let Some(component) = components.next() {
+            let digits = component
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(component.len());
+            if digits == 0 {
+                return Err(invalid().into());
+            }
+            release.push(component[..digits].parse().map_err(|_| invalid())?);
+            if digits < component.len() {
+                // Only the last component may carry a glued suffix like `rc1`.
+                if components.peek().is_some() || pre.is_some() {
+                    return Err(invalid().into());
+                }
+                pre = Some(component[digits..].to_owned());
+            }
+        }
+
+        Ok(Version {
+            raw: raw.to_owned(),
+            release,
+            pre: pre.filter(|p| !p.is_empty()),
+        })
+    }
+
+    /// The version string as it was reported.
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// All numeric release components, e.g. `[1, 2, 3]` for `1.2.3-beta`.
+    pub fn components(&self) -> &[u64] {
+        &self.release
+    }
+
+    /// The first release component.
+    pub fn major(&self) -> u64 {
+        self.component(0)
+    }
+
+    /// The second release component, or zero if absent.
+    pub fn minor(&self) -> u64 {
+        self.component(1)
+    }
+
+    /// The third release component, or zero if absent.
+    pub fn patch(&self) -> u64 {
+        self.component(2)
+    }
+
+    /// The pre-release tag, e.g. `beta.1` for `1.2.3-beta.1`.
+    pub fn pre_release(&self) -> Option<&str> {
+        self.pre.as_deref()
+    }
+
+    /// Whether this version is `min` or newer, failing if `min` is not a valid version.
+    pub fn at_least(&self, min: &str) -> Result<bool, Error> {
+        Ok(*self >= Version::parse(min)?)
+    }
+
+    /// Whether this version is strictly older than `max`, failing if `max` is not a valid
+    /// version.
+    pub fn older_than(&self, max: &str) -> Result<bool, Error> {
+        Ok(*self < Version::parse(max)?)
+    }
+
+    /// Whether this version is within `[min, max)`, failing if either is not a valid version.
+    pub fn in_range(&self, min: &str, max: &str) -> Result<bool, Error> {
+        Ok(self.at_least(min)? && self.older_than(max)?)
+    }
+
+    fn component(&self, idx: usize) -> u64 {
+        self.release.get(idx).copied().unwrap_or(0)
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Version::parse(s)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.release.len().max(other.release.len());
+        (0..len)
+            .map(|i| self.component(i).cmp(&other.component(i)))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => cmp_pre_release(a, b),
+            })
+    }
+}
+
+/// Compares pre-release tags the way semver does:
+/// dot-separated identifiers, numeric ones compared as numbers and sorted before
+/// alphanumeric ones.
+fn cmp_pre_release(a: &str, b: &str) -> Ordering {
+    let mut a_ids = a.split('.');
+    let mut b_ids = b.split('.');
+    loop {
+        match (a_ids.next(), b_ids.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => {
+                let ord = match (x.parse::<u64>(), y.parse::<u64>()) {
+                    (Ok(x), Ok(y)) => x.cmp(&y),
+                    (Ok(_), Err(_)) => Ordering::Less,
+                    (Err(_), Ok(_)) => Ordering::Greater,
+                    (Err(_), Err(_)) => x.cmp(y),
+                };
+                if ord.is_ne() {
+                    return ord;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Sets of versions written as comparators, like `>=2.0.0-beta, <2.0.0`, or wildcards,
+//! like `1.2.x`.
+
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::version::Version;
+use crate::{Error, ErrorKind};
+
+/// The versions satisfying all of a comma-separated list of comparators, `>=`, `>`, `<=`,
+/// `<` or `=` followed by a version, e.g. `>=2.0.0-beta, <2.0.0`. A version alone is just
+/// that version, and one ending in `.x` or `.*`, like `1.2.x`, the releases starting with it,
+/// but not the pre-releases of the next, like `1.3.0-rc1`.
+///
+/// Versions compare as [`Version`]s do, so `2.0.0-beta` is older than `2.0.0`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub(crate) struct VersionRange {
+    raw: String,
+    comparators: Vec<(Op, Version)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Eq,
+    /// Older than the version and its pre-releases, the upper bound of a wildcard.
+    Below,
+}
+
+impl VersionRange {
+    /// Whether `version` is in the range.
+    pub(crate) fn contains(&self, version: &Version) -> bool {
+        self.comparators.iter().all(|(op, bound)| match op {
+            Op::Greater => version > bound,
+            Op::GreaterEq => version >= bound,
+            Op::Less => version < bound,
+            Op::LessEq => version <= bound,
+            Op::Eq => version == bound,
+            Op::Below => version < bound && !same_release(version, bound),
+        })
+    }
+
+    /// The lowest version in the range, if bounded from below.
+    pub(crate) fn min(&self) -> Option<&Version> {
+        self.comparators
+            .iter()
+            .filter(|(op, _)| matches!(op, Op::Greater | Op::GreaterEq | Op::Eq))
+            .map(|(_, bound)| bound)
+            .max()
+    }
+}
+
+/// Whether `a` and `b` have the same release components, as a pre-release and its release do.
+fn same_release(a: &Version, b: &Version) -> bool {
+    let len = a.components().len().max(b.components().len());
+    (0..len).all(|i| a.components().get(i).unwrap_or(&0) == b.components().get(i).unwrap_or(&0))
+}
+
+impl TryFrom<String> for VersionRange {
+    type Error = String;
+
+    fn try_from(raw: String) -> Result<VersionRange, String> {
+        let invalid = || format!("invalid version range `{raw}`");
+        let mut comparators = Vec::new();
+        for comparator in raw.split(',') {
+            let comparator = comparator.trim();
+            let (op, version) = [
+                (">=", Op::GreaterEq),
+                ("<=", Op::LessEq),
+                (">", Op::Greater),
+                ("<", Op::Less),
+                ("=", Op::Eq),
+            ]
+            .iter()
+            .find_map(|(prefix, op)| Some((*op, comparator.strip_prefix(prefix)?.trim())))
+            .unwrap_or((Op::Eq, comparator));
+            let prefix = match version
+                .strip_suffix(".x")
+                .or_else(|| version.strip_suffix(".*"))
+            {
+                Some(prefix) if matches!(op, Op::Eq) => prefix,
+                _ => {
+                    let version = Version::parse(version).map_err(|_| invalid())?;
+                    comparators.push((op, version));
+                    continue;
+                }
+            };
+            // `1.2.x` is `>=1.2, <1.3`, without `1.3`'s pre-releases.
+            let lower = Version::parse(prefix).map_err(|_| invalid())?;
+            if lower.pre_release().is_some() {
+                return Err(invalid());
+            }
+            let mut upper = lower.components().to_vec();
+            if let Some(last) = upper.last_mut() {
+                *last += 1;
+            }
+            let upper = upper
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join(".");
+            comparators.push((Op::GreaterEq, lower));
+            comparators.push((Op::Below, Version::parse(&upper).map_err(|_| invalid())?));
+        }
+        Ok(VersionRange { raw, comparators })
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+/// Fails if `library` was found at a version in one of the `excluded` ranges. A library of
+/// unknown version passes.
+pub(crate) fn check_excluded(
+    library: &str,
+    version: Option<&Version>,
+    excluded: &[VersionRange],
+) -> Result<(), Error> {
+    let version = match version {
+        Some(version) => version,
+        None => return Ok(()),
+    };
+    match excluded.iter().find(|range| range.contains(version)) {
+        Some(range) => Err(ErrorKind::ExcludedVersion {
+            library: library.to_owned(),
+            version: version.to_string(),
+            range: range.to_string(),
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
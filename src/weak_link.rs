@@ -0,0 +1,54 @@
+//! Weak linking on Apple platforms.
+//!
+//! A library using APIs that only newer macOS or iOS versions have still loads on older ones
+//! if the framework or library providing them is linked weakly: its symbols are then null
+//! instead of failing the launch, and the code checks for them, or for the OS version, first.
+
+use serde::Deserialize;
+
+use crate::link::LinkDirectives;
+use crate::TargetInfo;
+
+/// The frameworks and libraries to link weakly on Apple targets.
+///
+/// ```toml
+/// [package.metadata.buildkit.weak-link]
+/// frameworks = ["UserNotifications"]
+/// libs = ["swiftUniformTypeIdentifiers"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct WeakLink {
+    #[serde(default)]
+    frameworks: Vec<String>,
+    #[serde(default)]
+    libs: Vec<String>,
+}
+
+impl WeakLink {
+    /// Replaces the frameworks and libraries in `link` with weak links to them, or adds
+    /// those, on Apple targets. Other targets have no weak linking and are left alone.
+    pub(crate) fn apply(&self, target: &TargetInfo, link: &mut LinkDirectives) {
+        if !target.is_apple() {
+            return;
+        }
+        // ld64 links strongly if any reference is strong.
+        link.libs.retain(|lib| {
+            let (kind, name) = match lib.split_once('=') {
+                Some((kind, name)) => (kind, name),
+                None => ("", lib.as_str()),
+            };
+            if kind.starts_with("framework") {
+                !self.frameworks.iter().any(|framework| framework == name)
+            } else {
+                !self.libs.iter().any(|weak| weak == name)
+            }
+        });
+        for framework in &self.frameworks {
+            link.args.push(format!("-Wl,-weak_framework,{framework}"));
+        }
+        for lib in &self.libs {
+            link.args.push(format!("-Wl,-weak-l{lib}"));
+        }
+    }
+}
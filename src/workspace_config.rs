@@ -0,0 +1,110 @@
+//! Overriding crate metadata from a `buildkit.toml` in the workspace.
+//!
+//! Integrators building many `-sys` crates, e.g. a distribution or a monorepo, need to force
+//! modes, point vendored builds at local checkouts or mirrors, and share downloads between
+//! builds. Environment variables do this but aren't reviewed or versioned with the workspace;
+//! this file is. It's looked for upwards from the target directory, so only the workspace
+//! building the crate can provide one, not the crate itself, or at `BUILDKIT_CONFIG` for
+//! target directories outside the workspace.
+//!
+//! ```toml
+//! mode = "pkg-config"
+//! source-mirror = "https://mirror.example.com/sources"
+//! vendor-dir = "third_party/sources"
+//! cache-dir = "/var/cache/buildkit"
+//!
+//! [crates.libgit2-sys]
+//! mode = "vendored-build"
+//! source-path = "../libgit2"
+//! ```
+
+use std::collections::BTreeMap;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+
+use crate::{env_overrides, io_error, path_var, BuildKitMode, Error, ErrorKind};
+
+/// The settings of `buildkit.toml`, which take precedence over crate metadata
+/// but not over environment variables.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct WorkspaceConfig {
+    /// The directory relative paths are resolved against.
+    #[serde(skip)]
+    dir: Utf8PathBuf,
+    /// The mode of every crate.
+    mode: Option<BuildKitMode>,
+    /// A mirror of the vendored sources, in the format of `BUILDKIT_SOURCE_MIRROR`.
+    source_mirror: Option<String>,
+    /// A directory of pre-fetched sources, in the layout of `BUILDKIT_VENDOR_DIR`.
+    vendor_dir: Option<Utf8PathBuf>,
+    /// Where downloaded archives are kept, shared between builds.
+    cache_dir: Option<Utf8PathBuf>,
+    /// Overrides by package name.
+    #[serde(default)]
+    crates: BTreeMap<String, CrateOverrides>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct CrateOverrides {
+    mode: Option<BuildKitMode>,
+    /// A local source tree to build instead of the vendored source.
+    source_path: Option<Utf8PathBuf>,
+}
+
+impl WorkspaceConfig {
+    /// Reads `BUILDKIT_CONFIG`, or the closest `buildkit.toml` above `OUT_DIR`, if any.
+    pub(crate) fn load() -> Result<Option<WorkspaceConfig>, Error> {
+        let path = match env_overrides::path("BUILDKIT_CONFIG")? {
+            Some(path) => path,
+            None => {
+                let out_dir = path_var("OUT_DIR")?;
+                match out_dir
+                    .ancestors()
+                    .map(|dir| dir.join("buildkit.toml"))
+                    .find(|path| path.is_file())
+                {
+                    Some(path) => path,
+                    None => return Ok(None),
+                }
+            }
+        };
+        println!("cargo:rerun-if-changed={path}");
+        let contents = std::fs::read_to_string(&path).map_err(io_error(&path))?;
+        let mut config: WorkspaceConfig =
+            toml::from_str(&contents).map_err(|err| ErrorKind::InvalidWorkspaceConfig {
+                path: path.clone(),
+                reason: err.message().to_owned(),
+            })?;
+        config.dir = path.parent().map(Utf8Path::to_owned).unwrap_or_default();
+        Ok(Some(config))
+    }
+
+    /// Gets the mode forced for the package `name`.
+    pub(crate) fn mode(&self, name: &str) -> Option<BuildKitMode> {
+        self.crates
+            .get(name)
+            .and_then(|overrides| overrides.mode)
+            .or(self.mode)
+    }
+
+    /// Gets the local source tree the package `name` builds instead of its vendored source.
+    pub(crate) fn source_path(&self, name: &str) -> Option<Utf8PathBuf> {
+        let path = self.crates.get(name)?.source_path.as_ref()?;
+        Some(self.dir.join(path))
+    }
+
+    pub(crate) fn source_mirror(&self) -> Option<&str> {
+        self.source_mirror.as_deref()
+    }
+
+    pub(crate) fn vendor_dir(&self) -> Option<Utf8PathBuf> {
+        self.vendor_dir.as_ref().map(|dir| self.dir.join(dir))
+    }
+
+    pub(crate) fn cache_dir(&self) -> Option<Utf8PathBuf> {
+        self.cache_dir.as_ref().map(|dir| self.dir.join(dir))
+    }
+}